@@ -0,0 +1,111 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// substr(s, m[, n]): the characters at positions m up to m + n - 1, counting from 1,
+// positions outside of `text` are dropped, so substr("hello", 0, 2) is "h"
+pub fn substr(text: &str, start: f64, length: Option<f64>) -> String {
+    let start = start.round();
+    let end = match length {
+        Some(length) => start + length.round(),
+        None => f64::INFINITY,
+    };
+    // a NaN length compares false below, which leaves nothing as well
+    if start.is_nan() || start >= end {
+        return String::new();
+    }
+    text.chars()
+        .enumerate()
+        .filter(|(index, _)| {
+            let position = (index + 1) as f64;
+            position >= start && position < end
+        })
+        .map(|(_, c)| c)
+        .collect()
+}
+
+// index(s, t): character position of the first t in s counting from 1, 0 when there is none
+pub fn index(text: &str, target: &str) -> usize {
+    if target.is_empty() {
+        return 0;
+    }
+    match text.find(target) {
+        Some(found) => text[..found].chars().count() + 1,
+        None => 0,
+    }
+}
+
+// rand() and srand(), the same seed always gives the same numbers
+#[derive(Debug)]
+pub struct Random {
+    seed: f64,
+    state: u64,
+}
+
+impl Default for Random {
+    fn default() -> Random {
+        let mut random = Random { seed: 0.0, state: 0 };
+        random.srand(Some(0.0));
+        random
+    }
+}
+
+impl Random {
+    // uniform in [0, 1)
+    pub fn rand(&mut self) -> f64 {
+        // splitmix64
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // seeds with `seed` or the time of day in seconds, returns the previous seed
+    pub fn srand(&mut self, seed: Option<f64>) -> f64 {
+        let seed = seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs() as f64)
+                .unwrap_or(0.0)
+        });
+        let previous = self.seed;
+        self.seed = seed;
+        self.state = seed.to_bits();
+        previous
+    }
+}
+
+#[test]
+fn test_substr() {
+    assert_eq!(substr("hello", 2.0, Some(3.0)), "ell");
+    assert_eq!(substr("hello", 2.0, None), "ello");
+    assert_eq!(substr("hello", 0.0, Some(2.0)), "h");
+    assert_eq!(substr("hello", -1.0, None), "hello");
+    assert_eq!(substr("hello", 4.0, Some(10.0)), "lo");
+    assert_eq!(substr("hello", 6.0, None), "");
+    assert_eq!(substr("hello", 2.0, Some(-1.0)), "");
+    assert_eq!(substr("hello", 1.5, Some(1.4)), "e");
+    assert_eq!(substr("héllo", 2.0, Some(2.0)), "él");
+    assert_eq!(substr("hello", f64::NAN, None), "");
+}
+
+#[test]
+fn test_index() {
+    assert_eq!(index("hello", "ll"), 3);
+    assert_eq!(index("héllo", "l"), 3);
+    assert_eq!(index("hello", "x"), 0);
+    assert_eq!(index("hello", ""), 0);
+}
+
+#[test]
+fn test_random() {
+    let mut random = Random::default();
+    let first: Vec<f64> = (0..3).map(|_| random.rand()).collect();
+    assert!(first.iter().all(|number| (0.0..1.0).contains(number)));
+    assert_eq!(random.srand(Some(42.0)), 0.0);
+    let seeded: Vec<f64> = (0..3).map(|_| random.rand()).collect();
+    assert_ne!(first, seeded);
+    assert_eq!(random.srand(Some(0.0)), 42.0);
+    let again: Vec<f64> = (0..3).map(|_| random.rand()).collect();
+    assert_eq!(first, again);
+}
@@ -0,0 +1,770 @@
+// Compiles the syntax tree into flat lists of instructions for the stack machine
+// in vm.rs. Variables are resolved to slots here: globals to an index into
+// `Bytecode::globals`, function parameters to an index into the call's locals,
+// so nothing is looked up by name while the program runs.
+//
+// Whatever the tree-walker reports as a runtime error (break outside a loop,
+// calls to undefined functions, wrong builtin arities) compiles to an Error
+// instruction, so both backends fail at the same point.
+//
+// The gawk extensions only parse with --gawk, whose programs run on the
+// tree-walker, so they never get here.
+use std::collections::HashMap;
+
+use crate::interpreter::{check_arity, RuntimeError};
+use crate::streams::{InputKind, OutputKind};
+use crate::syntax_tree::{
+    Action, BinaryOperator, BuiltinFuncName, Expr, InputFunction, Item, LValue,
+    OutputRedirection, Pattern, PrintStatement, Program, SimpleGet, SimpleStatement, Statement,
+    UnaryOperator,
+};
+use crate::value::Value;
+
+// what an instruction loads from or stores to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Place {
+    Global(usize),
+    Local(usize),
+    Nf,
+    // the field index is taken from the stack
+    Field,
+    // the array key is taken from the stack
+    GlobalElement(usize),
+    LocalElement(usize),
+}
+
+impl Place {
+    pub fn keyed(&self) -> bool {
+        matches!(self, Place::Field | Place::GlobalElement(_) | Place::LocalElement(_))
+    }
+}
+
+// the third argument of split
+#[derive(Debug, Clone, PartialEq)]
+pub enum Separator {
+    Fs,
+    Ere(String),
+    // any other expression, its value is on the stack
+    Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Push(Value),
+    Pop,
+    Dup,
+    // keyed places pop their key first
+    Load(Place),
+    // pops the value then the key, pushes the value back
+    Store(Place),
+    // ++ and --, pushes the new value when prefix and the old one otherwise
+    Increment(Place, f64, bool),
+    // moves the value on top to the arguments of the next call
+    PushArgument,
+    // a bare variable as an argument, arrays are passed by reference
+    PassVariable(Place),
+    // joins the values on top with SUBSEP
+    Subscript(usize),
+    Unary(UnaryOperator),
+    Binary(BinaryOperator),
+    // pops the ere then the text, true for !~
+    Match(bool),
+    // /ere/ against $0
+    MatchRecord(String),
+    // pops the key
+    In(Place),
+    // pops the key
+    Delete(Place),
+    // delete of a whole array
+    Clear(Place),
+    Jump(usize),
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+    // function index, number of arguments
+    Call(usize, usize),
+    // all but sub, gsub and split, with their arguments on the stack
+    Builtin(BuiltinFuncName, usize),
+    // length(name), the number of elements when name is an array
+    Length(Place),
+    // pops the separator value if any, then the text
+    Split(Place, Separator),
+    // sub and gsub, true for gsub, pops the key of the target, the replacement and the ere
+    Substitute(Place, bool),
+    // pops the key of the target, then the file or command name, pushes the status
+    Getline(Option<InputKind>, Option<Place>),
+    // pops the target of the redirection, then the values
+    Print(usize, Option<OutputKind>),
+    Printf(usize, Option<OutputKind>),
+    // a pattern without an action
+    PrintRecord,
+    ForIn(Place),
+    // assigns the next key to the place, or leaves the loop for the target
+    ForInNext(Place, usize),
+    // break out of a for (key in array)
+    ForInEnd,
+    // pushes whether the range pattern is between its start and end
+    RangeActive(usize),
+    // pops whether the end pattern matched
+    RangeEnd(usize),
+    Next,
+    // pops the exit code when true
+    Exit(bool),
+    Return,
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    pub code: Vec<Op>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Bytecode {
+    // names of the global slots
+    pub globals: Vec<String>,
+    pub functions: Vec<Function>,
+    // the BEGIN actions one after the other, the same for END
+    pub begin: Vec<Op>,
+    // the pattern-actions run for each record
+    pub records: Vec<Op>,
+    pub end: Vec<Op>,
+    // number of range patterns
+    pub ranges: usize,
+    // a program with only BEGIN actions does not read any input
+    pub reads_input: bool,
+    // the /ere/ operands pushed as strings, so that they are cached as literals
+    pub eres: Vec<String>,
+}
+
+// jumps to patch once the end of the loop is known
+#[derive(Default)]
+struct Loop {
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+    for_in: bool,
+}
+
+#[derive(Default)]
+struct Compiler {
+    globals: HashMap<String, usize>,
+    bytecode: Bytecode,
+    // index and number of parameters by name
+    functions: HashMap<String, (usize, usize)>,
+    // parameters of the function being compiled, None outside of functions
+    locals: Option<Vec<String>>,
+    code: Vec<Op>,
+    loops: Vec<Loop>,
+}
+
+pub fn compile(program: &Program) -> Bytecode {
+    let mut compiler = Compiler::default();
+    // functions can be called before they are declared
+    for item in &program.items {
+        if let Item::FunctionDeclaration(function) = item {
+            let index = compiler.functions.len();
+            compiler
+                .functions
+                .insert(function.name.clone(), (index, function.params.len()));
+        }
+    }
+    for item in &program.items {
+        if let Item::FunctionDeclaration(function) = item {
+            compiler.locals = Some(function.params.clone());
+            compiler.block(&function.body);
+            compiler.emit(Op::Push(Value::Uninitialized));
+            compiler.emit(Op::Return);
+            compiler.locals = None;
+            let code = std::mem::take(&mut compiler.code);
+            compiler.bytecode.functions.push(Function {
+                name: function.name.clone(),
+                params: function.params.clone(),
+                code,
+            });
+        }
+    }
+
+    for item in &program.items {
+        if let Item::PatternAction(Pattern::Begin, action) = item {
+            compiler.block(action);
+        }
+    }
+    compiler.bytecode.begin = std::mem::take(&mut compiler.code);
+
+    for item in &program.items {
+        match item {
+            Item::Action(action) => compiler.block(action),
+            Item::PatternAction(Pattern::Begin | Pattern::End, _) | Item::FunctionDeclaration(_) => {}
+            Item::PatternAction(pattern, action) => {
+                let skip = compiler.pattern(pattern);
+                compiler.block(action);
+                compiler.patch(skip);
+            }
+            Item::Pattern(pattern) => {
+                let skip = compiler.pattern(pattern);
+                compiler.emit(Op::PrintRecord);
+                compiler.patch(skip);
+            }
+        }
+    }
+    compiler.bytecode.records = std::mem::take(&mut compiler.code);
+    compiler.bytecode.reads_input = program.items.iter().any(|item| {
+        !matches!(
+            item,
+            Item::PatternAction(Pattern::Begin, _) | Item::FunctionDeclaration(_)
+        )
+    });
+
+    for item in &program.items {
+        if let Item::PatternAction(Pattern::End, action) = item {
+            compiler.block(action);
+        }
+    }
+    compiler.bytecode.end = std::mem::take(&mut compiler.code);
+    compiler.bytecode
+}
+
+impl Compiler {
+    fn emit(&mut self, op: Op) -> usize {
+        self.code.push(op);
+        self.code.len() - 1
+    }
+
+    // points the jump at `at` to the next instruction
+    fn patch(&mut self, at: usize) {
+        let target = self.code.len();
+        match &mut self.code[at] {
+            Op::Jump(to) | Op::JumpIfFalse(to) | Op::JumpIfTrue(to) | Op::ForInNext(_, to) => {
+                *to = target
+            }
+            op => unreachable!("{:?} is not a jump", op),
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.emit(Op::Error(message));
+    }
+
+    // a parameter of the function being compiled, or a global
+    fn variable(&mut self, name: &str) -> Place {
+        let local = self
+            .locals
+            .as_ref()
+            .and_then(|locals| locals.iter().position(|local| local == name));
+        if let Some(index) = local {
+            return Place::Local(index);
+        }
+        let next = self.globals.len();
+        let index = *self.globals.entry(name.to_string()).or_insert(next);
+        if index == next {
+            self.bytecode.globals.push(name.to_string());
+        }
+        Place::Global(index)
+    }
+
+    // compiles the key of subscripted and field lvalues
+    fn place(&mut self, lvalue: &LValue) -> Place {
+        match lvalue {
+            LValue::Name(name) if name == "NF" => Place::Nf,
+            LValue::Name(name) => self.variable(name),
+            LValue::NameOpenSquareBraceExprListCloseSquareBrace(name, subscripts) => {
+                self.subscript(subscripts);
+                match self.variable(name) {
+                    Place::Local(index) => Place::LocalElement(index),
+                    Place::Global(index) => Place::GlobalElement(index),
+                    place => unreachable!("{:?} is not an array", place),
+                }
+            }
+            LValue::DollarExpr(index) => {
+                self.expr(index);
+                Place::Field
+            }
+        }
+    }
+
+    fn subscript(&mut self, subscripts: &[Expr]) {
+        for subscript in subscripts {
+            self.expr(subscript);
+        }
+        self.emit(Op::Subscript(subscripts.len()));
+    }
+
+    // the jump to patch past the action when the pattern does not match
+    fn pattern(&mut self, pattern: &Pattern) -> usize {
+        match pattern {
+            Pattern::Expr(expr) => {
+                self.expr(expr);
+                self.emit(Op::JumpIfFalse(0))
+            }
+            Pattern::ExprCommaNewlineOptExpr(start, end) => {
+                let index = self.bytecode.ranges;
+                self.bytecode.ranges += 1;
+                self.emit(Op::RangeActive(index));
+                let active = self.emit(Op::JumpIfTrue(0));
+                self.expr(start);
+                let skip = self.emit(Op::JumpIfFalse(0));
+                self.patch(active);
+                // the end pattern is checked against the starting record too
+                self.expr(end);
+                self.emit(Op::RangeEnd(index));
+                skip
+            }
+            Pattern::Begin | Pattern::End => unreachable!("BEGIN and END are compiled apart"),
+            Pattern::BeginFile | Pattern::EndFile => unreachable!("--gawk runs on the tree-walker"),
+        }
+    }
+
+    fn block(&mut self, action: &Action) {
+        for statement in &action.statements {
+            self.statement(statement);
+        }
+    }
+
+    // the breaks and continues of the body are left for the caller to patch
+    fn loop_body(&mut self, body: &Statement, for_in: bool) -> Loop {
+        self.loops.push(Loop {
+            for_in,
+            ..Loop::default()
+        });
+        self.statement(body);
+        self.loops.pop().unwrap()
+    }
+
+    fn patch_all(&mut self, jumps: Vec<usize>) {
+        for jump in jumps {
+            self.patch(jump);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Action(action) => self.block(action),
+            Statement::Switch(..) => unreachable!("--gawk runs on the tree-walker"),
+            Statement::If(condition, then_branch, else_branch) => {
+                self.expr(condition);
+                let skip = self.emit(Op::JumpIfFalse(0));
+                self.statement(then_branch);
+                match else_branch {
+                    Some(else_branch) => {
+                        let end = self.emit(Op::Jump(0));
+                        self.patch(skip);
+                        self.statement(else_branch);
+                        self.patch(end);
+                    }
+                    None => self.patch(skip),
+                }
+            }
+            Statement::While(condition, body) => {
+                let start = self.code.len();
+                self.expr(condition);
+                let exit = self.emit(Op::JumpIfFalse(0));
+                let body = self.loop_body(body, false);
+                self.patch_all(body.continues);
+                self.emit(Op::Jump(start));
+                self.patch(exit);
+                self.patch_all(body.breaks);
+            }
+            Statement::Do(body, condition) => {
+                let start = self.code.len();
+                let body = self.loop_body(body, false);
+                self.patch_all(body.continues);
+                self.expr(condition);
+                self.emit(Op::JumpIfTrue(start));
+                self.patch_all(body.breaks);
+            }
+            Statement::For(init, condition, update, body) => {
+                if let Some(init) = init {
+                    self.simple(init);
+                }
+                let start = self.code.len();
+                let exit = condition.as_ref().map(|condition| {
+                    self.expr(condition);
+                    self.emit(Op::JumpIfFalse(0))
+                });
+                let body = self.loop_body(body, false);
+                self.patch_all(body.continues);
+                if let Some(update) = update {
+                    self.simple(update);
+                }
+                self.emit(Op::Jump(start));
+                if let Some(exit) = exit {
+                    self.patch(exit);
+                }
+                self.patch_all(body.breaks);
+            }
+            Statement::ForIn(key, array, body) => {
+                let array = self.variable(array);
+                self.emit(Op::ForIn(array));
+                let key = self.place(&LValue::Name(key.clone()));
+                let next = self.emit(Op::ForInNext(key, 0));
+                let body = self.loop_body(body, true);
+                self.patch_all(body.continues);
+                self.emit(Op::Jump(next));
+                self.patch(next);
+                self.patch_all(body.breaks);
+            }
+            Statement::Empty => {}
+            Statement::Break | Statement::Continue => {
+                let for_in = match self.loops.last() {
+                    Some(innermost) => innermost.for_in,
+                    None => return self.error("break or continue outside a loop".to_string()),
+                };
+                if *statement == Statement::Break && for_in {
+                    self.emit(Op::ForInEnd);
+                }
+                let jump = self.emit(Op::Jump(0));
+                let innermost = self.loops.last_mut().unwrap();
+                match statement {
+                    Statement::Break => innermost.breaks.push(jump),
+                    _ => innermost.continues.push(jump),
+                }
+            }
+            Statement::Next => {
+                self.emit(Op::Next);
+            }
+            Statement::Exit(code) => {
+                if let Some(code) = code {
+                    self.expr(code);
+                }
+                self.emit(Op::Exit(code.is_some()));
+            }
+            Statement::Return(value) => {
+                if self.locals.is_none() {
+                    return self.error("return outside a function".to_string());
+                }
+                match value {
+                    Some(value) => self.expr(value),
+                    None => {
+                        self.emit(Op::Push(Value::Uninitialized));
+                    }
+                }
+                self.emit(Op::Return);
+            }
+            Statement::SimpleStatement(simple) => self.simple(simple),
+        }
+    }
+
+    fn simple(&mut self, statement: &SimpleStatement) {
+        match statement {
+            SimpleStatement::Expr(expr) => {
+                self.expr(expr);
+                self.emit(Op::Pop);
+            }
+            SimpleStatement::Delete(name, subscripts) if subscripts.is_empty() => {
+                let array = self.variable(name);
+                self.emit(Op::Clear(array));
+            }
+            SimpleStatement::Delete(name, subscripts) => {
+                self.subscript(subscripts);
+                let array = self.variable(name);
+                self.emit(Op::Delete(array));
+            }
+            SimpleStatement::PrintStatement(PrintStatement::Print(exprs, redirection)) => {
+                let target = self.print_arguments(exprs, redirection);
+                self.emit(Op::Print(exprs.len(), target));
+            }
+            SimpleStatement::PrintStatement(PrintStatement::Printf(exprs, redirection)) => {
+                let target = self.print_arguments(exprs, redirection);
+                self.emit(Op::Printf(exprs.len(), target));
+            }
+        }
+    }
+
+    // the values to print, then the file or command they go to
+    fn print_arguments(
+        &mut self,
+        exprs: &[Expr],
+        redirection: &Option<OutputRedirection>,
+    ) -> Option<OutputKind> {
+        for expr in exprs {
+            self.expr(expr);
+        }
+        let (kind, target) = match redirection {
+            None => return None,
+            Some(OutputRedirection::GreaterThan(target)) => (OutputKind::File, target),
+            Some(OutputRedirection::Append(target)) => (OutputKind::Append, target),
+            Some(OutputRedirection::Pipe(target)) => (OutputKind::Pipe, target),
+        };
+        self.expr(target);
+        Some(kind)
+    }
+
+    // /ere/ as an operand of ~ or a regex argument is its text, not a match against $0
+    fn regex_operand(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Ere(ere) => {
+                self.bytecode.eres.push(ere.clone());
+                self.emit(Op::Push(Value::String(ere.clone())));
+            }
+            expr => self.expr(expr),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Number(number) => {
+                self.emit(Op::Push(Value::Number(*number)));
+            }
+            Expr::String(text) => {
+                self.emit(Op::Push(Value::String(text.clone())));
+            }
+            Expr::Ere(ere) => {
+                self.emit(Op::MatchRecord(ere.clone()));
+            }
+            Expr::LValue(lvalue) => {
+                let place = self.place(lvalue);
+                self.emit(Op::Load(place));
+            }
+            Expr::Grouping(expr) => self.expr(expr),
+            Expr::Unary(operator, operand) => {
+                self.expr(operand);
+                self.emit(Op::Unary(operator.clone()));
+            }
+            Expr::Binary(left, operator @ (BinaryOperator::And | BinaryOperator::Or), right) => {
+                // && leaves as soon as one side is false, || as soon as one is true
+                let and = *operator == BinaryOperator::And;
+                let short = |to| if and { Op::JumpIfFalse(to) } else { Op::JumpIfTrue(to) };
+                self.expr(left);
+                let first = self.emit(short(0));
+                self.expr(right);
+                let second = self.emit(short(0));
+                self.emit(Op::Push(Value::Number(and as i32 as f64)));
+                let end = self.emit(Op::Jump(0));
+                self.patch(first);
+                self.patch(second);
+                self.emit(Op::Push(Value::Number(!and as i32 as f64)));
+                self.patch(end);
+            }
+            Expr::Binary(left, operator @ (BinaryOperator::Match | BinaryOperator::NoMatch), right) => {
+                self.expr(left);
+                self.regex_operand(right);
+                self.emit(Op::Match(*operator == BinaryOperator::NoMatch));
+            }
+            Expr::Binary(left, operator, right) => {
+                self.expr(left);
+                self.expr(right);
+                self.emit(Op::Binary(operator.clone()));
+            }
+            Expr::Conditional(condition, then_branch, else_branch) => {
+                self.expr(condition);
+                let otherwise = self.emit(Op::JumpIfFalse(0));
+                self.expr(then_branch);
+                let end = self.emit(Op::Jump(0));
+                self.patch(otherwise);
+                self.expr(else_branch);
+                self.patch(end);
+            }
+            Expr::In(subscript, array) => {
+                self.subscript(std::slice::from_ref(subscript.as_ref()));
+                let array = self.variable(array);
+                self.emit(Op::In(array));
+            }
+            Expr::BracketMultipleExprListBracket(subscripts, array) => {
+                self.subscript(subscripts);
+                let array = self.variable(array);
+                self.emit(Op::In(array));
+            }
+            Expr::Assign(lvalue, value) => {
+                let place = self.place(lvalue);
+                self.expr(value);
+                self.emit(Op::Store(place));
+            }
+            Expr::CompoundAssign(lvalue, operator, value) => {
+                let place = self.place(lvalue);
+                if place.keyed() {
+                    self.emit(Op::Dup);
+                }
+                self.emit(Op::Load(place));
+                self.expr(value);
+                self.emit(Op::Binary(operator.clone()));
+                self.emit(Op::Store(place));
+            }
+            Expr::LValueIncr(lvalue) => self.increment(lvalue, 1.0, false),
+            Expr::LValueDecr(lvalue) => self.increment(lvalue, -1.0, false),
+            Expr::IncrLValue(lvalue) => self.increment(lvalue, 1.0, true),
+            Expr::DecrLValue(lvalue) => self.increment(lvalue, -1.0, true),
+            Expr::FuncCall(name, args) => self.call(name, args),
+            Expr::BuiltinFuncCall(builtin, args) => self.builtin(builtin, args),
+            Expr::InputFunction(function) => self.getline(function),
+        }
+    }
+
+    fn increment(&mut self, lvalue: &LValue, step: f64, prefix: bool) {
+        let place = self.place(lvalue);
+        self.emit(Op::Increment(place, step, prefix));
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr]) {
+        let (index, params) = match self.functions.get(name) {
+            Some(&function) => function,
+            None => return self.error(format!("function {} never defined", name)),
+        };
+        if args.len() > params {
+            return self.error(format!(
+                "function {} called with {} args, accepts only {}",
+                name,
+                args.len(),
+                params
+            ));
+        }
+        for arg in args {
+            match arg {
+                Expr::LValue(LValue::Name(name)) if name != "NF" => {
+                    let place = self.variable(name);
+                    self.emit(Op::PassVariable(place));
+                }
+                arg => {
+                    self.expr(arg);
+                    self.emit(Op::PushArgument);
+                }
+            }
+        }
+        self.emit(Op::Call(index, args.len()));
+    }
+
+    fn builtin(&mut self, builtin: &BuiltinFuncName, args: &[Expr]) {
+        if let Err(RuntimeError(message)) = check_arity(builtin, args.len()) {
+            return self.error(message);
+        }
+        match (builtin, args) {
+            (BuiltinFuncName::Length, [Expr::LValue(LValue::Name(name))]) if name != "NF" => {
+                let place = self.variable(name);
+                self.emit(Op::Length(place));
+            }
+            (BuiltinFuncName::Sub | BuiltinFuncName::Gsub, _) => {
+                self.regex_operand(&args[0]);
+                self.expr(&args[1]);
+                let target = match args.get(2) {
+                    Some(Expr::LValue(lvalue)) => self.place(lvalue),
+                    Some(arg) => {
+                        return self.error(format!("sub and gsub need a variable to change, got {:?}", arg))
+                    }
+                    None => {
+                        self.emit(Op::Push(Value::Number(0.0)));
+                        Place::Field
+                    }
+                };
+                self.emit(Op::Substitute(target, *builtin == BuiltinFuncName::Gsub));
+            }
+            (BuiltinFuncName::Split, _) => {
+                self.expr(&args[0]);
+                let array = match &args[1] {
+                    Expr::LValue(LValue::Name(name)) => self.variable(name),
+                    arg => return self.error(format!("split needs an array, got {:?}", arg)),
+                };
+                let separator = match args.get(2) {
+                    None => Separator::Fs,
+                    Some(Expr::Ere(ere)) => Separator::Ere(ere.clone()),
+                    Some(fs) => {
+                        self.expr(fs);
+                        Separator::Value
+                    }
+                };
+                self.emit(Op::Split(array, separator));
+            }
+            _ => {
+                for (index, arg) in args.iter().enumerate() {
+                    // the second argument of match is a regex
+                    if *builtin == BuiltinFuncName::Match && index == 1 {
+                        self.regex_operand(arg);
+                    } else {
+                        self.expr(arg);
+                    }
+                }
+                self.emit(Op::Builtin(builtin.clone(), args.len()));
+            }
+        }
+    }
+
+    fn getline(&mut self, function: &InputFunction) {
+        let (source, get) = match function {
+            InputFunction::SimpleGet(get) => (None, get),
+            InputFunction::SimpleGetLessThanExpr(get, file) => {
+                self.expr(file);
+                (Some(InputKind::File), get)
+            }
+            InputFunction::ExprBarSimpleGet(command, get) => {
+                self.expr(command);
+                (Some(InputKind::Command), get)
+            }
+        };
+        let target = match get {
+            SimpleGet::Getline => None,
+            SimpleGet::GetlineLValue(lvalue) => Some(self.place(lvalue)),
+        };
+        self.emit(Op::Getline(source, target));
+    }
+}
+
+#[cfg(test)]
+fn compile_source(source: &str) -> Bytecode {
+    let tokens = crate::lexer::tokenize(source.to_string()).expect("program should lex");
+    compile(&crate::parser::parse(tokens).expect("program should parse"))
+}
+
+#[test]
+fn test_compile_slots() {
+    let bytecode = compile_source("function f(a) { return a + x } { x += $1; print f(NF) }");
+    assert_eq!(bytecode.globals, ["x"]);
+    assert_eq!(
+        bytecode.functions[0].code,
+        [
+            Op::Load(Place::Local(0)),
+            Op::Load(Place::Global(0)),
+            Op::Binary(BinaryOperator::Add),
+            Op::Return,
+            Op::Push(Value::Uninitialized),
+            Op::Return,
+        ]
+    );
+    assert_eq!(
+        bytecode.records,
+        [
+            Op::Load(Place::Global(0)),
+            Op::Push(Value::Number(1.0)),
+            Op::Load(Place::Field),
+            Op::Binary(BinaryOperator::Add),
+            Op::Store(Place::Global(0)),
+            Op::Pop,
+            Op::Load(Place::Nf),
+            Op::PushArgument,
+            Op::Call(0, 1),
+            Op::Print(1, None),
+        ]
+    );
+    assert!(bytecode.reads_input);
+    assert!(!compile_source("BEGIN { print 1 }").reads_input);
+}
+
+#[test]
+fn test_compile_jumps() {
+    let bytecode = compile_source("BEGIN { while (i < 3) { if (i == 1) break; i++ } }");
+    assert_eq!(
+        bytecode.begin,
+        [
+            Op::Load(Place::Global(0)),
+            Op::Push(Value::Number(3.0)),
+            Op::Binary(BinaryOperator::LessThan),
+            Op::JumpIfFalse(12),
+            Op::Load(Place::Global(0)),
+            Op::Push(Value::Number(1.0)),
+            Op::Binary(BinaryOperator::Eq),
+            Op::JumpIfFalse(9),
+            Op::Jump(12),
+            Op::Increment(Place::Global(0), 1.0, false),
+            Op::Pop,
+            Op::Jump(0),
+        ]
+    );
+    let bytecode = compile_source("BEGIN { break; return; foo() }");
+    assert_eq!(
+        bytecode.begin,
+        [
+            Op::Error("break or continue outside a loop".to_string()),
+            Op::Error("return outside a function".to_string()),
+            Op::Error("function foo never defined".to_string()),
+            Op::Pop,
+        ]
+    );
+}
@@ -0,0 +1,490 @@
+// --debug, in the spirit of gawk's debugger: the program runs on the tree-walker,
+// which asks before every statement whether to stop. Statements are found by the
+// span the parser gave them, so breakpoints are on the lines they start on.
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+
+use rustyline::DefaultEditor;
+
+use crate::diagnostic::Span;
+use crate::interpreter::{sorted_keys, EvalResult, Interpreter, Unwind, Variable};
+use crate::pretty::{expr_text, string_text};
+use crate::syntax_tree::{Action, Expr, Item, LValue, Program, SpannedStatement, Statement};
+use crate::value::Value;
+use crate::{lexer, parser};
+
+const HELP: &str = "step (s)            run to the next statement, into function calls
+next (n)            run to the next statement, over function calls
+continue (c)        run to the next breakpoint or change of a watched expression
+break (b) [line | function]
+                    stop at the statements starting on a line (default: this one)
+                    or at the first statement of a function
+delete (d) [n]      remove breakpoint n, or all of them
+watch (w) expr      stop when the value of expr changes
+unwatch n           remove watch n
+print (p) expr      print the value of expr, of all elements when it names an array
+info (i)            list the breakpoints and watches
+list (l)            print the program around this line
+quit (q)            leave like exit, without running END
+help (h)            print this help
+an empty line repeats the last command, end of input runs the program to its end";
+
+// reads a command, shown the prompt, None at the end of input
+pub type Commands = Box<dyn FnMut(&str) -> Option<String>>;
+
+enum Breakpoint {
+    Line(usize),
+    // stops at the span of the first statement of the function
+    Function(String, Span),
+}
+
+struct Watch {
+    text: String,
+    expr: Expr,
+    value: String,
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Step,
+    // stops once the calls made from this depth returned
+    Next(usize),
+    Continue,
+}
+
+pub struct Debugger {
+    // the program text, shown where the program stops
+    lines: Vec<String>,
+    // the lines statements start on, breakpoints elsewhere would never be hit
+    statement_lines: BTreeSet<usize>,
+    functions: HashMap<String, Span>,
+    // deleted breakpoints and watches leave a hole so the others keep their numbers
+    breakpoints: Vec<Option<Breakpoint>>,
+    watches: Vec<Option<Watch>>,
+    mode: Mode,
+    commands: Commands,
+    out: Box<dyn Write>,
+    last_command: String,
+    quit: bool,
+}
+
+// the spans of `action` and its nested statements, blocks left out as they are never stopped at
+fn statement_spans(action: &Action, spans: &mut Vec<Span>) {
+    for statement in &action.statements {
+        statement_span(statement, spans);
+    }
+}
+
+fn statement_span(statement: &SpannedStatement, spans: &mut Vec<Span>) {
+    if let Statement::Action(action) = &statement.statement {
+        statement_spans(action, spans);
+        return;
+    }
+    spans.push(statement.span);
+    match &statement.statement {
+        Statement::If(_, then_branch, else_branch) => {
+            statement_span(then_branch, spans);
+            if let Some(else_branch) = else_branch {
+                statement_span(else_branch, spans);
+            }
+        }
+        Statement::While(_, body)
+        | Statement::Do(body, _)
+        | Statement::For(_, _, _, body)
+        | Statement::ForIn(_, _, body) => statement_span(body, spans),
+        Statement::Switch(_, cases) => {
+            for statement in cases.iter().flat_map(|case| &case.statements) {
+                statement_span(statement, spans);
+            }
+        }
+        _ => {}
+    }
+}
+
+// commands typed on the terminal, with line editing and history
+pub fn terminal() -> Result<Commands, String> {
+    let mut editor = DefaultEditor::new().map_err(|e| e.to_string())?;
+    Ok(Box::new(move |prompt| {
+        let line = editor.readline(prompt).ok()?;
+        let _ = editor.add_history_entry(line.as_str());
+        Some(line)
+    }))
+}
+
+impl Debugger {
+    pub fn new(program: &Program, source: &str, commands: Commands, out: Box<dyn Write>) -> Debugger {
+        let mut spans = vec![];
+        let mut functions = HashMap::new();
+        for item in &program.items {
+            match item {
+                Item::Action(action) | Item::PatternAction(_, action) => statement_spans(action, &mut spans),
+                Item::FunctionDeclaration(function) => {
+                    let mut body = vec![];
+                    statement_spans(&function.body, &mut body);
+                    if let Some(first) = body.first() {
+                        functions.insert(function.name.clone(), *first);
+                    }
+                    spans.extend(body);
+                }
+                Item::Pattern(_) => {}
+            }
+        }
+        Debugger {
+            lines: source.lines().map(str::to_string).collect(),
+            statement_lines: spans.iter().map(|span| span.line).collect(),
+            functions,
+            breakpoints: vec![],
+            watches: vec![],
+            mode: Mode::Step,
+            commands,
+            out,
+            last_command: String::new(),
+            quit: false,
+        }
+    }
+
+    // called before the statement at `span` runs, takes commands when it stops there
+    pub fn before(&mut self, interpreter: &mut Interpreter, span: Span) -> EvalResult<()> {
+        if self.quit {
+            return Err(Unwind::Exit);
+        }
+        let mut reasons = vec![];
+        for (index, breakpoint) in self.breakpoints.iter().enumerate() {
+            let hit = match breakpoint {
+                Some(Breakpoint::Line(line)) => *line == span.line,
+                Some(Breakpoint::Function(_, start)) => *start == span,
+                None => false,
+            };
+            if hit {
+                reasons.push(format!("breakpoint {}", index + 1));
+            }
+        }
+        for index in 0..self.watches.len() {
+            let Some(expr) = self.watches[index].as_ref().map(|watch| watch.expr.clone()) else {
+                continue;
+            };
+            let value = show(interpreter, &expr);
+            let watch = self.watches[index].as_mut().unwrap();
+            if value != watch.value {
+                reasons.push(format!("watch {}: {}: {} -> {}", index + 1, watch.text, watch.value, value));
+                watch.value = value;
+            }
+        }
+        let stop = match self.mode {
+            Mode::Step => true,
+            Mode::Next(depth) => interpreter.depth() <= depth,
+            Mode::Continue => false,
+        };
+        if !stop && reasons.is_empty() {
+            return Ok(());
+        }
+
+        // what the program printed so far goes first
+        interpreter.flush()?;
+        for reason in reasons {
+            self.say(reason);
+        }
+        self.say(format!("line {}: {}", span.line, self.line(span.line).trim()));
+        loop {
+            let Some(line) = (self.commands)("(awk) ") else {
+                // nothing more to ask, the program runs to its end
+                self.breakpoints.clear();
+                self.watches.clear();
+                self.mode = Mode::Continue;
+                return Ok(());
+            };
+            let line = match line.trim() {
+                "" => self.last_command.clone(),
+                line => line.to_string(),
+            };
+            self.last_command = line.clone();
+            if self.command(interpreter, span, &line) {
+                break;
+            }
+        }
+        if self.quit {
+            return Err(Unwind::Exit);
+        }
+        Ok(())
+    }
+
+    fn say(&mut self, text: String) {
+        // the debugger has nowhere else to report a failing terminal
+        let _ = writeln!(self.out, "{}", text);
+        let _ = self.out.flush();
+    }
+
+    fn line(&self, line: usize) -> &str {
+        self.lines.get(line.wrapping_sub(1)).map_or("", String::as_str)
+    }
+
+    // runs one command, true when the program should go on
+    fn command(&mut self, interpreter: &mut Interpreter, span: Span, line: &str) -> bool {
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (line, ""),
+        };
+        match (name, argument) {
+            ("s" | "step", "") => self.mode = Mode::Step,
+            ("n" | "next", "") => self.mode = Mode::Next(interpreter.depth()),
+            ("c" | "continue", "") => self.mode = Mode::Continue,
+            ("q" | "quit", "") => self.quit = true,
+            ("b" | "break", target) => {
+                let here = span.line.to_string();
+                self.add_breakpoint(if target.is_empty() { &here } else { target });
+                return false;
+            }
+            ("d" | "delete", "") => {
+                self.breakpoints.clear();
+                self.say("deleted all breakpoints".to_string());
+                return false;
+            }
+            ("d" | "delete", number) => {
+                match numbered(&mut self.breakpoints, number) {
+                    Some(_) => self.say(format!("deleted breakpoint {}", number)),
+                    None => self.say(format!("no breakpoint {}", number)),
+                }
+                return false;
+            }
+            ("w" | "watch", text) if !text.is_empty() => {
+                if let Some(expr) = self.parse(text, interpreter.gawk()) {
+                    let value = show(interpreter, &expr);
+                    self.say(format!("watch {}: {} = {}", self.watches.len() + 1, text, value));
+                    let text = text.to_string();
+                    self.watches.push(Some(Watch { text, expr, value }));
+                }
+                return false;
+            }
+            ("unwatch", number) => {
+                match numbered(&mut self.watches, number) {
+                    Some(_) => self.say(format!("deleted watch {}", number)),
+                    None => self.say(format!("no watch {}", number)),
+                }
+                return false;
+            }
+            ("p" | "print", text) if !text.is_empty() => {
+                if let Some(expr) = self.parse(text, interpreter.gawk()) {
+                    self.print(interpreter, &expr);
+                }
+                return false;
+            }
+            ("i" | "info", "") => {
+                self.info();
+                return false;
+            }
+            ("l" | "list", "") => {
+                let first = span.line.saturating_sub(5).max(1);
+                for number in first..=(span.line + 5).min(self.lines.len()) {
+                    let marker = if number == span.line { "=>" } else { "  " };
+                    self.say(format!("{}{:>4}  {}", marker, number, self.line(number)));
+                }
+                return false;
+            }
+            ("h" | "help", "") => {
+                self.say(HELP.to_string());
+                return false;
+            }
+            _ => {
+                self.say(format!("unknown command {}, see help", line));
+                return false;
+            }
+        }
+        true
+    }
+
+    fn add_breakpoint(&mut self, target: &str) {
+        let breakpoint = match target.parse::<usize>() {
+            Ok(line) if self.statement_lines.contains(&line) => Breakpoint::Line(line),
+            Ok(line) => return self.say(format!("no statement starts on line {}", line)),
+            Err(_) => match self.functions.get(target) {
+                Some(start) => Breakpoint::Function(target.to_string(), *start),
+                None => return self.say(format!("no function {} with statements", target)),
+            },
+        };
+        let line = match &breakpoint {
+            Breakpoint::Line(line) => *line,
+            Breakpoint::Function(_, start) => start.line,
+        };
+        self.breakpoints.push(Some(breakpoint));
+        self.say(format!("breakpoint {} at line {}", self.breakpoints.len(), line));
+    }
+
+    fn parse(&mut self, text: &str, gawk: bool) -> Option<Expr> {
+        let tokens = lexer::tokenize_with(text.to_string(), gawk);
+        // only a comment, there is no token for an error to point at
+        if tokens.as_ref().is_ok_and(Vec::is_empty) {
+            self.say(format!("{} is not an expression", text));
+            return None;
+        }
+        match tokens.and_then(parser::parse_expr) {
+            Ok(expr) => Some(expr),
+            Err(diagnostic) => {
+                self.say(diagnostic.render(text));
+                None
+            }
+        }
+    }
+
+    fn print(&mut self, interpreter: &mut Interpreter, expr: &Expr) {
+        if let Expr::LValue(LValue::Name(name)) = expr {
+            if let Some(Variable::Array(array)) = interpreter.lookup(name) {
+                let array = array.borrow();
+                if array.is_empty() {
+                    return self.say(format!("{} = empty array", name));
+                }
+                for key in sorted_keys(&array) {
+                    self.say(format!("{}[{}] = {}", name, string_text(&key), show_value(interpreter, &array[&key])));
+                }
+                return;
+            }
+        }
+        let value = show(interpreter, expr);
+        self.say(format!("{} = {}", expr_text(expr), value));
+    }
+
+    fn info(&mut self) {
+        let mut lines = vec![];
+        for (index, breakpoint) in self.breakpoints.iter().enumerate() {
+            match breakpoint {
+                Some(Breakpoint::Line(line)) => lines.push(format!("breakpoint {} at line {}", index + 1, line)),
+                Some(Breakpoint::Function(name, start)) => {
+                    lines.push(format!("breakpoint {} at line {} in {}", index + 1, start.line, name))
+                }
+                None => {}
+            }
+        }
+        for (index, watch) in self.watches.iter().enumerate() {
+            if let Some(watch) = watch {
+                lines.push(format!("watch {}: {} = {}", index + 1, watch.text, watch.value));
+            }
+        }
+        if lines.is_empty() {
+            lines.push("no breakpoints or watches".to_string());
+        }
+        for line in lines {
+            self.say(line);
+        }
+    }
+}
+
+// takes out breakpoint or watch `number`, counted from 1
+fn numbered<T>(items: &mut [Option<T>], number: &str) -> Option<T> {
+    let index = number.parse::<usize>().ok()?.checked_sub(1)?;
+    items.get_mut(index)?.take()
+}
+
+// the value of `expr` as the debugger prints it, or why it has none
+fn show(interpreter: &mut Interpreter, expr: &Expr) -> String {
+    match interpreter.eval(expr) {
+        Ok(value) => show_value(interpreter, &value),
+        Err(Unwind::Error(error)) => format!("<{}>", error),
+        Err(_) => "<next or exit>".to_string(),
+    }
+}
+
+fn show_value(interpreter: &Interpreter, value: &Value) -> String {
+    match value {
+        Value::Uninitialized => "uninitialized".to_string(),
+        Value::Number(_) | Value::StrNum(_) => interpreter.to_string(value),
+        Value::String(text) => string_text(text),
+    }
+}
+
+#[cfg(test)]
+fn debug_session(source: &str, input: &str, commands: &[&str]) -> String {
+    use std::collections::VecDeque;
+
+    let tokens = lexer::tokenize(source.to_string()).expect("program should lex");
+    let program = parser::parse(tokens).expect("program should parse");
+    let output = crate::interpreter::SharedOutput::default();
+    let mut interpreter = Interpreter::new(&program, Box::new(output.clone()));
+    interpreter.set_stdin(Box::new(std::io::Cursor::new(input.to_string())));
+    let mut commands: VecDeque<String> = commands.iter().map(|command| command.to_string()).collect();
+    let commands: Commands = Box::new(move |_| commands.pop_front());
+    interpreter.set_debugger(Debugger::new(&program, source, commands, Box::new(output.clone())));
+    interpreter.run(&program).unwrap();
+    let printed = output.0.borrow().clone();
+    String::from_utf8(printed).unwrap()
+}
+
+#[test]
+fn test_debug_functions_and_watches() {
+    let source = "function add(a, b) {
+    return a + b
+}
+{
+    total = add(total, $1)
+    count[$2]++
+}
+END {
+    print \"total\", total
+}
+";
+    let printed = debug_session(
+        source,
+        "1 x\n2 y\n",
+        &["break add", "c", "p b", "n", "watch total", "c", "d 1", "", "p count", "p $0", "info", "c"],
+    );
+    assert_eq!(
+        printed,
+        "line 5: total = add(total, $1)
+breakpoint 1 at line 2
+breakpoint 1
+line 2: return a + b
+b = 1
+line 6: count[$2]++
+watch 1: total = 1
+breakpoint 1
+line 2: return a + b
+deleted breakpoint 1
+no breakpoint 1
+count[\"x\"] = 1
+$0 = \"2 y\"
+watch 1: total = 1
+watch 1: total: 1 -> 3
+line 6: count[$2]++
+total 3
+"
+    );
+}
+
+#[test]
+fn test_debug_lines_and_quit() {
+    let source = "BEGIN {
+    x = 1; y = x + 1
+    if (y)
+        print \"y is\", y
+    print \"unreachable\"
+}
+END { print \"no END after quit\" }";
+    let printed = debug_session(
+        source,
+        "",
+        &["b 3", "b 1", "b nope", "c", "s", "p y == 2", "p arr", "bogus", "p #", "w # x", "s", "l", "q"],
+    );
+    assert_eq!(
+        printed,
+        "line 2: x = 1; y = x + 1
+breakpoint 1 at line 3
+no statement starts on line 1
+no function nope with statements
+breakpoint 1
+line 3: if (y)
+line 4: print \"y is\", y
+y == 2 = 1
+arr = uninitialized
+unknown command bogus, see help
+# is not an expression
+# x is not an expression
+y is 2
+line 5: print \"unreachable\"
+     1  BEGIN {
+     2      x = 1; y = x + 1
+     3      if (y)
+     4          print \"y is\", y
+=>   5      print \"unreachable\"
+     6  }
+     7  END { print \"no END after quit\" }
+"
+    );
+}
@@ -0,0 +1,119 @@
+use serde::Serialize;
+use std::fmt;
+
+// where a token starts in the program text, both counting from 1
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize) -> Span {
+        Span { line, column }
+    }
+}
+
+// a syntax error found by the lexer or the parser
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic {
+            message: message.into(),
+            span,
+        }
+    }
+
+    // the error followed by the offending line of `source` and a caret under the column
+    pub fn render(&self, source: &str) -> String {
+        format!("{}{}", self, excerpt(source, self.span))
+    }
+
+    // the same for the program files joined by newlines, as file:line:column: with
+    // the line counted from the start of its file
+    pub fn render_files(&self, files: &[(String, String)]) -> String {
+        let (index, line) = locate(self.span.line, files);
+        let (name, source) = &files[index];
+        let span = Span::new(line, self.span.column);
+        let excerpt = excerpt(source, span);
+        format!("{}:{}:{}: syntax error: {}{}", name, line, span.column, self.message, excerpt)
+    }
+}
+
+// `line` of the names and sources of the program files joined in this order, each
+// followed by a newline, as the index of its file and the line in that file
+pub fn locate(line: usize, files: &[(String, String)]) -> (usize, usize) {
+    let mut located = (0, line);
+    let mut start = 1;
+    for (index, (_, source)) in files.iter().enumerate() {
+        if line < start {
+            break;
+        }
+        located = (index, line - start + 1);
+        start += source.matches('\n').count() + 1;
+    }
+    located
+}
+
+// the line of `source` at `span` and a caret under its column
+fn excerpt(source: &str, span: Span) -> String {
+    let line = source.lines().nth(span.line.saturating_sub(1)).unwrap_or("");
+    // tabs are kept so the caret lines up however wide they are shown
+    let indent: String = line
+        .chars()
+        .take(span.column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    format!("\n    {}\n    {}^", line, indent)
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "syntax error at line {}, column {}: {}",
+            self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+#[test]
+fn test_render() {
+    let diagnostic = Diagnostic::new("unterminated string", Span::new(2, 8));
+    let source = "BEGIN {\n\tprint \"abc\n}";
+    assert_eq!(
+        diagnostic.render(source),
+        "syntax error at line 2, column 8: unterminated string\n    \tprint \"abc\n    \t      ^"
+    );
+    // an error at the end of no tokens at all
+    let diagnostic = Diagnostic::new("unexpected end of program", Span::default());
+    assert_eq!(
+        diagnostic.render("# x"),
+        "syntax error at line 0, column 0: unexpected end of program\n    # x\n    ^"
+    );
+}
+
+#[test]
+fn test_render_files() {
+    let files = [
+        ("e1.awk".to_string(), "function f() {\n  return 1\n}\n".to_string()),
+        ("e2.awk".to_string(), "BEGIN {\n  print f()\n}}\n".to_string()),
+    ];
+    // the joined text has an empty line after each file's last newline
+    let diagnostic = Diagnostic::new("unexpected '}'", Span::new(7, 2));
+    assert_eq!(
+        diagnostic.render_files(&files),
+        "e2.awk:3:2: syntax error: unexpected '}'\n    }}\n     ^"
+    );
+    assert_eq!(locate(2, &files), (0, 2));
+    assert_eq!(locate(4, &files), (0, 4));
+    assert_eq!(locate(5, &files), (1, 1));
+    let cmdline = [("cmdline".to_string(), "{ print $1 ]".to_string())];
+    let diagnostic = Diagnostic::new("unexpected ']'", Span::new(1, 12));
+    assert!(diagnostic.render_files(&cmdline).starts_with("cmdline:1:12: syntax error: unexpected ']'\n"));
+}
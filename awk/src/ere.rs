@@ -0,0 +1,236 @@
+// POSIX extended regular expressions on top of the regex crate, which has a
+// slightly different syntax: brackets can nest, `{` always starts a repetition
+// and `.` does not match a newline by default. It also matches leftmost-first,
+// /ab|abcd/ finds ab in abcd, where POSIX wants the leftmost-longest match, so
+// the leftmost match is extended as far as it goes from where it starts
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use regex_automata::meta::Regex;
+use regex_automata::{Anchored, Input, MatchKind};
+
+// `{n}`, `{n,}` or `{n,m}` starting at `chars[start]`, the index after the '}'
+fn interval_end(chars: &[char], start: usize) -> Option<usize> {
+    let mut index = start + 1;
+    let mut digits = 0;
+    let mut comma = false;
+    while let Some(&c) = chars.get(index) {
+        match c {
+            '0'..='9' => digits += 1,
+            ',' if !comma && digits > 0 => comma = true,
+            '}' if digits > 0 => return Some(index + 1),
+            _ => return None,
+        }
+        index += 1;
+    }
+    None
+}
+
+// the bracket expression starting at `chars[start]`, translated, and the index after it
+fn translate_bracket(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let mut translated = String::from("[");
+    let mut index = start + 1;
+    if chars.get(index) == Some(&'^') {
+        translated.push('^');
+        index += 1;
+    }
+    // a ']' right at the start is a literal
+    if chars.get(index) == Some(&']') {
+        translated.push_str("\\]");
+        index += 1;
+    }
+    while let Some(&c) = chars.get(index) {
+        match c {
+            ']' => {
+                translated.push(']');
+                return Some((translated, index + 1));
+            }
+            // [:alpha:] and the other classes are understood by both
+            '[' if chars.get(index + 1) == Some(&':') => {
+                let end = (index + 2..chars.len().saturating_sub(1))
+                    .find(|&end| chars[end] == ':' && chars[end + 1] == ']')?;
+                translated.extend(&chars[index..end + 2]);
+                index = end + 2;
+                continue;
+            }
+            '\\' => {
+                let escaped = *chars.get(index + 1)?;
+                translated.push_str(&escape_char(escaped));
+                index += 2;
+                continue;
+            }
+            // set operations and nested classes in the regex crate
+            '[' | '&' | '~' => {
+                translated.push('\\');
+                translated.push(c);
+            }
+            c => translated.push(c),
+        }
+        index += 1;
+    }
+    None
+}
+
+// a backslash escape as the regex crate wants it
+fn escape_char(c: char) -> String {
+    match c {
+        'n' => "\\n".to_string(),
+        't' => "\\t".to_string(),
+        'r' => "\\r".to_string(),
+        'f' => "\\x0C".to_string(),
+        'v' => "\\x0B".to_string(),
+        'a' => "\\x07".to_string(),
+        'b' => "\\x08".to_string(),
+        c => regex::escape(&c.to_string()),
+    }
+}
+
+pub fn translate(ere: &str) -> Result<String, String> {
+    let chars: Vec<char> = ere.chars().collect();
+    let mut translated = String::from("(?s)");
+    let mut index = 0;
+    while let Some(&c) = chars.get(index) {
+        match c {
+            '\\' => {
+                match chars.get(index + 1) {
+                    Some(&escaped) => translated.push_str(&escape_char(escaped)),
+                    None => return Err(format!("trailing backslash in regular expression /{}/", ere)),
+                }
+                index += 2;
+                continue;
+            }
+            '[' => match translate_bracket(&chars, index) {
+                Some((bracket, end)) => {
+                    translated.push_str(&bracket);
+                    index = end;
+                    continue;
+                }
+                None => return Err(format!("unterminated [ in regular expression /{}/", ere)),
+            },
+            '{' => match interval_end(&chars, index) {
+                // a repetition needs something to repeat
+                Some(end) if index > 0 => {
+                    translated.extend(&chars[index..end]);
+                    index = end;
+                    continue;
+                }
+                _ => translated.push_str("\\{"),
+            },
+            '}' => translated.push_str("\\}"),
+            c => translated.push(c),
+        }
+        index += 1;
+    }
+    Ok(translated)
+}
+
+#[derive(Debug, Clone)]
+pub struct Ere {
+    translated: String,
+    // leftmost-first, where the leftmost match starts
+    first: Regex,
+    // reports the longest match when anchored where a match starts
+    longest: Regex,
+    // the groups of gensub, a match of `first` which ends at the end as well
+    whole: OnceLock<Regex>,
+}
+
+pub fn compile(ere: &str) -> Result<Ere, String> {
+    let translated = translate(ere)?;
+    let invalid = |e: regex_automata::meta::BuildError| format!("invalid regular expression /{}/: {}", ere, e);
+    let first = Regex::new(&translated).map_err(invalid)?;
+    let longest = Regex::builder()
+        .configure(Regex::config().match_kind(MatchKind::All))
+        .build(&translated)
+        .map_err(invalid)?;
+    Ok(Ere {
+        translated,
+        first,
+        longest,
+        whole: OnceLock::new(),
+    })
+}
+
+impl Ere {
+    pub fn is_match(&self, text: &str) -> bool {
+        self.first.is_match(text)
+    }
+
+    // the leftmost-longest match at or after `start`
+    pub fn find_at(&self, text: &[u8], start: usize) -> Option<Range<usize>> {
+        let found = self.first.search(&Input::new(text).range(start..))?;
+        let input = Input::new(text).range(found.start()..).anchored(Anchored::Yes);
+        let end = self.longest.search(&input).map_or(found.end(), |longest| longest.end());
+        Some(found.start()..end.max(found.end()))
+    }
+
+    pub fn find(&self, text: &str) -> Option<Range<usize>> {
+        self.find_at(text.as_bytes(), 0)
+    }
+
+    // the matches which do not overlap from left to right, an empty match right
+    // after the previous match is skipped as in the regex crate: b* in abc gives
+    // 0..0, 1..2 and 3..3
+    pub fn find_iter<'a>(&'a self, text: &'a [u8]) -> impl Iterator<Item = Range<usize>> + 'a {
+        let mut start = 0;
+        let mut last_end = None;
+        std::iter::from_fn(move || loop {
+            if start > text.len() {
+                return None;
+            }
+            let found = self.find_at(text, start)?;
+            // empty matches between the bytes of a character are not reported
+            start = if found.is_empty() { found.end + 1 } else { found.end };
+            if found.is_empty() && last_end == Some(found.end) {
+                continue;
+            }
+            last_end = Some(found.end);
+            return Some(found);
+        })
+    }
+
+    // the match `found` of find_at and its groups, "" for those which did not take part
+    pub fn groups<'t>(&self, text: &'t str, found: Range<usize>) -> Vec<&'t str> {
+        let whole = self.whole.get_or_init(|| {
+            Regex::new(&format!("(?:{})\\z", self.translated)).expect("the ere compiled before")
+        });
+        // the text after the match is cut off so that \z ends it there
+        let input = Input::new(&text[..found.end]).range(found.start..).anchored(Anchored::Yes);
+        let mut captures = whole.create_captures();
+        whole.search_captures(&input, &mut captures);
+        let groups = (1..captures.group_len()).map(|index| captures.get_group(index));
+        let groups = groups.map(|group| group.map_or("", |span| &text[span.range()]));
+        std::iter::once(&text[found]).chain(groups).collect()
+    }
+}
+
+#[test]
+fn test_translate() {
+    assert_eq!(translate("a.b").unwrap(), "(?s)a.b");
+    assert_eq!(translate("[]a[]").unwrap(), "(?s)[\\]a\\[]");
+    assert_eq!(translate("[[:digit:]x]+").unwrap(), "(?s)[[:digit:]x]+");
+    assert_eq!(translate("a{2,3}b{").unwrap(), "(?s)a{2,3}b\\{");
+    assert_eq!(translate("\\/\\.").unwrap(), "(?s)/\\.");
+    assert!(translate("[abc").is_err());
+}
+
+#[test]
+fn test_compile() {
+    let regex = compile("^[[:alpha:]_][[:alnum:]_]*$").unwrap();
+    assert!(regex.is_match("snake_case1"));
+    assert!(!regex.is_match("1abc"));
+    assert!(compile("a.b").unwrap().is_match("a\nb"));
+    assert!(compile("(a|b").is_err());
+}
+
+#[test]
+fn test_leftmost_longest() {
+    let ere = compile("ab|abcd").unwrap();
+    assert_eq!(ere.find("xabcdx"), Some(1..5));
+    assert_eq!(ere.find_iter(b"abcd ab abc").collect::<Vec<_>>(), vec![0..4, 5..7, 8..10]);
+    assert_eq!(compile("a|aa").unwrap().find_iter(b"aaa").collect::<Vec<_>>(), vec![0..2, 2..3]);
+    assert_eq!(compile("b*").unwrap().find_iter(b"abc").collect::<Vec<_>>(), vec![0..0, 1..2, 3..3]);
+    assert_eq!(compile("x*").unwrap().find_iter("éx".as_bytes()).collect::<Vec<_>>(), vec![0..0, 2..3]);
+    let ere = compile("(a)(b)|(a)(bcd)").unwrap();
+    assert_eq!(ere.groups("xabcd", 1..5), vec!["abcd", "", "", "a", "bcd"]);
+}
@@ -0,0 +1,346 @@
+use crate::value::Value;
+
+// widths and precisions above this are taken as this, rather than padding with
+// gigabytes of spaces or zeros
+const MAX_COUNT: usize = 1 << 24;
+
+// the most digits after the point Rust formats a float with (one more for {:e}),
+// the digits of an f64 end long before so any further ones are zeros
+const MAX_DIGITS: usize = u16::MAX as usize - 1;
+
+// C style %e: mantissa with `precision` digits and at least two exponent digits,
+// `alternate` (the # flag) keeps the decimal point when there are no digits after it
+fn format_exponential(number: f64, precision: usize, upper: bool, alternate: bool) -> String {
+    let formatted = format!("{:.*e}", precision.min(MAX_DIGITS), number);
+    let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+    let mantissa = format!("{}{}", mantissa, "0".repeat(precision.saturating_sub(MAX_DIGITS)));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let sign = if exponent < 0 { '-' } else { '+' };
+    let e = if upper { 'E' } else { 'e' };
+    let point = if alternate && precision == 0 { "." } else { "" };
+    format!("{}{}{}{}{:02}", mantissa, point, e, sign, exponent.abs())
+}
+
+fn format_fixed(number: f64, precision: usize, alternate: bool) -> String {
+    let formatted = format!("{:.*}", precision.min(MAX_DIGITS), number);
+    let formatted = formatted + &"0".repeat(precision.saturating_sub(MAX_DIGITS));
+    if alternate && precision == 0 {
+        return formatted + ".";
+    }
+    formatted
+}
+
+fn strip_trailing_zeros(formatted: String) -> String {
+    if !formatted.contains('.') {
+        return formatted;
+    }
+    let (number, exponent) = match formatted.find(['e', 'E']) {
+        Some(index) => formatted.split_at(index),
+        None => (formatted.as_str(), ""),
+    };
+    let number = number.trim_end_matches('0').trim_end_matches('.');
+    format!("{}{}", number, exponent)
+}
+
+// C style %g: %e or %f depending on the exponent, without trailing zeros
+// unless `alternate` (the # flag) is set
+fn format_general(number: f64, precision: usize, upper: bool, alternate: bool) -> String {
+    let precision = precision.max(1);
+    let exponential = format!("{:.*e}", (precision - 1).min(MAX_DIGITS), number);
+    let exponent: i32 = exponential
+        .split_once('e')
+        .and_then(|(_, exponent)| exponent.parse().ok())
+        .unwrap_or(0);
+    let formatted = if exponent < -4 || exponent >= precision as i32 {
+        format_exponential(number, precision - 1, upper, alternate)
+    } else {
+        let decimals = (precision as i32 - 1 - exponent).max(0) as usize;
+        format_fixed(number, decimals, alternate)
+    };
+    if alternate {
+        return formatted;
+    }
+    strip_trailing_zeros(formatted)
+}
+
+fn format_special(number: f64) -> Option<String> {
+    if number.is_nan() {
+        return Some(if number.is_sign_negative() { "-nan" } else { "nan" }.to_string());
+    }
+    if number.is_infinite() {
+        return Some(if number < 0.0 { "-inf" } else { "inf" }.to_string());
+    }
+    None
+}
+
+// number to string conversion through CONVFMT / OFMT
+pub fn format_number(number: f64, fmt: &str) -> String {
+    if let Some(special) = format_special(number) {
+        return special;
+    }
+    if number == number.trunc() && number.abs() < 1e16 {
+        return format!("{}", number as i64);
+    }
+    // a %s in `fmt` formats the number again, with %.6g rather than `fmt` itself
+    sprintf(fmt, &[Value::Number(number)], "%.6g")
+}
+
+// one %[flags][width][.precision]conversion of a format string
+#[derive(Debug, Default, PartialEq)]
+struct Spec {
+    left: bool,      // '-'
+    plus: bool,      // '+'
+    space: bool,     // ' '
+    alternate: bool, // '#'
+    zero: bool,      // '0'
+    width: usize,
+    precision: Option<usize>,
+    conversion: char,
+}
+
+impl Spec {
+    fn sign(&self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        }
+    }
+
+    // pads `prefix` (sign, 0x) followed by `body` to the width, zeros go between the two
+    fn pad(&self, prefix: &str, body: &str, zero_allowed: bool) -> String {
+        let length = prefix.chars().count() + body.chars().count();
+        let fill = self.width.saturating_sub(length);
+        if self.left {
+            format!("{}{}{}", prefix, body, " ".repeat(fill))
+        } else if self.zero && zero_allowed {
+            format!("{}{}{}", prefix, "0".repeat(fill), body)
+        } else {
+            format!("{}{}{}", " ".repeat(fill), prefix, body)
+        }
+    }
+
+    fn format_integer(&self, number: f64) -> String {
+        if let Some(special) = format_special(number) {
+            return self.pad("", &special, false);
+        }
+        let number = number.trunc();
+        let negative = number < 0.0;
+        let digits = match self.conversion {
+            // printed through %.0f so that values beyond 64 bits keep their digits
+            'd' | 'i' => format!("{:.0}", number.abs()),
+            // negative numbers wrap around like a C cast to unsigned
+            conversion => {
+                let unsigned = if negative { number as i64 as u64 } else { number as u64 };
+                match conversion {
+                    'o' => format!("{:o}", unsigned),
+                    'x' => format!("{:x}", unsigned),
+                    'X' => format!("{:X}", unsigned),
+                    _ => format!("{}", unsigned),
+                }
+            }
+        };
+        let signed = matches!(self.conversion, 'd' | 'i');
+        let mut digits = match self.precision {
+            // an explicit zero precision prints nothing for zero
+            Some(0) if digits == "0" => String::new(),
+            Some(precision) if digits.len() < precision => "0".repeat(precision - digits.len()) + &digits,
+            _ => digits,
+        };
+        let prefix = match self.conversion {
+            'd' | 'i' => self.sign(negative && signed),
+            'o' if self.alternate && !digits.starts_with('0') => {
+                digits.insert(0, '0');
+                ""
+            }
+            'x' if self.alternate && number != 0.0 => "0x",
+            'X' if self.alternate && number != 0.0 => "0X",
+            _ => "",
+        };
+        self.pad(prefix, &digits, self.precision.is_none())
+    }
+
+    fn format_float(&self, number: f64) -> String {
+        if let Some(special) = format_special(number.abs()) {
+            let special = if self.conversion.is_ascii_uppercase() { special.to_uppercase() } else { special };
+            return self.pad(self.sign(number.is_sign_negative()), &special, false);
+        }
+        let precision = self.precision.unwrap_or(6);
+        let upper = self.conversion.is_ascii_uppercase();
+        let body = match self.conversion {
+            'e' | 'E' => format_exponential(number.abs(), precision, upper, self.alternate),
+            'f' | 'F' => format_fixed(number.abs(), precision, self.alternate),
+            _ => format_general(number.abs(), precision, upper, self.alternate),
+        };
+        self.pad(self.sign(number.is_sign_negative()), &body, true)
+    }
+
+    fn format_string(&self, text: &str) -> String {
+        let text: String = match self.precision {
+            Some(precision) => text.chars().take(precision).collect(),
+            None => text.to_string(),
+        };
+        self.pad("", &text, false)
+    }
+}
+
+// a width or precision, either digits or '*' taking the next argument
+fn parse_count(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    args: &mut std::slice::Iter<Value>,
+) -> Option<f64> {
+    if chars.peek() == Some(&'*') {
+        chars.next();
+        return Some(args.next().map(Value::to_number).unwrap_or(0.0));
+    }
+    let mut digits = String::new();
+    while let Some(digit) = chars.peek().filter(|d| d.is_ascii_digit()) {
+        digits.push(*digit);
+        chars.next();
+    }
+    digits.parse().ok()
+}
+
+// C style formatting of awk values for printf and sprintf, arguments are converted to the
+// number or string each conversion needs, missing ones are taken as uninitialized
+pub fn sprintf(format: &str, args: &[Value], convfmt: &str) -> String {
+    let mut output = String::new();
+    let mut args = args.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        let mut spec = Spec::default();
+        let mut text = String::from('%');
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => spec.left = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '#' => spec.alternate = true,
+                '0' => spec.zero = true,
+                _ => break,
+            }
+            text.push(flag);
+            chars.next();
+        }
+        if let Some(width) = parse_count(&mut chars, &mut args) {
+            // a negative * width means left justified
+            spec.left |= width < 0.0;
+            spec.width = (width.abs() as usize).min(MAX_COUNT);
+        }
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            // a negative * precision is taken as if it was left out
+            spec.precision = match parse_count(&mut chars, &mut args) {
+                Some(precision) if precision < 0.0 => None,
+                precision => Some((precision.unwrap_or(0.0) as usize).min(MAX_COUNT)),
+            };
+        }
+        spec.conversion = match chars.next() {
+            Some(conversion) => conversion,
+            None => {
+                output.push_str(&text);
+                break;
+            }
+        };
+        if spec.conversion == '%' {
+            output.push('%');
+            continue;
+        }
+        let formatted = match spec.conversion {
+            'd' | 'i' | 'o' | 'x' | 'X' | 'u' => {
+                spec.format_integer(args.next().map(Value::to_number).unwrap_or(0.0))
+            }
+            'e' | 'E' | 'f' | 'F' | 'g' | 'G' => {
+                spec.format_float(args.next().map(Value::to_number).unwrap_or(0.0))
+            }
+            // a number is a character code, a string gives its first character
+            'c' => {
+                let character = match args.next() {
+                    Some(arg @ (Value::Number(_) | Value::StrNum(_))) => char::from_u32(arg.to_number() as u32)
+                        .map(String::from)
+                        .unwrap_or_default(),
+                    Some(arg) => arg.to_string_with(convfmt).chars().take(1).collect(),
+                    None => String::new(),
+                };
+                spec.pad("", &character, false)
+            }
+            's' => {
+                let text = args.next().map(|arg| arg.to_string_with(convfmt)).unwrap_or_default();
+                spec.format_string(&text)
+            }
+            // not a conversion, print as is
+            other => {
+                output.push_str(&text);
+                output.push(other);
+                continue;
+            }
+        };
+        output.push_str(&formatted);
+    }
+    output
+}
+
+#[test]
+fn test_format_number() {
+    assert_eq!(format_number(42.0, "%.6g"), "42");
+    assert_eq!(format_number(-3.0, "%.6g"), "-3");
+    assert_eq!(format_number(1.23456789, "%.6g"), "1.23457");
+    assert_eq!(format_number(0.1, "%.6g"), "0.1");
+    assert_eq!(format_number(1e20, "%.6g"), "1e+20");
+    assert_eq!(format_number(0.00001234, "%.6g"), "1.234e-05");
+    assert_eq!(format_number(123456.7, "%.6g"), "123457");
+    assert_eq!(format_number(1234567.8, "%.6g"), "1.23457e+06");
+    assert_eq!(format_number(2.5, "%.2f"), "2.50");
+    assert_eq!(format_number(0.5, "%s"), "0.5");
+    assert_eq!(format_number(1.23456789, "<%s>"), "<1.23457>");
+}
+
+#[test]
+fn test_sprintf() {
+    let args = [
+        Value::String("abc".to_string()),
+        Value::Number(65.0),
+        Value::StrNum("12.7".to_string()),
+    ];
+    assert_eq!(sprintf("%s-%c-%d%%", &args, "%.6g"), "abc-A-12%");
+    assert_eq!(sprintf("%.1s|%x|%e", &args[..1], "%.6g"), "a|0|0.000000e+00");
+    assert_eq!(sprintf("%.2f %g", &[Value::Number(1.23456), Value::Number(100000.0)], "%.6g"), "1.23 100000");
+}
+
+#[test]
+fn test_sprintf_flags_and_widths() {
+    let number = |number: f64| Value::Number(number);
+    assert_eq!(sprintf("[%5d|%-5d|%05d|%+d|% d]", &[number(42.0), number(42.0), number(-42.0), number(42.0), number(42.0)], "%.6g"), "[   42|42   |-0042|+42| 42]");
+    assert_eq!(sprintf("%.3d|%.0d|%8.3f|%-8.2e|", &[number(7.0), number(0.0), number(-1.23456), number(1234.5)], "%.6g"), "007||  -1.235|1.23e+03|");
+    assert_eq!(sprintf("%#o|%#x|%#X|%x|%u", &[number(8.0), number(255.0), number(255.0), number(-1.0), number(3.9)], "%.6g"), "010|0xff|0XFF|ffffffffffffffff|3");
+    assert_eq!(sprintf("%*d|%-*d|%.*f", &[number(4.0), number(1.0), number(3.0), number(2.0), number(2.0), number(1.23456)], "%.6g"), "   1|2  |1.23");
+    assert_eq!(sprintf("%*d|", &[number(-4.0), number(1.0)], "%.6g"), "1   |");
+    assert_eq!(sprintf("%#g|%g|%#.0f|%G", &[number(1.5), number(0.0), number(2.0), number(1e-10)], "%.6g"), "1.50000|0|2.|1E-10");
+    assert_eq!(sprintf("%5s|%-5s|%.2s|%3c", &[Value::String("ab".to_string()), Value::String("ab".to_string()), Value::String("xyz".to_string()), Value::String("q".to_string())], "%.6g"), "   ab|ab   |xy|  q");
+    assert_eq!(sprintf("%d|%c|%s", &[Value::String("12abc".to_string()), Value::StrNum("66".to_string()), number(0.5)], "%.6g"), "12|B|0.5");
+    assert_eq!(sprintf("%d %5.1f %-4f|", &[number(1e20), number(f64::INFINITY), number(f64::NAN)], "%.6g"), "100000000000000000000   inf nan |");
+    assert_eq!(sprintf("%5%|%z|%", &[], "%.6g"), "%|%z|%");
+}
+
+#[test]
+fn test_sprintf_huge_widths() {
+    let number = |number: f64| Value::Number(number);
+    assert_eq!(sprintf("%*d", &[number(1e18), number(1.0)], "%.6g").len(), MAX_COUNT);
+    assert_eq!(sprintf("%-*d|", &[number(-1e18), number(1.0)], "%.6g").len(), MAX_COUNT + 1);
+    assert_eq!(sprintf("%99999999999999999999s", &[number(1.0)], "%.6g").len(), MAX_COUNT);
+    assert_eq!(sprintf("%.*d", &[number(1e18), number(1.0)], "%.6g").len(), MAX_COUNT);
+    // past the digits Rust formats floats with the zeros are added
+    let fixed = sprintf("%.70000f", &[number(0.5)], "%.6g");
+    assert_eq!((fixed.len(), &fixed[..4]), (70002, "0.50"));
+    let exponential = sprintf("%.70000e", &[number(1.5)], "%.6g");
+    assert_eq!((exponential.len(), &exponential[exponential.len() - 6..]), (70006, "00e+00"));
+    assert_eq!(sprintf("%.70000g", &[number(0.1)], "%.6g"), "0.1000000000000000055511151231257827021181583404541015625");
+}
@@ -0,0 +1,224 @@
+// --gawk: the parts of the gawk extensions which don't need the interpreter,
+// @include, the orders of PROCINFO["sorted_in"] and asort, gensub's replacement
+// text and strftime.
+use std::cmp::Ordering;
+use std::ffi::CString;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::interpreter::{sorted_keys, Array};
+use crate::value::{str_to_number, Value};
+
+// what strftime() formats without arguments, PROCINFO["strftime"] in gawk
+pub const DEFAULT_TIME_FORMAT: &str = "%a %b %e %H:%M:%S %Z %Y";
+
+// the program files with the files they @include put before them, each file only
+// once; the @include lines are left blank so the lines of the rest don't move
+pub fn include_files(sources: Vec<(String, String)>) -> Result<Vec<(String, String)>, String> {
+    let mut included = vec![];
+    for (name, text) in sources {
+        include(name, text, &mut included)?;
+    }
+    Ok(included)
+}
+
+fn include(name: String, text: String, included: &mut Vec<(String, String)>) -> Result<(), String> {
+    let mut lines = vec![];
+    for line in text.split('\n') {
+        let Some(path) = include_path(line) else {
+            lines.push(line);
+            continue;
+        };
+        lines.push("");
+        // relative to the file which includes it, or to the current directory
+        let beside = Path::new(&name).with_file_name(&path);
+        let path = match beside.exists() {
+            true => beside.to_string_lossy().to_string(),
+            false => path,
+        };
+        if included.iter().any(|(name, _)| *name == path) {
+            continue;
+        }
+        let text = fs::read_to_string(&path).map_err(|e| format!("can't read included file {}: {}", path, e))?;
+        include(path, text, included)?;
+    }
+    included.push((name, lines.join("\n")));
+    Ok(())
+}
+
+// the file of an `@include "file"` line, comments may follow
+fn include_path(line: &str) -> Option<String> {
+    let rest = line.trim_start().strip_prefix("@include")?.trim_start();
+    let (path, rest) = rest.strip_prefix('"')?.split_once('"')?;
+    let rest = rest.trim_start();
+    (rest.is_empty() || rest.starts_with('#')).then(|| path.to_string())
+}
+
+// the order of for (k in array) for a PROCINFO["sorted_in"] like "@ind_num_asc",
+// None when it is not one of gawk's, it is then the name of a comparison function
+pub fn sorted_in(array: &Array, order: &str, convfmt: &str) -> Option<Vec<String>> {
+    let (order, descending) = match order.strip_suffix("_desc") {
+        Some(order) => (order, true),
+        None => (order.strip_suffix("_asc").unwrap_or(order), false),
+    };
+    let mut keys = sorted_keys(array);
+    match order {
+        "@unsorted" => return Some(keys),
+        "@ind_str" => keys.sort(),
+        "@ind_num" => keys.sort_by(|a, b| compare_numbers(str_to_number(a), str_to_number(b)).then(a.cmp(b))),
+        "@val_str" => keys.sort_by(|a, b| {
+            let (a_value, b_value) = (array[a].to_string_with(convfmt), array[b].to_string_with(convfmt));
+            a_value.cmp(&b_value).then(a.cmp(b))
+        }),
+        "@val_num" => keys.sort_by(|a, b| {
+            compare_numbers(array[a].to_number(), array[b].to_number()).then(a.cmp(b))
+        }),
+        "@val_type" => keys.sort_by(|a, b| compare_values(&array[a], &array[b], convfmt).then(a.cmp(b))),
+        _ => return None,
+    }
+    if descending {
+        keys.reverse();
+    }
+    Some(keys)
+}
+
+// the order of asort: numbers first by value, then strings
+pub fn compare_values(a: &Value, b: &Value, convfmt: &str) -> Ordering {
+    match (is_number(a), is_number(b)) {
+        (true, true) => compare_numbers(a.to_number(), b.to_number()),
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => a.to_string_with(convfmt).cmp(&b.to_string_with(convfmt)),
+    }
+}
+
+fn is_number(value: &Value) -> bool {
+    match value {
+        Value::Number(_) | Value::Uninitialized => true,
+        Value::StrNum(text) => crate::value::looks_numeric(text),
+        Value::String(_) => false,
+    }
+}
+
+fn compare_numbers(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+}
+
+// the replacement of gensub: \0 and & are the match, \1 to \9 the groups, \& a
+// literal & and \\ a backslash
+pub fn expand_replacement(replacement: &str, groups: &[&str]) -> String {
+    let group = |index: usize| groups.get(index).copied().unwrap_or_default();
+    let mut result = String::new();
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.peek() {
+                Some(&digit) if digit.is_ascii_digit() => {
+                    chars.next();
+                    result.push_str(group(digit as usize - '0' as usize));
+                }
+                Some('&') | Some('\\') => result.push(chars.next().unwrap()),
+                _ => result.push('\\'),
+            },
+            '&' => result.push_str(group(0)),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+// seconds since the epoch
+pub fn systime() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs()) as f64
+}
+
+// the C library's strftime, in the local time zone or in UTC
+pub fn strftime(format: &str, timestamp: f64, utc: bool) -> String {
+    // like C, the format ends at a NUL
+    let format = format.split('\0').next().unwrap_or_default();
+    if format.is_empty() {
+        return String::new();
+    }
+    let Ok(format) = CString::new(format) else {
+        return String::new();
+    };
+    let time = timestamp as libc::time_t;
+    // SAFETY: tm is plain data which gmtime_r and localtime_r fill in from `time`
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let converted = unsafe {
+        if utc {
+            libc::gmtime_r(&time, &mut tm)
+        } else {
+            libc::localtime_r(&time, &mut tm)
+        }
+    };
+    if converted.is_null() {
+        return String::new();
+    }
+    // strftime returns 0 when the buffer is too small, or for an empty result
+    let mut size = 256;
+    while size <= 1 << 20 {
+        let mut buffer = vec![0u8; size];
+        // SAFETY: strftime writes at most `size` bytes, NUL included, into buffer
+        let length = unsafe {
+            libc::strftime(buffer.as_mut_ptr() as *mut libc::c_char, size, format.as_ptr(), &tm)
+        };
+        if length > 0 {
+            buffer.truncate(length);
+            return String::from_utf8_lossy(&buffer).to_string();
+        }
+        size *= 4;
+    }
+    String::new()
+}
+
+#[test]
+fn test_include_files() {
+    let directory = std::env::temp_dir().join(format!("awk-include-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let library = directory.join("lib.awk");
+    fs::write(&library, "@include \"util.awk\"\nfunction f() { return g() }\n").unwrap();
+    fs::write(directory.join("util.awk"), "function g() { return 1 }\n").unwrap();
+    let main = directory.join("main.awk").to_string_lossy().to_string();
+    let text = "@include \"lib.awk\" # twice\n@include \"lib.awk\"\nBEGIN { print f() }\n";
+    let sources = include_files(vec![(main.clone(), text.to_string())]).unwrap();
+    let names: Vec<&str> = sources.iter().map(|(name, _)| name.as_str()).collect();
+    let util = directory.join("util.awk").to_string_lossy().to_string();
+    assert_eq!(names, vec![util.as_str(), library.to_str().unwrap(), main.as_str()]);
+    assert_eq!(sources[1].1, "\nfunction f() { return g() }\n");
+    assert_eq!(sources[2].1, "\n\nBEGIN { print f() }\n");
+    let missing = include_files(vec![("cmdline".to_string(), "@include \"nowhere.awk\"".to_string())]);
+    assert!(missing.unwrap_err().starts_with("can't read included file nowhere.awk"));
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_sorted_in() {
+    let array: Array = [("10", "b"), ("9", "a"), ("x", "10"), ("y", "9")]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), Value::from_input(value.to_string())))
+        .collect();
+    let order = |order: &str| sorted_in(&array, order, "%.6g").map(|keys| keys.join(" "));
+    assert_eq!(order("@unsorted").unwrap(), "9 10 x y");
+    assert_eq!(order("@ind_str_asc").unwrap(), "10 9 x y");
+    assert_eq!(order("@ind_num_desc").unwrap(), "10 9 y x");
+    assert_eq!(order("@val_str_asc").unwrap(), "x y 9 10");
+    assert_eq!(order("@val_num_asc").unwrap(), "10 9 y x");
+    assert_eq!(order("@val_type_asc").unwrap(), "y x 9 10");
+    assert_eq!(order("compare"), None);
+}
+
+#[test]
+fn test_expand_replacement() {
+    let groups = ["ab", "a", "b"];
+    assert_eq!(expand_replacement("[\\2\\1|&|\\0|\\&|\\\\|\\q]", &groups), "[ba|ab|ab|&|\\|\\q]");
+    assert_eq!(expand_replacement("\\2\\1\\9", &["a", "a", ""]), "a");
+}
+
+#[test]
+fn test_strftime() {
+    assert_eq!(strftime("%Y-%m-%d %H:%M:%S %j", 86400.0 * 365.0 + 3661.0, true), "1971-01-01 01:01:01 001");
+    assert_eq!(strftime("", 0.0, true), "");
+    assert_eq!(strftime("%%", 0.0, false), "%");
+}
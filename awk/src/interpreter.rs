@@ -0,0 +1,2366 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::builtins::{self, Random};
+use crate::bytecode::Bytecode;
+use crate::debugger::Debugger;
+use crate::diagnostic::Span;
+use crate::ere::{self, Ere};
+use crate::format::sprintf;
+use crate::gawk;
+use crate::lexer::unescape;
+use crate::parallel::{self, ParallelPlan};
+use crate::profile::Profile;
+use crate::record::{
+    quote_csv, split_regex, FieldSeparator, Record, RecordReader, RecordSeparator,
+};
+use crate::streams::{exit_status, InputKind, OutputKind, Streams};
+use crate::syntax_tree::{
+    Action, BinaryOperator, BuiltinFuncName, Expr, FunctionDeclaration, InputFunction, Item,
+    LValue, OutputRedirection, Pattern, PrintStatement, Program, SimpleGet, SimpleStatement,
+    SpannedStatement, Statement, UnaryOperator,
+};
+use crate::value::{looks_numeric, str_to_number, Value};
+use crate::vm;
+
+#[derive(Debug, PartialEq)]
+pub struct RuntimeError(pub String);
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "runtime error: {}", self.0)
+    }
+}
+
+impl From<io::Error> for RuntimeError {
+    fn from(error: io::Error) -> RuntimeError {
+        RuntimeError(error.to_string())
+    }
+}
+
+// `next` and `exit` leave the current action even from inside function calls,
+// so they travel up the call stack the same way errors do
+#[derive(Debug)]
+pub enum Unwind {
+    Error(RuntimeError),
+    Next,
+    Exit,
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Unwind {
+        Unwind::Error(error)
+    }
+}
+
+impl From<io::Error> for Unwind {
+    fn from(error: io::Error) -> Unwind {
+        Unwind::Error(error.into())
+    }
+}
+
+pub type EvalResult<T> = Result<T, Unwind>;
+
+fn error<T>(message: String) -> EvalResult<T> {
+    Err(Unwind::Error(RuntimeError(message)))
+}
+
+// the remaining ways out of a statement, handled by the enclosing loop or function
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+// what $expr refers to
+enum Field {
+    Index(usize),
+    // --json
+    Key(String),
+}
+
+pub type Array = HashMap<String, Value>;
+
+#[derive(Debug, Clone)]
+pub enum Variable {
+    Value(Value),
+    // arrays are shared so that they can be passed to functions by reference
+    Array(Rc<RefCell<Array>>),
+}
+
+impl Variable {
+    // `name` is only used in the error
+    pub fn value(&self, name: &str) -> EvalResult<Value> {
+        match self {
+            Variable::Value(value) => Ok(value.clone()),
+            Variable::Array(_) => error(format!("attempt to use array {} in a scalar context", name)),
+        }
+    }
+
+    pub fn set_value(&mut self, name: &str, value: Value) -> EvalResult<()> {
+        if let Variable::Array(_) = self {
+            return error(format!("attempt to use array {} in a scalar context", name));
+        }
+        *self = Variable::Value(value);
+        Ok(())
+    }
+
+    // an uninitialized variable becomes an array on first use as one
+    pub fn array(&mut self, name: &str) -> EvalResult<Rc<RefCell<Array>>> {
+        match self {
+            Variable::Array(array) => Ok(array.clone()),
+            Variable::Value(Value::Uninitialized) => {
+                let array = Rc::new(RefCell::new(Array::new()));
+                *self = Variable::Array(array.clone());
+                Ok(array)
+            }
+            Variable::Value(_) => error(format!("can't use scalar {} as array", name)),
+        }
+    }
+}
+
+// global variables by name, each name keeps its slot so compiled code can address it directly
+#[derive(Default)]
+struct Globals {
+    names: HashMap<String, usize>,
+    slots: Vec<Variable>,
+}
+
+impl Globals {
+    fn get(&self, name: &str) -> Option<&Variable> {
+        self.names.get(name).map(|&slot| &self.slots[slot])
+    }
+
+    // the slot of `name`, a new uninitialized one the first time
+    fn slot(&mut self, name: &str) -> usize {
+        if let Some(&slot) = self.names.get(name) {
+            return slot;
+        }
+        self.slots.push(Variable::Value(Value::Uninitialized));
+        self.names.insert(name.to_string(), self.slots.len() - 1);
+        self.slots.len() - 1
+    }
+
+    fn insert(&mut self, name: &str, variable: Variable) {
+        let slot = self.slot(name);
+        self.slots[slot] = variable;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&String, &Variable)> {
+        self.names.iter().map(|(name, &slot)| (name, &self.slots[slot]))
+    }
+}
+
+// numeric looking keys first, in numeric order, then the rest as strings
+pub fn sorted_keys(array: &Array) -> Vec<String> {
+    let mut keys: Vec<String> = array.keys().cloned().collect();
+    keys.sort_by(|a, b| match (looks_numeric(a), looks_numeric(b)) {
+        (true, true) => str_to_number(a)
+            .partial_cmp(&str_to_number(b))
+            .unwrap_or(Ordering::Equal),
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => a.cmp(b),
+    });
+    keys
+}
+
+pub fn builtin_name(builtin: &BuiltinFuncName) -> String {
+    format!("{:?}", builtin).to_lowercase()
+}
+
+pub fn check_arity(builtin: &BuiltinFuncName, count: usize) -> Result<(), RuntimeError> {
+    let arity = match builtin {
+        BuiltinFuncName::Rand | BuiltinFuncName::Systime => 0..=0,
+        BuiltinFuncName::Srand | BuiltinFuncName::Length | BuiltinFuncName::Fflush => 0..=1,
+        BuiltinFuncName::Cos
+        | BuiltinFuncName::Sin
+        | BuiltinFuncName::Exp
+        | BuiltinFuncName::Log
+        | BuiltinFuncName::Sqrt
+        | BuiltinFuncName::Int
+        | BuiltinFuncName::Tolower
+        | BuiltinFuncName::Toupper
+        | BuiltinFuncName::Close
+        | BuiltinFuncName::System => 1..=1,
+        BuiltinFuncName::Atan2 | BuiltinFuncName::Index | BuiltinFuncName::Match => 2..=2,
+        BuiltinFuncName::Substr
+        | BuiltinFuncName::Sub
+        | BuiltinFuncName::Gsub
+        | BuiltinFuncName::Split => 2..=3,
+        BuiltinFuncName::Sprintf => 1..=usize::MAX,
+        BuiltinFuncName::Gensub => 3..=4,
+        BuiltinFuncName::Asort | BuiltinFuncName::Asorti => 1..=2,
+        BuiltinFuncName::Strftime => 0..=3,
+    };
+    if !arity.contains(&count) {
+        return Err(RuntimeError(format!(
+            "function {} called with {} args",
+            builtin_name(builtin),
+            count
+        )));
+    }
+    Ok(())
+}
+
+// the third argument of split
+pub enum SplitSeparator {
+    Fs,
+    // /ere/
+    Ere(String),
+    Value(Value),
+}
+
+// the files named in ARGV, read one after the other by the main loop and plain getline
+#[derive(Default)]
+struct MainInput {
+    reader: Option<RecordReader>,
+    // ARGV index of the next operand
+    next: usize,
+    read_file: bool,
+}
+
+// how many regular expressions computed at run time are kept compiled
+const DYNAMIC_REGEXES: usize = 64;
+
+// assigning a field past this, or NF, is an error rather than an allocation of
+// that many empty fields
+const MAX_FIELDS: usize = 1_000_000;
+
+pub struct Interpreter {
+    globals: Globals,
+    // locals of the functions being called, innermost last
+    frames: Vec<HashMap<String, Variable>>,
+    functions: HashMap<String, Rc<FunctionDeclaration>>,
+    record: Record,
+    output: Box<dyn Write>,
+    // files and commands opened by print > file, >> file and | command
+    streams: Streams,
+    stdin: Option<Box<dyn io::BufRead>>,
+    input: MainInput,
+    // whether each range pattern, by item index, is between its start and end
+    ranges: HashMap<usize, bool>,
+    exit_code: i32,
+    // set when the program was found safe to run over chunks of input in parallel
+    parallel: Option<(ParallelPlan, usize)>,
+    // the /ere/ literals of the program, compiled, by their source
+    regexes: HashMap<String, Rc<Ere>>,
+    // regular expressions computed at run time, emptied when DYNAMIC_REGEXES are kept
+    dynamic_regexes: HashMap<String, Rc<Ere>>,
+    random: Random,
+    // FS (and whether RS is "") and RS, compiled for the text they were last seen with
+    field_separator: Option<((String, bool), FieldSeparator)>,
+    record_separator: Option<(String, RecordSeparator)>,
+    // --csv: records and fields are read as CSV and print quotes what it writes
+    csv: bool,
+    // --json: $"key" looks up a key of the record parsed as JSON
+    json: bool,
+    // run on the stack machine in vm.rs instead of walking the syntax tree
+    bytecode: Option<Bytecode>,
+    // --debug: asked before every statement whether to stop
+    debugger: Option<Box<Debugger>>,
+    // --profile: counts statements and times pattern-actions and functions
+    profile: Option<Profile>,
+    // --gawk: PROCINFO["sorted_in"] orders for (k in array)
+    gawk: bool,
+    // the BEGINFILE and ENDFILE actions with their index in the program
+    begin_file: Rc<Vec<(usize, Action)>>,
+    end_file: Rc<Vec<(usize, Action)>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariableSnapshot {
+    Value(Value),
+    Array(Array),
+}
+
+// copy of global variables which can be moved between threads
+pub type Snapshot = HashMap<String, VariableSnapshot>;
+
+impl Interpreter {
+    pub fn new(program: &Program, output: Box<dyn Write>) -> Interpreter {
+        let mut interpreter = Interpreter {
+            globals: Globals::default(),
+            frames: vec![],
+            functions: HashMap::new(),
+            record: Record::default(),
+            output,
+            streams: Streams::default(),
+            stdin: None,
+            input: MainInput::default(),
+            ranges: HashMap::new(),
+            exit_code: 0,
+            parallel: None,
+            regexes: HashMap::new(),
+            dynamic_regexes: HashMap::new(),
+            random: Random::default(),
+            field_separator: None,
+            record_separator: None,
+            csv: false,
+            json: false,
+            bytecode: None,
+            debugger: None,
+            profile: None,
+            gawk: false,
+            begin_file: Rc::default(),
+            end_file: Rc::default(),
+        };
+        let (mut begin_file, mut end_file) = (vec![], vec![]);
+        for (index, item) in program.items.iter().enumerate() {
+            match item {
+                Item::FunctionDeclaration(function) => interpreter.define_function(function),
+                Item::PatternAction(Pattern::BeginFile, action) => begin_file.push((index, action.clone())),
+                Item::PatternAction(Pattern::EndFile, action) => end_file.push((index, action.clone())),
+                _ => {}
+            }
+        }
+        interpreter.begin_file = Rc::new(begin_file);
+        interpreter.end_file = Rc::new(end_file);
+
+        let defaults = [
+            ("FS", " "),
+            ("OFS", " "),
+            ("ORS", "\n"),
+            ("RS", "\n"),
+            ("SUBSEP", "\x1c"),
+            ("CONVFMT", "%.6g"),
+            ("OFMT", "%.6g"),
+            ("FILENAME", ""),
+        ];
+        for (name, value) in defaults {
+            interpreter.set_global(name, Value::String(value.to_string()));
+        }
+        for (name, value) in [("NR", 0.0), ("FNR", 0.0), ("RSTART", 0.0), ("RLENGTH", -1.0)] {
+            interpreter.set_global(name, Value::Number(value));
+        }
+        let environ: Array = std::env::vars()
+            .map(|(key, value)| (key, Value::from_input(value)))
+            .collect();
+        interpreter.globals.insert(
+            "ENVIRON",
+            Variable::Array(Rc::new(RefCell::new(environ))),
+        );
+        interpreter.set_argv(&["awk".to_string()]);
+        interpreter
+    }
+
+    // a later definition replaces an earlier one, which only the REPL makes
+    pub fn define_function(&mut self, function: &FunctionDeclaration) {
+        self.functions
+            .insert(function.name.clone(), Rc::new(function.clone()));
+    }
+
+    // records are read from here for "-" or when there are no input files
+    pub fn set_stdin(&mut self, stdin: Box<dyn io::BufRead>) {
+        self.stdin = Some(stdin);
+    }
+
+    // FS and RS are not used any more, OFS is a comma unless changed later
+    pub fn set_csv(&mut self) {
+        self.csv = true;
+        self.record.set_csv(true);
+        self.set_global("OFS", Value::String(",".to_string()));
+    }
+
+    pub fn set_json(&mut self) {
+        self.json = true;
+    }
+
+    pub fn json(&self) -> bool {
+        self.json
+    }
+
+    pub fn set_bytecode(&mut self, bytecode: Bytecode) {
+        // an invalid one is reported when it is used
+        for ere in &bytecode.eres {
+            let _ = self.literal_regex(ere);
+        }
+        self.bytecode = Some(bytecode);
+    }
+
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.debugger = Some(Box::new(debugger));
+    }
+
+    // PROCINFO is an array of its own, strftime() formats with PROCINFO["strftime"]
+    pub fn set_gawk(&mut self) {
+        self.gawk = true;
+        let procinfo: Array = [
+            ("version", Value::String(crate::meta::VERSION.to_string())),
+            ("pid", Value::Number(std::process::id() as f64)),
+            ("strftime", Value::String(gawk::DEFAULT_TIME_FORMAT.to_string())),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+        self.globals.insert("PROCINFO", Variable::Array(Rc::new(RefCell::new(procinfo))));
+    }
+
+    pub fn gawk(&self) -> bool {
+        self.gawk
+    }
+
+    pub fn set_profile(&mut self) {
+        self.profile = Some(Profile::default());
+    }
+
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    pub fn set_exit_code(&mut self, code: i32) {
+        self.exit_code = code;
+    }
+
+    pub fn set_parallel(&mut self, plan: ParallelPlan, jobs: usize) {
+        self.parallel = Some((plan, jobs));
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        self.globals
+            .iter()
+            .map(|(name, variable)| {
+                let snapshot = match variable {
+                    Variable::Value(value) => VariableSnapshot::Value(value.clone()),
+                    Variable::Array(array) => VariableSnapshot::Array(array.borrow().clone()),
+                };
+                (name.clone(), snapshot)
+            })
+            .collect()
+    }
+
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        for (name, snapshot) in snapshot {
+            let variable = match snapshot {
+                VariableSnapshot::Value(value) => Variable::Value(value),
+                VariableSnapshot::Array(array) => Variable::Array(Rc::new(RefCell::new(array))),
+            };
+            self.globals.insert(&name, variable);
+        }
+    }
+
+    pub fn write_output(&mut self, output: &[u8]) -> Result<(), RuntimeError> {
+        self.output.write_all(output)?;
+        Ok(())
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals.insert(name, Variable::Value(value));
+    }
+
+    // ARGV[0] is the program name, the rest are the operands which run reads as input
+    pub fn set_argv(&mut self, argv: &[String]) {
+        let array: Array = argv
+            .iter()
+            .enumerate()
+            .map(|(index, arg)| (index.to_string(), Value::from_input(arg.clone())))
+            .collect();
+        self.globals.insert(
+            "ARGV",
+            Variable::Array(Rc::new(RefCell::new(array))),
+        );
+        self.set_global("ARGC", Value::Number(argv.len() as f64));
+    }
+
+    // var=value from -v or from the operands, false when `text` is not an assignment
+    pub fn command_line_assignment(&mut self, text: &str) -> bool {
+        match split_assignment(text) {
+            Some((name, value)) => {
+                self.set_global(name, Value::from_input(unescape(value)));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn global_string(&self, name: &str) -> String {
+        match self.globals.get(name) {
+            Some(Variable::Value(value)) => value.to_string_with(&self.convfmt()),
+            _ => String::new(),
+        }
+    }
+
+    fn convfmt(&self) -> Cow<'_, str> {
+        match self.globals.get("CONVFMT") {
+            Some(Variable::Value(Value::String(fmt) | Value::StrNum(fmt))) => Cow::Borrowed(fmt),
+            Some(Variable::Value(value)) => Cow::Owned(value.to_string_with("%.6g")),
+            _ => Cow::Borrowed("%.6g"),
+        }
+    }
+
+    pub fn to_string(&self, value: &Value) -> String {
+        value.to_string_with(&self.convfmt())
+    }
+
+    fn field_separator(&mut self) -> EvalResult<FieldSeparator> {
+        if self.csv {
+            return Ok(FieldSeparator::Csv);
+        }
+        let key = (self.global_string("FS"), self.global_string("RS").is_empty());
+        match &self.field_separator {
+            Some((cached, separator)) if *cached == key => Ok(separator.clone()),
+            _ => {
+                let separator = FieldSeparator::new(&key.0, key.1).map_err(RuntimeError)?;
+                self.field_separator = Some((key, separator.clone()));
+                Ok(separator)
+            }
+        }
+    }
+
+    fn record_separator(&mut self) -> EvalResult<RecordSeparator> {
+        if self.csv {
+            return Ok(RecordSeparator::Csv);
+        }
+        let rs = self.global_string("RS");
+        match &self.record_separator {
+            Some((cached, separator)) if *cached == rs => Ok(separator.clone()),
+            _ => {
+                let separator = RecordSeparator::new(&rs).map_err(RuntimeError)?;
+                self.record_separator = Some((rs, separator.clone()));
+                Ok(separator)
+            }
+        }
+    }
+
+    // numbers in print output go through OFMT instead of CONVFMT
+    fn to_output_string(&self, value: &Value) -> String {
+        value.to_string_with(&self.global_string("OFMT"))
+    }
+
+    pub fn run(&mut self, program: &Program) -> Result<i32, RuntimeError> {
+        let result = match self.bytecode.take() {
+            Some(bytecode) => vm::run(self, &bytecode),
+            None => self.run_program(program),
+        };
+        let code = self.finish()?;
+        match result {
+            Ok(()) | Err(Unwind::Exit) => Ok(code),
+            Err(Unwind::Next) => Err(RuntimeError("next used in BEGIN or END".to_string())),
+            Err(Unwind::Error(error)) => Err(error),
+        }
+    }
+
+    // flushes the output and closes the files and commands still open
+    pub fn finish(&mut self) -> Result<i32, RuntimeError> {
+        self.output.flush()?;
+        self.streams.close_all()?;
+        Ok(self.exit_code)
+    }
+
+    // writes out what print buffered so far
+    pub fn flush(&mut self) -> Result<(), RuntimeError> {
+        self.output.flush()?;
+        Ok(())
+    }
+
+    fn run_program(&mut self, program: &Program) -> EvalResult<()> {
+        let mut exited = false;
+        for (index, item) in program.items.iter().enumerate() {
+            if let Item::PatternAction(Pattern::Begin, action) = item {
+                match self.run_special(index, action) {
+                    Err(Unwind::Exit) => {
+                        exited = true;
+                        break;
+                    }
+                    result => result?,
+                }
+            }
+        }
+
+        // a program with only BEGIN actions does not read any input
+        let reads_input = program.items.iter().any(|item| {
+            !matches!(
+                item,
+                Item::PatternAction(Pattern::Begin, _) | Item::FunctionDeclaration(_)
+            )
+        });
+        if !exited && reads_input {
+            let result = match self.parallel.take() {
+                // chunks can only be cut at a single character record separator
+                Some((plan, jobs)) if self.global_string("RS").len() == 1 && !self.csv => {
+                    parallel::run_records(self, program, &plan, jobs).map_err(Unwind::from)
+                }
+                _ => self.run_records(program),
+            };
+            match result {
+                Err(Unwind::Exit) => {}
+                result => result?,
+            }
+        }
+
+        // exit inside END stops right away, that is handled by the caller
+        for (index, item) in program.items.iter().enumerate() {
+            if let Item::PatternAction(Pattern::End, action) = item {
+                self.run_special(index, action)?;
+            }
+        }
+        Ok(())
+    }
+
+    // a BEGIN, END, BEGINFILE or ENDFILE action, the item at `index`
+    fn run_special(&mut self, index: usize, action: &Action) -> EvalResult<()> {
+        let started = self.profile.is_some().then(Instant::now);
+        let result = self.run_action(action);
+        if let (Some(profile), Some(started)) = (self.profile.as_mut(), started) {
+            profile.item(index, true, started.elapsed());
+        }
+        result
+    }
+
+    pub fn open_operand(&mut self, operand: &str) -> Result<Box<dyn io::BufRead>, RuntimeError> {
+        if operand == "-" {
+            return Ok(match self.stdin.take() {
+                Some(stdin) => stdin,
+                None => Box::new(BufReader::new(io::stdin())),
+            });
+        }
+        match File::open(operand) {
+            Ok(file) => Ok(Box::new(BufReader::new(file))),
+            Err(e) => Err(RuntimeError(format!("can't open file {}: {}", operand, e))),
+        }
+    }
+
+    pub fn argv(&mut self, index: usize) -> Result<String, RuntimeError> {
+        let argv = match self.get_array("ARGV") {
+            Ok(argv) => argv,
+            Err(_) => return Err(RuntimeError("ARGV is not an array".to_string())),
+        };
+        let arg = argv.borrow().get(&index.to_string()).cloned();
+        Ok(arg.map(|arg| self.to_string(&arg)).unwrap_or_default())
+    }
+
+    fn run_records(&mut self, program: &Program) -> EvalResult<()> {
+        while let Some(text) = self.next_record()? {
+            self.set_record(text)?;
+            match self.run_items(program) {
+                Ok(()) | Err(Unwind::Next) => {}
+                Err(unwind) => return Err(unwind),
+            }
+        }
+        Ok(())
+    }
+
+    // the next record of the main input, counted in NR and FNR
+    pub fn next_record(&mut self) -> EvalResult<Option<String>> {
+        loop {
+            let rs = self.record_separator()?;
+            if let Some(reader) = self.input.reader.as_mut() {
+                if let Some(text) = reader.read_record(&rs)? {
+                    self.increment_global("NR");
+                    self.increment_global("FNR");
+                    return Ok(Some(text));
+                }
+                self.input.reader = None;
+                self.run_file_actions(false)?;
+            }
+            if !self.open_next_operand()? {
+                return Ok(None);
+            }
+            self.run_file_actions(true)?;
+        }
+    }
+
+    // BEGINFILE once a file of the main input is open, ENDFILE after its last record
+    fn run_file_actions(&mut self, begin: bool) -> EvalResult<()> {
+        let actions = if begin { self.begin_file.clone() } else { self.end_file.clone() };
+        for (index, action) in actions.iter() {
+            match self.run_special(*index, action) {
+                Err(Unwind::Next) => return error("next used in BEGINFILE or ENDFILE".to_string()),
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    // ARGV is read as the records are, so BEGIN can change which files are read
+    fn open_next_operand(&mut self) -> EvalResult<bool> {
+        if self.input.next == 0 {
+            self.input.next = 1;
+        }
+        while (self.input.next as f64) < self.global_number("ARGC") {
+            let operand = self.argv(self.input.next)?;
+            self.input.next += 1;
+            if operand.is_empty() || self.command_line_assignment(&operand) {
+                continue;
+            }
+            self.input.read_file = true;
+            self.open_main_input(&operand, &operand)?;
+            return Ok(true);
+        }
+        if self.input.read_file {
+            return Ok(false);
+        }
+        // stdin when there are no files
+        self.input.read_file = true;
+        self.open_main_input("-", "")?;
+        Ok(true)
+    }
+
+    // reads the next records from `path`, and stdin is no longer read when the input ends
+    pub fn load(&mut self, path: &str) -> EvalResult<()> {
+        self.input.read_file = true;
+        self.open_main_input(path, path)
+    }
+
+    fn open_main_input(&mut self, operand: &str, filename: &str) -> EvalResult<()> {
+        self.input.reader = Some(RecordReader::new(self.open_operand(operand)?));
+        self.set_global("FILENAME", Value::String(filename.to_string()));
+        self.set_global("FNR", Value::Number(0.0));
+        Ok(())
+    }
+
+    // runs the pattern-actions over one chunk of a file, see parallel.rs
+    pub fn run_chunk(
+        &mut self,
+        program: &Program,
+        input: Box<dyn io::BufRead>,
+        filename: &str,
+    ) -> Result<(), RuntimeError> {
+        self.set_global("FILENAME", Value::String(filename.to_string()));
+        let result = self.read_records(program, &mut RecordReader::new(input));
+        self.output.flush()?;
+        match result {
+            Ok(()) => Ok(()),
+            Err(Unwind::Error(error)) => Err(error),
+            Err(_) => Err(RuntimeError("exit in a parallel pattern-action".to_string())),
+        }
+    }
+
+    fn read_records(&mut self, program: &Program, reader: &mut RecordReader) -> EvalResult<()> {
+        while let Some(text) = reader.read_record(&self.record_separator()?)? {
+            self.increment_global("NR");
+            self.increment_global("FNR");
+            self.set_record(text)?;
+            match self.run_items(program) {
+                Ok(()) | Err(Unwind::Next) => {}
+                Err(unwind) => return Err(unwind),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn global_number(&self, name: &str) -> f64 {
+        match self.globals.get(name) {
+            Some(Variable::Value(value)) => value.to_number(),
+            _ => 0.0,
+        }
+    }
+
+    fn increment_global(&mut self, name: &str) {
+        let value = self.global_number(name);
+        self.set_global(name, Value::Number(value + 1.0));
+    }
+
+    // pattern-action items for the current record
+    pub fn run_items(&mut self, program: &Program) -> EvalResult<()> {
+        for (index, item) in program.items.iter().enumerate() {
+            let (pattern, action) = match item {
+                Item::PatternAction(
+                    Pattern::Begin | Pattern::End | Pattern::BeginFile | Pattern::EndFile,
+                    _,
+                ) => continue,
+                Item::Action(action) => (None, Some(action)),
+                Item::PatternAction(pattern, action) => (Some(pattern), Some(action)),
+                Item::Pattern(pattern) => (Some(pattern), None),
+                Item::FunctionDeclaration(_) => continue,
+            };
+            let started = self.profile.is_some().then(Instant::now);
+            let matched = match pattern {
+                None => true,
+                Some(Pattern::Begin | Pattern::End | Pattern::BeginFile | Pattern::EndFile) => {
+                    unreachable!("skipped above")
+                }
+                Some(Pattern::Expr(expr)) => self.eval(expr)?.to_bool(),
+                Some(Pattern::ExprCommaNewlineOptExpr(start, end)) => {
+                    self.match_range(index, start, end)?
+                }
+            };
+            let result = match action {
+                _ if !matched => Ok(()),
+                Some(action) => self.run_action(action),
+                None => self.print_record(),
+            };
+            // next leaves the action, it still counts
+            if let (Some(profile), Some(started)) = (self.profile.as_mut(), started) {
+                profile.item(index, matched, started.elapsed());
+            }
+            result?;
+        }
+        Ok(())
+    }
+
+    fn match_range(&mut self, index: usize, start: &Expr, end: &Expr) -> EvalResult<bool> {
+        let active = self.ranges.get(&index).copied().unwrap_or(false);
+        if !active && !self.eval(start)?.to_bool() {
+            return Ok(false);
+        }
+        // the end pattern is checked against the starting record too
+        let ended = self.eval(end)?.to_bool();
+        self.ranges.insert(index, !ended);
+        Ok(true)
+    }
+
+    pub fn print_record(&mut self) -> EvalResult<()> {
+        let line = format!("{}{}", self.record.text(), self.global_string("ORS"));
+        self.output.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn run_action(&mut self, action: &Action) -> EvalResult<()> {
+        match self.exec_block(action)? {
+            Flow::Normal => Ok(()),
+            Flow::Break | Flow::Continue => {
+                error("break or continue outside a loop".to_string())
+            }
+            Flow::Return(_) => error("return outside a function".to_string()),
+        }
+    }
+
+    fn exec_block(&mut self, action: &Action) -> EvalResult<Flow> {
+        for statement in &action.statements {
+            match self.exec(statement)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    // runs a loop body, Some(flow) when the loop has to stop
+    fn exec_loop_body(&mut self, body: &SpannedStatement) -> EvalResult<Option<Flow>> {
+        match self.exec(body)? {
+            Flow::Normal | Flow::Continue => Ok(None),
+            Flow::Break => Ok(Some(Flow::Normal)),
+            flow => Ok(Some(flow)),
+        }
+    }
+
+    fn exec(&mut self, statement: &SpannedStatement) -> EvalResult<Flow> {
+        // blocks are only stepped into
+        if self.debugger.is_some() && !matches!(statement.statement, Statement::Action(_)) {
+            self.debug(statement.span)?;
+        }
+        if let Some(profile) = self.profile.as_mut() {
+            profile.statement(statement.span);
+        }
+        match &statement.statement {
+            Statement::Action(action) => self.exec_block(action),
+            Statement::If(condition, then_branch, else_branch) => {
+                if self.eval(condition)?.to_bool() {
+                    return self.exec(then_branch);
+                }
+                match else_branch {
+                    Some(else_branch) => self.exec(else_branch),
+                    None => Ok(Flow::Normal),
+                }
+            }
+            Statement::While(condition, body) => {
+                while self.eval(condition)?.to_bool() {
+                    if let Some(flow) = self.exec_loop_body(body)? {
+                        return Ok(flow);
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Statement::Do(body, condition) => {
+                loop {
+                    if let Some(flow) = self.exec_loop_body(body)? {
+                        return Ok(flow);
+                    }
+                    if !self.eval(condition)?.to_bool() {
+                        return Ok(Flow::Normal);
+                    }
+                }
+            }
+            Statement::For(init, condition, update, body) => {
+                if let Some(init) = init {
+                    self.exec_simple(init)?;
+                }
+                loop {
+                    if let Some(condition) = condition {
+                        if !self.eval(condition)?.to_bool() {
+                            return Ok(Flow::Normal);
+                        }
+                    }
+                    if let Some(flow) = self.exec_loop_body(body)? {
+                        return Ok(flow);
+                    }
+                    if let Some(update) = update {
+                        self.exec_simple(update)?;
+                    }
+                }
+            }
+            Statement::ForIn(key, array, body) => {
+                let array = self.get_array(array)?;
+                let keys = self.for_in_keys(&array)?;
+                for k in keys {
+                    // elements deleted by the body are skipped
+                    if !array.borrow().contains_key(&k) {
+                        continue;
+                    }
+                    self.assign(&LValue::Name(key.clone()), Value::from_input(k))?;
+                    if let Some(flow) = self.exec_loop_body(body)? {
+                        return Ok(flow);
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Statement::Switch(subject, cases) => {
+                let subject = self.eval(subject)?;
+                let mut start = cases.iter().position(|case| case.label.is_none());
+                for (index, case) in cases.iter().enumerate() {
+                    if let Some(label) = &case.label {
+                        if self.case_matches(&subject, label)? {
+                            start = Some(index);
+                            break;
+                        }
+                    }
+                }
+                // the cases after the matching one run too, up to a break
+                let Some(start) = start else {
+                    return Ok(Flow::Normal);
+                };
+                for case in &cases[start..] {
+                    for statement in &case.statements {
+                        match self.exec(statement)? {
+                            Flow::Normal => {}
+                            Flow::Break => return Ok(Flow::Normal),
+                            flow => return Ok(flow),
+                        }
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Statement::Empty => Ok(Flow::Normal),
+            Statement::Break => Ok(Flow::Break),
+            Statement::Continue => Ok(Flow::Continue),
+            Statement::Next => Err(Unwind::Next),
+            Statement::Exit(code) => {
+                if let Some(code) = code {
+                    self.exit_code = self.eval(code)?.to_number() as i32;
+                }
+                Err(Unwind::Exit)
+            }
+            Statement::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Uninitialized,
+                };
+                Ok(Flow::Return(value))
+            }
+            Statement::SimpleStatement(simple) => {
+                self.exec_simple(simple)?;
+                Ok(Flow::Normal)
+            }
+        }
+    }
+
+    // a regex label matches the text of the subject, the others compare like ==
+    fn case_matches(&mut self, subject: &Value, label: &Expr) -> EvalResult<bool> {
+        match label {
+            Expr::Ere(ere) => {
+                let text = self.to_string(subject);
+                Ok(self.literal_regex(ere)?.is_match(&text))
+            }
+            label => {
+                let label = self.eval(label)?;
+                Ok(subject.compare(&label, &self.convfmt()) == Ordering::Equal)
+            }
+        }
+    }
+
+    // the keys of a for (k in array) in the order of PROCINFO["sorted_in"] with --gawk,
+    // either one of gawk's orders or the name of a function comparing two elements
+    fn for_in_keys(&mut self, array: &Rc<RefCell<Array>>) -> EvalResult<Vec<String>> {
+        let order = match self.globals.get("PROCINFO") {
+            Some(Variable::Array(procinfo)) if self.gawk => procinfo.borrow().get("sorted_in").cloned(),
+            _ => None,
+        };
+        let Some(order) = order.map(|order| self.to_string(&order)) else {
+            return Ok(sorted_keys(&array.borrow()));
+        };
+        if let Some(keys) = gawk::sorted_in(&array.borrow(), &order, &self.convfmt()) {
+            return Ok(keys);
+        }
+        let Some(function) = self.functions.get(&order).cloned() else {
+            return error(format!("PROCINFO[\"sorted_in\"] is {}, neither an order nor a function", order));
+        };
+        let elements = array.borrow().clone();
+        let mut keys = sorted_keys(&elements);
+        let mut failed = None;
+        keys.sort_by(|a, b| {
+            if failed.is_some() {
+                return Ordering::Equal;
+            }
+            let args = [
+                Value::from_input(a.clone()),
+                elements[a].clone(),
+                Value::from_input(b.clone()),
+                elements[b].clone(),
+            ];
+            match self.call_with_values(&function, args.into_iter().collect()) {
+                Ok(result) => result.to_number().partial_cmp(&0.0).unwrap_or(Ordering::Equal),
+                Err(unwind) => {
+                    failed = Some(unwind);
+                    Ordering::Equal
+                }
+            }
+        });
+        match failed {
+            Some(unwind) => Err(unwind),
+            None => Ok(keys),
+        }
+    }
+
+    // lets the debugger stop before the statement at `span`
+    fn debug(&mut self, span: Span) -> EvalResult<()> {
+        let Some(mut debugger) = self.debugger.take() else {
+            return Ok(());
+        };
+        let result = debugger.before(self, span);
+        self.debugger = Some(debugger);
+        result
+    }
+
+    // how many function calls deep the statement being run is
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    fn exec_simple(&mut self, statement: &SimpleStatement) -> EvalResult<()> {
+        match statement {
+            SimpleStatement::Expr(expr) => {
+                self.eval(expr)?;
+            }
+            SimpleStatement::Delete(name, subscripts) if subscripts.is_empty() => {
+                self.get_array(name)?.borrow_mut().clear();
+            }
+            SimpleStatement::Delete(name, subscripts) => {
+                let key = self.subscript(subscripts)?;
+                self.get_array(name)?.borrow_mut().remove(&key);
+            }
+            SimpleStatement::PrintStatement(PrintStatement::Print(exprs, redirection)) => {
+                let mut values = vec![];
+                for expr in exprs {
+                    values.push(self.eval(expr)?);
+                }
+                let target = self.output_target(redirection)?;
+                self.print(values, target)?;
+            }
+            SimpleStatement::PrintStatement(PrintStatement::Printf(exprs, redirection)) => {
+                let mut values = vec![];
+                for expr in exprs {
+                    values.push(self.eval(expr)?);
+                }
+                let target = self.output_target(redirection)?;
+                self.printf(values, target)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn output_target(&mut self, redirection: &Option<OutputRedirection>) -> EvalResult<Option<(OutputKind, Value)>> {
+        let (kind, target) = match redirection {
+            None => return Ok(None),
+            Some(OutputRedirection::GreaterThan(target)) => (OutputKind::File, target),
+            Some(OutputRedirection::Append(target)) => (OutputKind::Append, target),
+            Some(OutputRedirection::Pipe(target)) => (OutputKind::Pipe, target),
+        };
+        Ok(Some((kind, self.eval(target)?)))
+    }
+
+    // print without values prints $0
+    pub fn print(&mut self, values: Vec<Value>, target: Option<(OutputKind, Value)>) -> EvalResult<()> {
+        let line = if values.is_empty() {
+            self.record.text().to_string()
+        } else {
+            let texts: Vec<String> = values
+                .iter()
+                .map(|value| {
+                    let text = self.to_output_string(value);
+                    if self.csv { quote_csv(&text) } else { text }
+                })
+                .collect();
+            texts.join(&self.global_string("OFS"))
+        };
+        let line = line + &self.global_string("ORS");
+        self.write_to(target, &line)
+    }
+
+    pub fn printf(&mut self, mut values: Vec<Value>, target: Option<(OutputKind, Value)>) -> EvalResult<()> {
+        let format = self.to_string(&values.remove(0));
+        let formatted = sprintf(&format, &values, &self.convfmt());
+        self.write_to(target, &formatted)
+    }
+
+    // /dev/stdout and /dev/stderr name the standard streams instead of files
+    fn write_to(&mut self, target: Option<(OutputKind, Value)>, text: &str) -> EvalResult<()> {
+        let (kind, target) = match target {
+            None => {
+                self.output.write_all(text.as_bytes())?;
+                return Ok(());
+            }
+            Some(target) => target,
+        };
+        let name = self.to_string(&target);
+        match name.as_str() {
+            "/dev/stdout" => self.output.write_all(text.as_bytes())?,
+            "/dev/stderr" => io::stderr().write_all(text.as_bytes())?,
+            name => {
+                // a command writes to the same stdout, what was printed before must come first
+                if kind == OutputKind::Pipe && !self.streams.is_open(name) {
+                    self.output.flush()?;
+                }
+                if let Err(e) = self.streams.write(kind, name, text.as_bytes()) {
+                    return error(format!("can't redirect to {}: {}", name, e));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // arr[a, b] is stored under a SUBSEP b
+    fn subscript(&mut self, subscripts: &[Expr]) -> EvalResult<String> {
+        let mut values = vec![];
+        for subscript in subscripts {
+            values.push(self.eval(subscript)?);
+        }
+        Ok(self.join_subscript(&values))
+    }
+
+    pub fn join_subscript(&self, values: &[Value]) -> String {
+        if let [value] = values {
+            return self.to_string(value);
+        }
+        let keys: Vec<String> = values.iter().map(|value| self.to_string(value)).collect();
+        keys.join(&self.global_string("SUBSEP"))
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Variable> {
+        if let Some(frame) = self.frames.last() {
+            if let Some(variable) = frame.get(name) {
+                return Some(variable);
+            }
+        }
+        self.globals.get(name)
+    }
+
+    // a name which is not a local is a global, created when first used
+    fn lookup_mut(&mut self, name: &str) -> &mut Variable {
+        if self.frames.last().is_some_and(|frame| frame.contains_key(name)) {
+            return self.frames.last_mut().unwrap().get_mut(name).unwrap();
+        }
+        let slot = self.globals.slot(name);
+        &mut self.globals.slots[slot]
+    }
+
+    fn get_array(&mut self, name: &str) -> EvalResult<Rc<RefCell<Array>>> {
+        self.lookup_mut(name).array(name)
+    }
+
+    // the slot compiled code uses for the global `name`
+    pub fn global_slot(&mut self, name: &str) -> usize {
+        self.globals.slot(name)
+    }
+
+    pub fn global_mut(&mut self, slot: usize) -> &mut Variable {
+        &mut self.globals.slots[slot]
+    }
+
+    // with --json, $ of a string which is not a number is a key of the record
+    fn field(&self, index: Value) -> EvalResult<Field> {
+        match index {
+            Value::String(key) if self.json && !looks_numeric(&key) => Ok(Field::Key(key)),
+            value => {
+                let index = value.to_number();
+                if index < 0.0 {
+                    return error(format!("trying to access out of range field {}", index));
+                }
+                Ok(Field::Index(index as usize))
+            }
+        }
+    }
+
+    // $index
+    pub fn get_field(&mut self, index: Value) -> EvalResult<Value> {
+        let index = match self.field(index)? {
+            Field::Index(index) => index,
+            Field::Key(key) => return Ok(self.record.key(&key)),
+        };
+        if index == 0 {
+            return Ok(Value::from_input(self.record.text().to_string()));
+        }
+        match self.record.field(index) {
+            Some(field) => Ok(Value::from_input(field.to_string())),
+            None => Ok(Value::Uninitialized),
+        }
+    }
+
+    pub fn set_field(&mut self, index: Value, value: Value) -> EvalResult<()> {
+        let index = match self.field(index)? {
+            Field::Index(index) => index,
+            Field::Key(key) => return error(format!("can't assign to ${:?}, JSON keys are read only", key)),
+        };
+        let text = self.to_string(&value);
+        if index == 0 {
+            self.set_record(text)?;
+        } else if index > MAX_FIELDS {
+            return error(format!("field index {} too large", index));
+        } else {
+            let ofs = self.global_string("OFS");
+            self.record.set_field(index, text, &ofs);
+        }
+        Ok(())
+    }
+
+    pub fn nf(&self) -> Value {
+        Value::Number(self.record.nf() as f64)
+    }
+
+    pub fn set_nf(&mut self, value: Value) -> EvalResult<()> {
+        let nf = value.to_number();
+        if nf < 0.0 {
+            return error(format!("NF set to negative value {}", nf));
+        }
+        if nf > MAX_FIELDS as f64 {
+            return error(format!("field index {} too large", nf));
+        }
+        let ofs = self.global_string("OFS");
+        self.record.set_nf(nf as usize, &ofs);
+        Ok(())
+    }
+
+    // splits `text` into the fields of $0 with the current FS
+    pub fn set_record(&mut self, text: String) -> EvalResult<()> {
+        let fs = self.field_separator()?;
+        self.record.set(text, &fs);
+        Ok(())
+    }
+
+    fn get_lvalue(&mut self, lvalue: &LValue) -> EvalResult<Value> {
+        match lvalue {
+            LValue::Name(name) if name == "NF" => Ok(self.nf()),
+            LValue::Name(name) => match self.lookup(name) {
+                Some(variable) => variable.value(name),
+                None => Ok(Value::Uninitialized),
+            },
+            LValue::NameOpenSquareBraceExprListCloseSquareBrace(name, subscripts) => {
+                let key = self.subscript(subscripts)?;
+                let array = self.get_array(name)?;
+                // referencing an element creates it
+                let value = array
+                    .borrow_mut()
+                    .entry(key)
+                    .or_insert(Value::Uninitialized)
+                    .clone();
+                Ok(value)
+            }
+            LValue::DollarExpr(index) => {
+                let index = self.eval(index)?;
+                self.get_field(index)
+            }
+        }
+    }
+
+    // evaluates subscripts and the field index once, for operators which read and then assign
+    fn resolve_lvalue(&mut self, lvalue: &LValue) -> EvalResult<LValue> {
+        match lvalue {
+            LValue::Name(name) => Ok(LValue::Name(name.clone())),
+            LValue::NameOpenSquareBraceExprListCloseSquareBrace(name, subscripts) => {
+                let key = self.subscript(subscripts)?;
+                Ok(LValue::NameOpenSquareBraceExprListCloseSquareBrace(
+                    name.clone(),
+                    vec![Expr::String(key)],
+                ))
+            }
+            LValue::DollarExpr(index) => {
+                let index = self.eval(index)?;
+                match self.field(index)? {
+                    Field::Index(index) => Ok(LValue::DollarExpr(Box::new(Expr::Number(index as f64)))),
+                    Field::Key(key) => Ok(LValue::DollarExpr(Box::new(Expr::String(key)))),
+                }
+            }
+        }
+    }
+
+    // ++ and --, the value is the one before the change unless `prefix`
+    fn increment(&mut self, lvalue: &LValue, step: f64, prefix: bool) -> EvalResult<Value> {
+        let lvalue = self.resolve_lvalue(lvalue)?;
+        let old = self.get_lvalue(&lvalue)?.to_number();
+        self.assign(&lvalue, Value::Number(old + step))?;
+        Ok(Value::Number(if prefix { old + step } else { old }))
+    }
+
+    fn assign(&mut self, lvalue: &LValue, value: Value) -> EvalResult<()> {
+        match lvalue {
+            LValue::Name(name) if name == "NF" => self.set_nf(value),
+            LValue::Name(name) => self.lookup_mut(name).set_value(name, value),
+            LValue::NameOpenSquareBraceExprListCloseSquareBrace(name, subscripts) => {
+                let key = self.subscript(subscripts)?;
+                self.get_array(name)?.borrow_mut().insert(key, value);
+                Ok(())
+            }
+            LValue::DollarExpr(index) => {
+                let index = self.eval(index)?;
+                self.set_field(index, value)
+            }
+        }
+    }
+
+    // the program has a bounded number of /ere/ literals, which are kept for good
+    fn literal_regex(&mut self, ere: &str) -> EvalResult<Rc<Ere>> {
+        if let Some(regex) = self.regexes.get(ere) {
+            return Ok(regex.clone());
+        }
+        let regex = Rc::new(ere::compile(ere).map_err(RuntimeError)?);
+        self.regexes.insert(ere.to_string(), regex.clone());
+        Ok(regex)
+    }
+
+    // a dynamic regex like $0 ~ $1 can be different for every record, only the last
+    // ones are kept
+    fn compile_regex(&mut self, ere: &str) -> EvalResult<Rc<Ere>> {
+        if let Some(regex) = self.regexes.get(ere).or_else(|| self.dynamic_regexes.get(ere)) {
+            return Ok(regex.clone());
+        }
+        let regex = Rc::new(ere::compile(ere).map_err(RuntimeError)?);
+        if self.dynamic_regexes.len() >= DYNAMIC_REGEXES {
+            self.dynamic_regexes.clear();
+        }
+        self.dynamic_regexes.insert(ere.to_string(), regex.clone());
+        Ok(regex)
+    }
+
+    // a regex operand is either /ere/ or any expression whose string value is used as one
+    fn regex_operand(&mut self, expr: &Expr) -> EvalResult<Value> {
+        match expr {
+            Expr::Ere(ere) => {
+                self.literal_regex(ere)?;
+                Ok(Value::String(ere.clone()))
+            }
+            expr => self.eval(expr),
+        }
+    }
+
+    fn regex(&mut self, ere: &Value) -> EvalResult<Rc<Ere>> {
+        let ere = self.to_string(ere);
+        self.compile_regex(&ere)
+    }
+
+    // text ~ ere
+    pub fn is_match(&mut self, text: &Value, ere: &Value) -> EvalResult<bool> {
+        let text = self.to_string(text);
+        Ok(self.regex(ere)?.is_match(&text))
+    }
+
+    // /ere/ on its own matches $0
+    pub fn matches_record(&mut self, ere: &str) -> EvalResult<bool> {
+        Ok(self.literal_regex(ere)?.is_match(self.record.text()))
+    }
+
+    pub fn eval(&mut self, expr: &Expr) -> EvalResult<Value> {
+        match expr {
+            Expr::Number(number) => Ok(Value::Number(*number)),
+            Expr::String(text) => Ok(Value::String(text.clone())),
+            Expr::Ere(ere) => Ok(Value::Number(self.matches_record(ere)? as i32 as f64)),
+            Expr::LValue(lvalue) => self.get_lvalue(lvalue),
+            Expr::Grouping(expr) => self.eval(expr),
+            Expr::Unary(operator, operand) => Ok(unary(operator, &self.eval(operand)?)),
+            Expr::Binary(left, BinaryOperator::And, right) => {
+                let result = self.eval(left)?.to_bool() && self.eval(right)?.to_bool();
+                Ok(Value::Number(result as i32 as f64))
+            }
+            Expr::Binary(left, BinaryOperator::Or, right) => {
+                let result = self.eval(left)?.to_bool() || self.eval(right)?.to_bool();
+                Ok(Value::Number(result as i32 as f64))
+            }
+            Expr::Binary(left, operator @ (BinaryOperator::Match | BinaryOperator::NoMatch), right) => {
+                let left = self.eval(left)?;
+                let right = self.regex_operand(right)?;
+                let result = self.is_match(&left, &right)? == (*operator == BinaryOperator::Match);
+                Ok(Value::Number(result as i32 as f64))
+            }
+            Expr::Binary(left, operator, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                self.binary(&left, operator, &right)
+            }
+            Expr::Conditional(condition, then_branch, else_branch) => {
+                if self.eval(condition)?.to_bool() {
+                    self.eval(then_branch)
+                } else {
+                    self.eval(else_branch)
+                }
+            }
+            Expr::In(subscript, array) => {
+                let key = self.subscript(std::slice::from_ref(subscript.as_ref()))?;
+                let exists = self.get_array(array)?.borrow().contains_key(&key);
+                Ok(Value::Number(exists as i32 as f64))
+            }
+            Expr::BracketMultipleExprListBracket(subscripts, array) => {
+                let key = self.subscript(subscripts)?;
+                let exists = self.get_array(array)?.borrow().contains_key(&key);
+                Ok(Value::Number(exists as i32 as f64))
+            }
+            Expr::Assign(lvalue, value) => {
+                // the subscript or field index before the value, as the compiled code does
+                let lvalue = self.resolve_lvalue(lvalue)?;
+                let value = self.eval(value)?;
+                self.assign(&lvalue, value.clone())?;
+                Ok(value)
+            }
+            Expr::CompoundAssign(lvalue, operator, value) => {
+                let lvalue = self.resolve_lvalue(lvalue)?;
+                let current = self.get_lvalue(&lvalue)?;
+                let value = self.eval(value)?;
+                let result = self.binary(&current, operator, &value)?;
+                self.assign(&lvalue, result.clone())?;
+                Ok(result)
+            }
+            Expr::LValueIncr(lvalue) => self.increment(lvalue, 1.0, false),
+            Expr::LValueDecr(lvalue) => self.increment(lvalue, -1.0, false),
+            Expr::IncrLValue(lvalue) => self.increment(lvalue, 1.0, true),
+            Expr::DecrLValue(lvalue) => self.increment(lvalue, -1.0, true),
+            Expr::FuncCall(name, args) => self.call_function(name, args),
+            Expr::BuiltinFuncCall(builtin, args) => self.call_builtin(builtin, args),
+            Expr::InputFunction(function) => self.getline(function),
+        }
+    }
+
+    pub fn binary(&self, left: &Value, operator: &BinaryOperator, right: &Value) -> EvalResult<Value> {
+        let (l, r) = (left.to_number(), right.to_number());
+        let result = match operator {
+            BinaryOperator::RaiseTo => l.powf(r),
+            BinaryOperator::Multiply => l * r,
+            BinaryOperator::Divide => {
+                if r == 0.0 {
+                    return error("division by zero".to_string());
+                }
+                l / r
+            }
+            BinaryOperator::Modulus => {
+                if r == 0.0 {
+                    return error("division by zero in %".to_string());
+                }
+                l % r
+            }
+            BinaryOperator::Add => l + r,
+            BinaryOperator::Substract => l - r,
+            BinaryOperator::Concat => {
+                return Ok(Value::String(self.to_string(left) + &self.to_string(right)))
+            }
+            comparison => {
+                let ordering = left.compare(right, &self.convfmt());
+                let result = match comparison {
+                    BinaryOperator::LessThan => ordering == Ordering::Less,
+                    BinaryOperator::Le => ordering != Ordering::Greater,
+                    BinaryOperator::Ne => ordering != Ordering::Equal,
+                    BinaryOperator::Eq => ordering == Ordering::Equal,
+                    BinaryOperator::GreaterThan => ordering == Ordering::Greater,
+                    BinaryOperator::Ge => ordering != Ordering::Less,
+                    _ => unreachable!("logical and match operators are handled in eval"),
+                };
+                result as i32 as f64
+            }
+        };
+        Ok(Value::Number(result))
+    }
+
+    fn call_builtin(&mut self, builtin: &BuiltinFuncName, args: &[Expr]) -> EvalResult<Value> {
+        check_arity(builtin, args.len())?;
+        match (builtin, args) {
+            (BuiltinFuncName::Length, [Expr::LValue(LValue::Name(name))]) => {
+                if let Some(Variable::Array(array)) = self.lookup(name) {
+                    return Ok(Value::Number(array.borrow().len() as f64));
+                }
+            }
+            (BuiltinFuncName::Sub, _) => return self.builtin_sub(args, false),
+            (BuiltinFuncName::Gsub, _) => return self.builtin_sub(args, true),
+            (BuiltinFuncName::Split, _) => return self.builtin_split(args),
+            (BuiltinFuncName::Asort, _) => return self.builtin_asort(args, false),
+            (BuiltinFuncName::Asorti, _) => return self.builtin_asort(args, true),
+            _ => {}
+        }
+        let mut values = vec![];
+        for (index, arg) in args.iter().enumerate() {
+            // the second argument of match and the first of gensub are regexes
+            let regex = matches!((builtin, index), (BuiltinFuncName::Match, 1) | (BuiltinFuncName::Gensub, 0));
+            let value = if regex {
+                self.regex_operand(arg)?
+            } else {
+                self.eval(arg)?
+            };
+            values.push(value);
+        }
+        self.builtin(builtin, values)
+    }
+
+    // the builtins which only need the values of their arguments, all but sub, gsub, split,
+    // asort and asorti
+    pub fn builtin(&mut self, builtin: &BuiltinFuncName, mut args: Vec<Value>) -> EvalResult<Value> {
+        let math = |function: fn(f64) -> f64| Ok(Value::Number(function(args[0].to_number())));
+        match builtin {
+            BuiltinFuncName::Atan2 => Ok(Value::Number(args[0].to_number().atan2(args[1].to_number()))),
+            BuiltinFuncName::Cos => math(f64::cos),
+            BuiltinFuncName::Sin => math(f64::sin),
+            BuiltinFuncName::Exp => math(f64::exp),
+            BuiltinFuncName::Log => math(f64::ln),
+            BuiltinFuncName::Sqrt => math(f64::sqrt),
+            BuiltinFuncName::Int => math(f64::trunc),
+            BuiltinFuncName::Rand => Ok(Value::Number(self.random.rand())),
+            BuiltinFuncName::Srand => {
+                let seed = args.first().map(Value::to_number);
+                Ok(Value::Number(self.random.srand(seed)))
+            }
+            BuiltinFuncName::Index => {
+                let found = builtins::index(&self.to_string(&args[0]), &self.to_string(&args[1]));
+                Ok(Value::Number(found as f64))
+            }
+            // length of $0 without an argument
+            BuiltinFuncName::Length => {
+                let length = match args.first() {
+                    None => self.record.text().chars().count(),
+                    Some(value) => self.to_string(value).chars().count(),
+                };
+                Ok(Value::Number(length as f64))
+            }
+            BuiltinFuncName::Substr => {
+                let start = args[1].to_number();
+                let length = args.get(2).map(Value::to_number);
+                Ok(Value::String(builtins::substr(&self.to_string(&args[0]), start, length)))
+            }
+            BuiltinFuncName::Tolower => Ok(Value::String(self.to_string(&args[0]).to_lowercase())),
+            BuiltinFuncName::Toupper => Ok(Value::String(self.to_string(&args[0]).to_uppercase())),
+            BuiltinFuncName::System => self.builtin_system(&args[0]),
+            BuiltinFuncName::Match => self.builtin_match(&args[0], &args[1]),
+            BuiltinFuncName::Sprintf => {
+                let format = self.to_string(&args.remove(0));
+                Ok(Value::String(sprintf(&format, &args, &self.convfmt())))
+            }
+            BuiltinFuncName::Close => {
+                let name = self.to_string(&args[0]);
+                Ok(Value::Number(self.streams.close(&name) as f64))
+            }
+            BuiltinFuncName::Fflush => self.builtin_fflush(args.first()),
+            BuiltinFuncName::Gensub => self.builtin_gensub(&args),
+            // strftime([format [, timestamp [, utc]]])
+            BuiltinFuncName::Strftime => {
+                let format = match args.first() {
+                    Some(format) => self.to_string(format),
+                    None => self.procinfo("strftime").unwrap_or(gawk::DEFAULT_TIME_FORMAT.to_string()),
+                };
+                let timestamp = args.get(1).map_or_else(gawk::systime, Value::to_number);
+                let utc = args.get(2).is_some_and(Value::to_bool);
+                Ok(Value::String(gawk::strftime(&format, timestamp, utc)))
+            }
+            BuiltinFuncName::Systime => Ok(Value::Number(gawk::systime())),
+            BuiltinFuncName::Sub
+            | BuiltinFuncName::Gsub
+            | BuiltinFuncName::Split
+            | BuiltinFuncName::Asort
+            | BuiltinFuncName::Asorti => {
+                unreachable!("sub, gsub, split, asort and asorti change their arguments")
+            }
+        }
+    }
+
+    // runs the command with sh -c after flushing the output, returns its exit status
+    fn builtin_system(&mut self, command: &Value) -> EvalResult<Value> {
+        let command = self.to_string(command);
+        self.output.flush()?;
+        self.streams.flush_all()?;
+        let status = match std::process::Command::new("sh").arg("-c").arg(&command).status() {
+            Ok(status) => exit_status(status),
+            Err(e) => return error(format!("can't run {}: {}", command, e)),
+        };
+        Ok(Value::Number(status as f64))
+    }
+
+    // 1 when a record was read, 0 at the end of the input and -1 when it can't be read
+    fn getline(&mut self, function: &InputFunction) -> EvalResult<Value> {
+        let (get, source) = match function {
+            InputFunction::SimpleGet(get) => (get, None),
+            InputFunction::SimpleGetLessThanExpr(get, file) => {
+                (get, Some((InputKind::File, self.eval(file)?)))
+            }
+            InputFunction::ExprBarSimpleGet(command, get) => {
+                (get, Some((InputKind::Command, self.eval(command)?)))
+            }
+        };
+        let text = match self.read_line(source)? {
+            Ok(Some(text)) => text,
+            Ok(None) => return Ok(Value::Number(0.0)),
+            Err(_) => return Ok(Value::Number(-1.0)),
+        };
+        match get {
+            SimpleGet::Getline => self.set_record(text)?,
+            SimpleGet::GetlineLValue(lvalue) => self.assign(lvalue, Value::from_input(text))?,
+        }
+        Ok(Value::Number(1.0))
+    }
+
+    // the next record for getline, of the main input or of the file or command named by `source`,
+    // the inner error is for a file or command which can't be read
+    pub fn read_line(&mut self, source: Option<(InputKind, Value)>) -> EvalResult<io::Result<Option<String>>> {
+        let (kind, name) = match source {
+            None => return Ok(Ok(self.next_record()?)),
+            Some((kind, name)) => (kind, self.to_string(&name)),
+        };
+        // the command may write to stdout too, what was printed before must come first
+        if kind == InputKind::Command && !self.streams.is_open(&name) {
+            self.output.flush()?;
+        }
+        let rs = self.record_separator()?;
+        let text = self.streams.read(kind, &name, &rs);
+        // only records read from commands are counted in NR
+        if kind == InputKind::Command && matches!(text, Ok(Some(_))) {
+            self.increment_global("NR");
+        }
+        Ok(text)
+    }
+
+    // fflush() flushes every output, fflush(name) the file or command called name
+    fn builtin_fflush(&mut self, name: Option<&Value>) -> EvalResult<Value> {
+        let name = match name {
+            Some(name) => self.to_string(name),
+            None => {
+                self.output.flush()?;
+                self.streams.flush_all()?;
+                return Ok(Value::Number(0.0));
+            }
+        };
+        let status = match name.as_str() {
+            "/dev/stdout" => {
+                self.output.flush()?;
+                0
+            }
+            "/dev/stderr" => 0,
+            name => self.streams.flush(name),
+        };
+        Ok(Value::Number(status as f64))
+    }
+
+    // match(s, ere) sets RSTART and RLENGTH, positions count characters from 1
+    fn builtin_match(&mut self, text: &Value, ere: &Value) -> EvalResult<Value> {
+        let text = self.to_string(text);
+        let (start, length) = match self.regex(ere)?.find(&text) {
+            Some(found) => (
+                text[..found.start].chars().count() as f64 + 1.0,
+                text[found].chars().count() as f64,
+            ),
+            None => (0.0, -1.0),
+        };
+        self.set_global("RSTART", Value::Number(start));
+        self.set_global("RLENGTH", Value::Number(length));
+        Ok(Value::Number(start))
+    }
+
+    // sub(ere, repl[, target]) and gsub, the target defaults to $0
+    fn builtin_sub(&mut self, args: &[Expr], global: bool) -> EvalResult<Value> {
+        let ere = self.regex_operand(&args[0])?;
+        let replacement = self.eval(&args[1])?;
+        let record = LValue::DollarExpr(Box::new(Expr::Number(0.0)));
+        let target = match args.get(2) {
+            Some(Expr::LValue(lvalue)) => lvalue,
+            Some(arg) => return error(format!("sub and gsub need a variable to change, got {:?}", arg)),
+            None => &record,
+        };
+        let value = self.get_lvalue(target)?;
+        let (count, result) = self.replace(&ere, &replacement, &value, global)?;
+        if count > 0 {
+            self.assign(target, Value::String(result))?;
+        }
+        Ok(Value::Number(count as f64))
+    }
+
+    // the number of matches of `ere` replaced in `text` and the text after the replacements
+    pub fn replace(
+        &mut self,
+        ere: &Value,
+        replacement: &Value,
+        text: &Value,
+        global: bool,
+    ) -> EvalResult<(usize, String)> {
+        let regex = self.regex(ere)?;
+        let replacement = self.to_string(replacement);
+        let text = self.to_string(text);
+        let mut result = String::new();
+        let mut last = 0;
+        let mut count = 0;
+        for found in regex.find_iter(text.as_bytes()) {
+            result.push_str(&text[last..found.start]);
+            result.push_str(&substitute(&replacement, &text[found.clone()]));
+            last = found.end;
+            count += 1;
+            if !global {
+                break;
+            }
+        }
+        result.push_str(&text[last..]);
+        Ok((count, result))
+    }
+
+    fn procinfo(&self, key: &str) -> Option<String> {
+        match self.globals.get("PROCINFO") {
+            Some(Variable::Array(procinfo)) => procinfo.borrow().get(key).map(|value| self.to_string(value)),
+            _ => None,
+        }
+    }
+
+    // gensub(ere, replacement, how[, target]) returns the target, $0 by default, with
+    // every match replaced when how starts with g or G and else the how-th one
+    fn builtin_gensub(&mut self, args: &[Value]) -> EvalResult<Value> {
+        let regex = self.regex(&args[0])?;
+        let replacement = self.to_string(&args[1]);
+        let how = self.to_string(&args[2]);
+        let global = how.starts_with(['g', 'G']);
+        let nth = (args[2].to_number() as usize).max(1);
+        let text = match args.get(3) {
+            Some(target) => self.to_string(target),
+            None => self.record.text().to_string(),
+        };
+        let mut result = String::new();
+        let mut last = 0;
+        for (index, found) in regex.find_iter(text.as_bytes()).enumerate() {
+            if !global && index + 1 != nth {
+                continue;
+            }
+            result.push_str(&text[last..found.start]);
+            let groups = regex.groups(&text, found.clone());
+            result.push_str(&gawk::expand_replacement(&replacement, &groups));
+            last = found.end;
+        }
+        result.push_str(&text[last..]);
+        Ok(Value::String(result))
+    }
+
+    // asort(source[, dest]) puts the values of source, asorti its indices, sorted into
+    // dest from 1, or into source itself, returns how many there are
+    fn builtin_asort(&mut self, args: &[Expr], indices: bool) -> EvalResult<Value> {
+        let mut arrays = vec![];
+        for arg in args {
+            match arg {
+                Expr::LValue(LValue::Name(name)) => arrays.push(self.get_array(name)?),
+                arg => return error(format!("asort and asorti need arrays, got {:?}", arg)),
+            }
+        }
+        let convfmt = self.convfmt().to_string();
+        let mut elements: Vec<Value> = if indices {
+            let mut keys: Vec<String> = arrays[0].borrow().keys().cloned().collect();
+            keys.sort();
+            keys.into_iter().map(Value::String).collect()
+        } else {
+            arrays[0].borrow().values().cloned().collect()
+        };
+        if !indices {
+            elements.sort_by(|a, b| gawk::compare_values(a, b, &convfmt));
+        }
+        let count = elements.len();
+        let mut dest = arrays.last().unwrap().borrow_mut();
+        dest.clear();
+        for (index, element) in elements.into_iter().enumerate() {
+            dest.insert((index + 1).to_string(), element);
+        }
+        Ok(Value::Number(count as f64))
+    }
+
+    // split(s, a[, fs]) with FS rules for fs, /ere/ always splits on the regex
+    fn builtin_split(&mut self, args: &[Expr]) -> EvalResult<Value> {
+        let text = self.eval(&args[0])?;
+        let array = match &args[1] {
+            Expr::LValue(LValue::Name(name)) => self.get_array(name)?,
+            arg => return error(format!("split needs an array, got {:?}", arg)),
+        };
+        let separator = match args.get(2) {
+            None => SplitSeparator::Fs,
+            Some(Expr::Ere(ere)) => SplitSeparator::Ere(ere.clone()),
+            Some(fs) => SplitSeparator::Value(self.eval(fs)?),
+        };
+        self.split(&text, &array, separator)
+    }
+
+    // fills `array` with the fields of `text` from 1, returns how many there are
+    pub fn split(
+        &mut self,
+        text: &Value,
+        array: &RefCell<Array>,
+        separator: SplitSeparator,
+    ) -> EvalResult<Value> {
+        let text = self.to_string(text);
+        let fs = match &separator {
+            SplitSeparator::Fs => self.global_string("FS"),
+            SplitSeparator::Ere(ere) => ere.clone(),
+            SplitSeparator::Value(fs) => self.to_string(fs),
+        };
+        let fields = match separator {
+            SplitSeparator::Ere(_) => split_regex(&text, &*self.literal_regex(&fs)?),
+            _ if fs.chars().count() <= 1 => FieldSeparator::new(&fs, false).map_err(RuntimeError)?.split(&text),
+            _ => split_regex(&text, &*self.compile_regex(&fs)?),
+        };
+        let mut array = array.borrow_mut();
+        array.clear();
+        for (index, field) in fields.iter().enumerate() {
+            array.insert((index + 1).to_string(), Value::from_input(field.clone()));
+        }
+        Ok(Value::Number(fields.len() as f64))
+    }
+
+    fn call_function(&mut self, name: &str, args: &[Expr]) -> EvalResult<Value> {
+        let function = match self.functions.get(name) {
+            Some(function) => function.clone(),
+            None => return error(format!("function {} never defined", name)),
+        };
+        if args.len() > function.params.len() {
+            return error(format!(
+                "function {} called with {} args, accepts only {}",
+                name,
+                args.len(),
+                function.params.len()
+            ));
+        }
+
+        let mut frame = HashMap::new();
+        // the untyped variables passed, by parameter
+        let mut untyped = vec![];
+        for (param, arg) in function.params.iter().zip(args) {
+            // arrays are passed by reference, everything else by value
+            let variable = match arg {
+                Expr::LValue(LValue::Name(arg_name)) if arg_name != "NF" => match self.lookup(arg_name) {
+                    Some(Variable::Array(array)) => Variable::Array(array.clone()),
+                    None | Some(Variable::Value(Value::Uninitialized)) => {
+                        untyped.push((param, arg_name));
+                        Variable::Value(Value::Uninitialized)
+                    }
+                    Some(Variable::Value(value)) => Variable::Value(value.clone()),
+                },
+                arg => Variable::Value(self.eval(arg)?),
+            };
+            frame.insert(param.clone(), variable);
+        }
+        for param in function.params.iter().skip(args.len()) {
+            frame.insert(param.clone(), Variable::Value(Value::Uninitialized));
+        }
+        let (value, mut frame) = self.run_function(&function, frame)?;
+        // an untyped variable becomes the array the function made of it
+        for (param, name) in untyped {
+            if let Some(Variable::Array(array)) = frame.remove(param) {
+                let variable = self.lookup_mut(name);
+                if let Variable::Value(Value::Uninitialized) = variable {
+                    *variable = Variable::Array(array);
+                }
+            }
+        }
+        Ok(value)
+    }
+
+    // calls `function` with values for its first parameters, the rest are uninitialized
+    fn call_with_values(&mut self, function: &FunctionDeclaration, values: Vec<Value>) -> EvalResult<Value> {
+        let mut frame = HashMap::new();
+        let mut values = values.into_iter();
+        for param in &function.params {
+            let value = values.next().unwrap_or(Value::Uninitialized);
+            frame.insert(param.clone(), Variable::Value(value));
+        }
+        self.run_function(function, frame).map(|(value, _)| value)
+    }
+
+    // the value the function returned and its variables when it did
+    fn run_function(
+        &mut self,
+        function: &FunctionDeclaration,
+        frame: HashMap<String, Variable>,
+    ) -> EvalResult<(Value, HashMap<String, Variable>)> {
+        let started = self.profile.is_some().then(Instant::now);
+        self.frames.push(frame);
+        let flow = self.exec_block(&function.body);
+        let frame = self.frames.pop().expect("the frame of the call");
+        if let (Some(profile), Some(started)) = (self.profile.as_mut(), started) {
+            profile.call(&function.name, started.elapsed());
+        }
+        match flow? {
+            Flow::Return(value) => Ok((value, frame)),
+            Flow::Normal => Ok((Value::Uninitialized, frame)),
+            Flow::Break | Flow::Continue => {
+                error("break or continue outside a loop".to_string())
+            }
+        }
+    }
+}
+
+pub fn unary(operator: &UnaryOperator, operand: &Value) -> Value {
+    let result = match operator {
+        UnaryOperator::Plus => operand.to_number(),
+        UnaryOperator::Minus => -operand.to_number(),
+        UnaryOperator::Invert => {
+            if operand.to_bool() {
+                0.0
+            } else {
+                1.0
+            }
+        }
+    };
+    Value::Number(result)
+}
+
+// the replacement of sub and gsub: & is the matched text, \\& a literal &
+fn substitute(replacement: &str, matched: &str) -> String {
+    let mut result = String::new();
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if matches!(chars.peek(), Some('&') | Some('\\')) => {
+                result.push(chars.next().unwrap());
+            }
+            '&' => result.push_str(matched),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+// name and value of a var=value operand, None for anything else
+pub fn split_assignment(text: &str) -> Option<(&str, &str)> {
+    let (name, value) = text.split_once('=')?;
+    let mut chars = name.chars();
+    let valid_name = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid_name.then_some((name, value))
+}
+
+// output which can still be read after being handed to an Interpreter
+#[derive(Clone, Default)]
+pub struct SharedOutput(pub Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// runs `source` over `input` given on stdin, returns what was printed
+#[cfg(test)]
+pub fn run_source(source: &str, input: &str) -> Result<String, RuntimeError> {
+    run_source_with(source, input, false)
+}
+
+// the same, compiled to bytecode first when `compiled`
+#[cfg(test)]
+pub fn run_source_with(source: &str, input: &str, compiled: bool) -> Result<String, RuntimeError> {
+    let tokens = crate::lexer::tokenize(source.to_string()).expect("program should lex");
+    let program = crate::parser::parse(tokens).expect("program should parse");
+    let output = SharedOutput::default();
+    let mut interpreter = Interpreter::new(&program, Box::new(output.clone()));
+    if compiled {
+        interpreter.set_bytecode(crate::bytecode::compile(&program));
+    }
+    interpreter.set_stdin(Box::new(io::Cursor::new(input.to_string())));
+    interpreter.run(&program)?;
+    let printed = output.0.borrow().clone();
+    Ok(String::from_utf8(printed).unwrap())
+}
+
+#[cfg(test)]
+fn run_mock(name: &str, input: &str) -> String {
+    let source = std::fs::read_to_string(format!("./tests/mocks/{}.awk", name)).expect("Unable to read mock");
+    run_source(&source, input).unwrap()
+}
+
+#[test]
+fn test_sample_1() {
+    assert_eq!(run_mock("sample_1", "a b\nc d\n"), "a\nc\n");
+}
+
+#[test]
+fn test_sample_2() {
+    assert_eq!(run_mock("sample_2", "1\n2\n3\n"), "Total lines: 3\n");
+}
+
+#[test]
+fn test_sample_3() {
+    assert_eq!(run_mock("sample_3", "1\n2\n3\n"), "Sum: 6\n");
+}
+
+#[test]
+fn test_sample_4() {
+    assert_eq!(
+        run_mock("sample_4", "4\n7\n"),
+        "Number is greater than 10\nDouble of 4 is 8\nNumber is less than or equal to 10\nDouble of 7 is 14\n"
+    );
+}
+
+#[test]
+fn test_sample_5() {
+    assert_eq!(
+        run_mock("sample_5", "a\nb\n"),
+        "Processing started...\nProcessing finished.\nTotal lines processed: 14\n"
+    );
+}
+
+#[test]
+fn test_sample_6() {
+    assert_eq!(
+        run_mock("sample_6", "a b\nc d e\n"),
+        "# not a comment 2\n# not a comment 3\nWords: 5\n"
+    );
+}
+
+#[test]
+fn test_compound_assignment_and_increments() {
+    let source = "{ x += $1; y -= $1; z = 2; z ^= $1; $1 *= 10; a[$2]++; print x, y, z, $0, a[$2]++, a[$2], ++a[$2], --a[$2] }";
+    assert_eq!(run_source(source, "3 k\n").unwrap(), "3 -3 8 30 k 1 2 3 2\n");
+    let source = "BEGIN { i = 1; arr[i++] += 5; print i, arr[1]; $0 = \"a b c\"; i = 1; $i++; print $1, i; x = 7; x /= 2; x %= 2; print x }";
+    assert_eq!(run_source(source, "").unwrap(), "2 5\n1 1\n1.5\n");
+}
+
+#[test]
+fn test_fields_and_variables() {
+    let output = run_source("{ $2 = \"x\"; print; print NF, $NF }", "a b c\n").unwrap();
+    assert_eq!(output, "a x c\n3 c\n");
+    let output = run_source("{ NF = 2; print } END { print NR }", "a b c\n").unwrap();
+    assert_eq!(output, "a b\n1\n");
+}
+
+#[test]
+fn test_patterns() {
+    let output = run_source("$1 > 1 { print \"big\", $1 }\nNR == 1", "1\n2\n").unwrap();
+    assert_eq!(output, "1\nbig 2\n");
+    let output = run_source("$1 == 2, $1 == 3 { print }", "1\n2\n3\n4\n").unwrap();
+    assert_eq!(output, "2\n3\n");
+}
+
+#[test]
+fn test_arrays_and_functions() {
+    let source = "function fill(arr, n,    i) { for (i = 1; i <= n; i = i + 1) arr[i] = i * i }
+BEGIN { squares[1] = 0; fill(squares, 3); for (k in squares) print k, squares[k]; if ((2) in squares) print \"yes\"; delete squares[2]; print (2 in squares) }";
+    assert_eq!(run_source(source, "").unwrap(), "1 1\n2 4\n3 9\nyes\n0\n");
+}
+
+#[test]
+fn test_multidimensional_arrays() {
+    let source = "{ cell[NR, 1] = $1; cell[NR, 2] = $2 }
+END {
+    print ((2, 1) in cell), ((1, 3) in cell), cell[2, 2]
+    for (k in cell) { split(k, at, SUBSEP); if (at[2] == 1) first = first cell[k] }
+    print first
+    delete cell[1, 1]; print length(cell); delete cell; print length(cell)
+}";
+    assert_eq!(run_source(source, "a b\nc d\n").unwrap(), "1 0 d\nac\n3\n0\n");
+    // the untyped variable becomes the array the function makes of it, also passed on
+    let source = "function add(a, k) { a[k]++ } function both(a) { add(a, \"x\"); add(a, \"y\") }
+BEGIN { both(seen); add(seen, \"x\"); print length(seen), seen[\"x\"]; add(n, 1); print n[1] }";
+    assert_eq!(run_source(source, "").unwrap(), "2 2\n1\n");
+    assert!(run_source("BEGIN { x = 1; delete x }", "").is_err());
+}
+
+#[test]
+fn test_control_flow() {
+    let source = "{ if ($1 == \"skip\") next; print } END { exit 3; print \"unreachable\" }";
+    assert_eq!(run_source(source, "a\nskip\nb\n").unwrap(), "a\nb\n");
+    let source = "BEGIN { while (1) { i = i + 1; if (i > 3) break; if (i == 2) continue; print i } do print \"once\"; while (0) }";
+    assert_eq!(run_source(source, "").unwrap(), "1\n3\nonce\n");
+}
+
+#[test]
+fn test_float_literals_and_escapes() {
+    let source = "BEGIN { print 0.1 + 0.2, 1e3, 2.5 * 2, 0x10; print \"a\\tb\\\"c\\\"\\041\" }";
+    assert_eq!(run_source(source, "").unwrap(), "0.3 1000 5 16\na\tb\"c\"!\n");
+    let source = "BEGIN { x = 1 + \\\n 2; print x, \"multi\\\nline\" }";
+    assert_eq!(run_source(source, "").unwrap(), "3 multiline\n");
+}
+
+#[test]
+fn test_printf_and_number_output() {
+    let source = "BEGIN { printf \"%s=%d \", \"x\", 7 / 2; print 1 / 4, 10 / 2, 1 / 3; x = 3 \"\"; print x + 1 }";
+    assert_eq!(run_source(source, "").unwrap(), "x=3 0.25 5 0.333333\n4\n");
+}
+
+#[test]
+fn test_runtime_errors() {
+    assert_eq!(
+        run_source("BEGIN { print 1 / 0 }", ""),
+        Err(RuntimeError("division by zero".to_string()))
+    );
+    assert_eq!(
+        run_source("BEGIN { f() }", ""),
+        Err(RuntimeError("function f never defined".to_string()))
+    );
+    for compiled in [false, true] {
+        assert_eq!(
+            run_source_with("BEGIN { $1e10 = 1 }", "", compiled),
+            Err(RuntimeError("field index 10000000000 too large".to_string()))
+        );
+        assert_eq!(
+            run_source_with("BEGIN { NF = 1e12 }", "", compiled),
+            Err(RuntimeError("field index 1000000000000 too large".to_string()))
+        );
+        assert_eq!(run_source_with("BEGIN { NF = 3; $5 = 1; print; print NF }", "", compiled).unwrap(), "    1\n5\n");
+    }
+}
+
+#[test]
+fn test_command_line_assignments() {
+    let tokens = crate::lexer::tokenize("BEGIN { print x + 1, y } { print } END { print z }".to_string()).unwrap();
+    let program = crate::parser::parse(tokens).unwrap();
+    let output = SharedOutput::default();
+    let mut interpreter = Interpreter::new(&program, Box::new(output.clone()));
+    assert!(interpreter.command_line_assignment("x=41"));
+    assert!(interpreter.command_line_assignment("y=a\\tb"));
+    assert!(!interpreter.command_line_assignment("1x=2"));
+    assert!(!interpreter.command_line_assignment("file.txt"));
+    interpreter.set_argv(&["awk".to_string(), "z=done".to_string(), "-".to_string()]);
+    interpreter.set_stdin(Box::new(io::Cursor::new("line\n")));
+    interpreter.run(&program).unwrap();
+    let printed = String::from_utf8(output.0.borrow().clone()).unwrap();
+    assert_eq!(printed, "42 a\tb\nline\ndone\n");
+}
+
+#[test]
+fn test_regular_expressions() {
+    let source = "/^err/ { print \"error:\", $2 } $2 ~ \"[0-9]+\" && $2 !~ /^1/ { print \"number\", $2 }";
+    assert_eq!(
+        run_source(source, "error 42\nwarn 7\nerror x\nok 12\n").unwrap(),
+        "error: 42\nnumber 42\nnumber 7\nerror: x\n"
+    );
+    let source = "{ n = gsub(/o/, \"[&]\"); print n, $0; sub(/l+/, \"L\", $1); print $1 }";
+    assert_eq!(run_source(source, "hello world\n").unwrap(), "2 hell[o] w[o]rld\nheL[o]\n");
+    let source = "BEGIN { print match(\"foobar\", /o+b/), RSTART, RLENGTH; print match(\"x\", /y/), RLENGTH }";
+    assert_eq!(run_source(source, "").unwrap(), "2 2 3\n0 -1\n");
+    let source = "BEGIN { n = split(\"a1b22c\", parts, /[0-9]+/); print n, parts[1] parts[2] parts[3]; print split(\"x:y\", parts, \":\"), parts[2] }";
+    assert_eq!(run_source(source, "").unwrap(), "3 abc\n2 y\n");
+    // the leftmost-longest match, not the first alternative which matches
+    let source = "BEGIN { print match(\"abcd\", /ab|abcd/), RLENGTH; s = \"xabcdx\"; sub(/ab|abcd/, \"Y\", s); print s
+t = \"abcd ab\"; print gsub(/ab|abcd/, \"<&>\", t), t; print split(\"1abcd2ab3\", parts, /ab|abcd/), parts[2] parts[3] }";
+    assert_eq!(run_source(source, "").unwrap(), "1 4\nxYx\n2 <abcd> <ab>\n3 23\n");
+    let source = "BEGIN { FS = \"x|xy\"; RS = \"ab|abcd\" } { print $2, $0 }";
+    assert_eq!(run_source(source, "1xy2abcd3x4").unwrap(), "2 1xy2\n4 3x4\n");
+}
+
+#[test]
+fn test_regex_cache() {
+    let source = "$0 ~ $1 && /x/ { n++ } { sub(\"^\" NR, \"\"); split($0, parts, /y/) } END { print n }";
+    let input: String = (0..100).map(|i| format!("{}x{} y\n", i, i)).collect();
+    for compiled in [false, true] {
+        let program = crate::parser::parse(crate::lexer::tokenize(source.to_string()).unwrap()).unwrap();
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::new(&program, Box::new(output.clone()));
+        if compiled {
+            interpreter.set_bytecode(crate::bytecode::compile(&program));
+        }
+        interpreter.set_stdin(Box::new(io::Cursor::new(input.clone())));
+        interpreter.run(&program).unwrap();
+        assert_eq!(*output.0.borrow(), b"100\n");
+        // the literals stay, the 200 other regexes did not pile up
+        let mut literals: Vec<&String> = interpreter.regexes.keys().collect();
+        literals.sort();
+        assert_eq!(literals, ["x", "y"]);
+        assert!(interpreter.dynamic_regexes.len() <= DYNAMIC_REGEXES);
+    }
+}
+
+#[test]
+fn test_printf_and_sprintf() {
+    let source = "{ printf \"%-6s|%5.2f|%03d\\n\", $1, $2, NR; s = sprintf(\"%*s\", 4, $1); print s \"!\" }";
+    assert_eq!(
+        run_source(source, "ab 3.14159\nxyz 2\n").unwrap(),
+        "ab    | 3.14|001\n  ab!\nxyz   | 2.00|002\n xyz!\n"
+    );
+    let source = "BEGIN { printf(\"%c%c %x\\n\", 72, \"io\", 255); print sprintf(\"%e\", 12345) }";
+    assert_eq!(run_source(source, "").unwrap(), "Hi ff\n1.234500e+04\n");
+}
+
+#[test]
+fn test_output_redirection() {
+    let directory = std::env::temp_dir().join(format!("awk-redirection-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = |name: &str| directory.join(name).to_string_lossy().to_string();
+    std::fs::write(path("b.txt"), "old\n").unwrap();
+
+    let source = format!(
+        "{{ print $2 > (\"{dir}/\" $1 \".txt\") }}
+        END {{
+            close(\"{dir}/a.txt\"); print \"more\" >> \"{dir}/a.txt\"
+            printf \"%s\\n\", \"piped\" | \"cat > {dir}/piped.txt\"
+            print close(\"cat > {dir}/piped.txt\"), close(\"exit 3\"), close(\"not open\")
+            print \"x\" | \"cat > /dev/null; exit 3\"; print close(\"cat > /dev/null; exit 3\"), fflush()
+        }}",
+        dir = directory.to_string_lossy()
+    );
+    let printed = run_source(&source, "a 1\nb 2\na 3\n").unwrap();
+    assert_eq!(printed, "0 -1 -1\n3 0\n");
+    assert_eq!(std::fs::read_to_string(path("a.txt")).unwrap(), "1\n3\nmore\n");
+    assert_eq!(std::fs::read_to_string(path("b.txt")).unwrap(), "2\n");
+    assert_eq!(std::fs::read_to_string(path("piped.txt")).unwrap(), "piped\n");
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_getline() {
+    // plain getline moves the main input along, NR and FNR included
+    let source = "NR == 1 { getline; print NR, FNR, NF, $0; getline line; print NR, line, $0 } END { print getline, NR }";
+    assert_eq!(run_source(source, "a\nb c\nd\ne\n").unwrap(), "2 2 2 b c\n3 d b c\n0 4\n");
+
+    let path = std::env::temp_dir().join(format!("awk-getline-{}", std::process::id()));
+    std::fs::write(&path, "x y\nz\n").unwrap();
+    let source = format!(
+        "BEGIN {{
+            file = \"{file}\"
+            while ((getline < file) > 0) print NR, NF, $0
+            print (getline line < file), close(file), (getline line < file), line, NR
+            print (getline < \"/no/such/file\")
+            while ((\"echo one; echo two three\" | getline) > 0) print NR, NF, $0
+            \"echo word\" | getline w; print w, NR, close(\"echo word\")
+        }}",
+        file = path.to_string_lossy()
+    );
+    assert_eq!(
+        run_source(&source, "").unwrap(),
+        "0 2 x y\n0 1 z\n0 0 1 x y 0\n-1\n1 1 one\n2 2 two three\nword 3 0\n"
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_string_and_math_builtins() {
+    let source = "{ print length, length($2), length() } END { n = split(\"a b c\", parts); print n, length(parts) }";
+    assert_eq!(run_source(source, "héllo wörld\n").unwrap(), "11 5 11\n3 3\n");
+    let source = "BEGIN { s = \"Hello, World\"; print substr(s, 8), substr(s, 0, 3), substr(s, 8, 100) \"|\"; print index(s, \"World\"), tolower(s), toupper(s) }";
+    assert_eq!(run_source(source, "").unwrap(), "World He World|\n8 hello, world HELLO, WORLD\n");
+    let source = "BEGIN { print int(3.9), int(-3.9), sqrt(16), exp(0), log(1), sin(0), cos(0), atan2(0, -1) }";
+    assert_eq!(run_source(source, "").unwrap(), "3 -3 4 1 0 0 1 3.14159\n");
+    let source = "BEGIN { print srand(5), srand(7); x = rand(); srand(7); print (x == rand()), (x >= 0 && x < 1) }";
+    assert_eq!(run_source(source, "").unwrap(), "0 5\n1 1\n");
+    let source = "BEGIN { print system(\"exit 3\"), system(\"true\") }";
+    assert_eq!(run_source(source, "").unwrap(), "3 0\n");
+    assert!(run_source("BEGIN { substr(\"a\") }", "").is_err());
+}
+
+#[test]
+fn test_field_and_record_separators() {
+    let source = "BEGIN { FS = \"[0-9]+\" } { print NF, $2 }";
+    assert_eq!(run_source(source, "a12b3c\nx\n").unwrap(), "3 b\n1 \n");
+    let source = "BEGIN { FS = \"|\"; OFS = \"-\" } { $1 = $1; print; $5 = \"e\"; print; NF = 2; print }";
+    assert_eq!(run_source(source, "a|b|c\n").unwrap(), "a-b-c\na-b-c--e\na-b\n");
+    let source = "{ $0 = \"x y z\"; print NF, $3 }";
+    assert_eq!(run_source(source, "a\n").unwrap(), "3 z\n");
+    // changing FS only affects the next record
+    let source = "{ FS = \",\"; print $1 }";
+    assert_eq!(run_source(source, "a,b c\nd,e f\n").unwrap(), "a,b\nd\n");
+
+    let source = "BEGIN { RS = \"\" } { print NR \": \" $1 \"/\" $NF \" \" NF }";
+    assert_eq!(
+        run_source(source, "\n\nJohn Smith\n12 Main St\n\n\nJane Doe\n5 Oak Ave\n\n").unwrap(),
+        "1: John/St 5\n2: Jane/Ave 5\n"
+    );
+    let source = "BEGIN { RS = \"\"; FS = \":\" } { print NF, $2 }";
+    assert_eq!(run_source(source, "a:b\nc\n").unwrap(), "3 b\n");
+    let source = "BEGIN { RS = \"[;,]+\"; ORS = \".\" } { print } END { printf \"\\n\" }";
+    assert_eq!(run_source(source, "a;b,,c\n").unwrap(), "a.b.c\n.\n");
+}
+
+#[test]
+fn test_csv_mode() {
+    let source = "{ print NF, $2; $1 = \"x,y\"; print } END { print \"a\", \"b \\\"c\\\"\" }";
+    let program = crate::parser::parse(crate::lexer::tokenize(source.to_string()).unwrap()).unwrap();
+    let output = SharedOutput::default();
+    let mut interpreter = Interpreter::new(&program, Box::new(output.clone()));
+    interpreter.set_csv();
+    let input = "1,\"two, with comma\",3\r\nq,\"multi\nline\"\r\n";
+    interpreter.set_stdin(Box::new(io::Cursor::new(input)));
+    interpreter.run(&program).unwrap();
+    let printed = String::from_utf8(output.0.borrow().clone()).unwrap();
+    assert_eq!(
+        printed,
+        "3,\"two, with comma\"\n\"x,y\",\"two, with comma\",3\n2,\"multi\nline\"\n\"x,y\",\"multi\nline\"\na,\"b \"\"c\"\"\"\n"
+    );
+}
+
+#[test]
+fn test_json_mode() {
+    let run = |source: &str, input: &str| {
+        let program = crate::parser::parse(crate::lexer::tokenize(source.to_string()).unwrap()).unwrap();
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::new(&program, Box::new(output.clone()));
+        interpreter.set_json();
+        interpreter.command_line_assignment("key=name");
+        interpreter.set_stdin(Box::new(io::Cursor::new(input.to_string())));
+        interpreter.run(&program)?;
+        let printed = output.0.borrow().clone();
+        Ok::<String, RuntimeError>(String::from_utf8(printed).unwrap())
+    };
+    let source = "$\"level\" == \"error\" { n++; print $\"user.id\" + 1, $(\"user.\" key), $1 } END { print n }";
+    let input = "{\"level\": \"error\", \"user\": {\"id\": 41, \"name\": \"ann\"}}\n{\"level\": \"info\"}\nnot json\n";
+    assert_eq!(run(source, input).unwrap(), "42 ann {\"level\":\n1\n");
+    assert!(run("{ $\"a\" = 1 }", "{}\n").is_err());
+}
+
+#[test]
+fn test_gawk_mode() {
+    let directory = std::env::temp_dir().join(format!("awk-gawk-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let files = [("one", "a 3\nb 1\n"), ("empty", ""), ("two", "c 2\n")];
+    let mut argv = vec!["awk".to_string()];
+    for (name, text) in files {
+        let path = directory.join(name);
+        std::fs::write(&path, text).unwrap();
+        argv.push(path.to_string_lossy().to_string());
+    }
+    let run = |source: &str| {
+        let tokens = crate::lexer::tokenize_with(source.to_string(), true).expect("program should lex");
+        let program = crate::parser::parse(tokens).expect("program should parse");
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::new(&program, Box::new(output.clone()));
+        interpreter.set_gawk();
+        interpreter.set_argv(&argv);
+        interpreter.run(&program)?;
+        let printed = output.0.borrow().clone();
+        Ok::<String, RuntimeError>(String::from_utf8(printed).unwrap())
+    };
+
+    let source = "BEGINFILE { n = split(FILENAME, path, \"/\"); printf \"%s:\", path[n] }
+ENDFILE { print FNR }
+{ v[$1] = $2 }
+END {
+    PROCINFO[\"sorted_in\"] = \"@val_num_desc\"
+    for (k in v) printf \"%s \", k
+    PROCINFO[\"sorted_in\"] = \"reversed\"
+    for (k in v) printf \"%s \", k
+    print asort(v, sorted), sorted[1] sorted[3], asorti(v), v[1] v[3], 2 ** 3 ** 2
+}
+function reversed(i1, v1, i2, v2) { return i1 < i2 ? 1 : -1 }";
+    assert_eq!(run(source).unwrap(), "one:2\nempty:0\ntwo:1\na c b c b a 3 13 3 ac 512\n");
+
+    let source = "BEGIN {
+    for (i = 1; i <= 4; i++)
+        switch (i) {
+        case 1:
+            printf \"one \"
+        case /2/:
+            printf \"two \"
+            break
+        case \"3\":
+            continue
+        default:
+            print \"default\", i
+        }
+    print gensub(/(a+)(b)/, \"<\\\\2\\\\1>\", \"g\", \"aab xab\"), gensub(/o/, \"0\", 2, \"foo\")
+    $0 = \"x.y\"; print gensub(/\\./, \"&&\", \"G\"), $0, (length(PROCINFO) > 0)
+}";
+    assert_eq!(run(source).unwrap(), "one two two default 4\n<baa> x<ba> fo0\nx..y x.y 1\n");
+    assert!(run("BEGIN { PROCINFO[\"sorted_in\"] = \"nope\"; a[1]; for (k in a) ; }").is_err());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,49 @@
+use serde_json::Value as Json;
+
+use crate::value::Value;
+
+// a record which is not valid JSON has no keys, like an empty object
+pub fn parse(text: &str) -> Json {
+    serde_json::from_str(text).unwrap_or(Json::Null)
+}
+
+// "user.id" or ".user.id" walks into objects by key and into arrays by index from 0
+pub fn lookup<'a>(document: &'a Json, path: &str) -> Option<&'a Json> {
+    let path = path.strip_prefix('.').unwrap_or(path);
+    if path.is_empty() {
+        return Some(document);
+    }
+    path.split('.').try_fold(document, |json, key| match json {
+        Json::Object(object) => object.get(key),
+        Json::Array(array) => key.parse::<usize>().ok().and_then(|index| array.get(index)),
+        _ => None,
+    })
+}
+
+// strings and numbers are input, so they compare as numbers when they look like one,
+// true and false are 1 and 0, objects and arrays are their compact JSON text
+pub fn to_value(json: Option<&Json>) -> Value {
+    match json {
+        None | Some(Json::Null) => Value::Uninitialized,
+        Some(Json::Bool(true)) => Value::Number(1.0),
+        Some(Json::Bool(false)) => Value::Number(0.0),
+        Some(Json::Number(number)) => Value::StrNum(number.to_string()),
+        Some(Json::String(text)) => Value::from_input(text.clone()),
+        Some(json) => Value::String(json.to_string()),
+    }
+}
+
+#[test]
+fn test_lookup() {
+    let document = parse(r#"{"user": {"id": 42, "tags": ["a", "b"]}, "ok": true, "msg": "hi there", "n": null}"#);
+    let get = |path: &str| to_value(lookup(&document, path));
+    assert_eq!(get("user.id"), Value::StrNum("42".to_string()));
+    assert_eq!(get(".user.tags.1"), Value::String("b".to_string()));
+    assert_eq!(get("user.tags"), Value::String(r#"["a","b"]"#.to_string()));
+    assert_eq!(get("ok"), Value::Number(1.0));
+    assert_eq!(get("msg"), Value::String("hi there".to_string()));
+    assert_eq!(get("n"), Value::Uninitialized);
+    assert_eq!(get("user.missing.deeper"), Value::Uninitialized);
+    assert_eq!(get("user.tags.x"), Value::Uninitialized);
+    assert_eq!(to_value(lookup(&parse("not json"), "a")), Value::Uninitialized);
+}
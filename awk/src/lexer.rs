@@ -1,41 +1,11 @@
-use std::{char, fs};
+#[cfg(test)]
+use std::fs;
 
-use crate::tokens::Token;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::tokens::{SpannedToken, Token};
 
-fn is_delimiter_token(c: char) -> Option<Token> {
-    match c {
-        // Seperator(Seperator),
-        '{' => Some(Token::OpenCurlyBrace),
-        '}' => Some(Token::CloseCurlyBrace),
-        '(' => Some(Token::OpenBrace),
-        ')' => Some(Token::CloseBrace),
-        '[' => Some(Token::OpenSquareBrace),
-        ']' => Some(Token::CloseSquareBrace),
-        ',' => Some(Token::Comma),
-        ';' => Some(Token::SemiColon),
-        // NEWLINE
-        '\n' => Some(Token::Newline),
-        // Operator - OneCharOperator(OneCharOperator),
-        '+' => Some(Token::Add),
-        '-' => Some(Token::Substract),
-        '*' => Some(Token::Multiply),
-        '/' => Some(Token::Divide),
-        '%' => Some(Token::Modulus),
-        '^' => Some(Token::RaiseTo),
-        ':' => Some(Token::Colon),
-        '~' => Some(Token::Tilde),
-        '$' => Some(Token::Dollar),
-        '?' => Some(Token::Question),
-        // whitespace
-        ' ' => Some(Token::WhiteSpace),
-        _ => None,
-    }
-}
-
-fn deduce_partial(prev: Vec<char>) -> Option<Token> {
-    let str = prev.iter().collect::<String>();
-
-    let is_builtin = match str.as_str() {
+fn keyword(word: &str) -> Option<Token> {
+    match word {
         "BEGIN" => Some(Token::Begin),
         "END" => Some(Token::End),
         "break" => Some(Token::Break),
@@ -74,139 +44,543 @@ fn deduce_partial(prev: Vec<char>) -> Option<Token> {
         "toupper" => Some(Token::Toupper),
         "close" => Some(Token::Close),
         "system" => Some(Token::System),
+        "fflush" => Some(Token::Fflush),
         "getline" => Some(Token::Getline),
-        "||" => Some(Token::Or),
-        "&&" => Some(Token::And),
-        "!=" => Some(Token::NoMatch),
-        "==" => Some(Token::Eq),
-        "<=" => Some(Token::Le),
-        ">=" => Some(Token::Ge),
-        // Single pasangai
-        "!" => Some(Token::Invert),
-        "<" => Some(Token::LessThan),
-        ">" => Some(Token::GreaterThan),
-        "|" => Some(Token::Bar),
-        "=" => Some(Token::Equal),
         _ => None,
-    };
+    }
+}
 
-    match is_builtin {
-        Some(token) => Some(token),
-        None => {
-            if let Ok(num_parsed) = str.parse::<i64>() {
-                return Some(Token::Number(num_parsed));
-            }
+// the words gawk adds, names in POSIX awk
+fn gawk_keyword(word: &str) -> Option<Token> {
+    match word {
+        "BEGINFILE" => Some(Token::BeginFile),
+        "ENDFILE" => Some(Token::EndFile),
+        "switch" => Some(Token::Switch),
+        "case" => Some(Token::Case),
+        "default" => Some(Token::Default),
+        "gensub" => Some(Token::Gensub),
+        "asort" => Some(Token::Asort),
+        "asorti" => Some(Token::Asorti),
+        "strftime" => Some(Token::Strftime),
+        "systime" => Some(Token::Systime),
+        _ => None,
+    }
+}
+
+fn two_char_operator(first: char, second: char) -> Option<Token> {
+    match (first, second) {
+        ('|', '|') => Some(Token::Or),
+        ('&', '&') => Some(Token::And),
+        ('!', '~') => Some(Token::NoMatch),
+        ('!', '=') => Some(Token::Ne),
+        ('=', '=') => Some(Token::Eq),
+        ('<', '=') => Some(Token::Le),
+        ('>', '=') => Some(Token::Ge),
+        ('>', '>') => Some(Token::Append),
+        ('+', '+') => Some(Token::Incr),
+        ('-', '-') => Some(Token::Decr),
+        ('^', '=') => Some(Token::RaiseToAssign),
+        ('%', '=') => Some(Token::ModulusAssign),
+        ('*', '=') => Some(Token::MultiplyAssign),
+        ('+', '=') => Some(Token::AddAssign),
+        ('-', '=') => Some(Token::SubstractAssign),
+        _ => None,
+    }
+}
 
-            let identifier = str.chars().all(|c| char::is_alphabetic(c) || c == '_');
-            if identifier && !prev.is_empty() {
-                return Some(Token::Name(prev));
+fn one_char_token(c: char) -> Option<Token> {
+    match c {
+        // Seperator(Seperator),
+        '{' => Some(Token::OpenCurlyBrace),
+        '}' => Some(Token::CloseCurlyBrace),
+        '(' => Some(Token::OpenBrace),
+        ')' => Some(Token::CloseBrace),
+        '[' => Some(Token::OpenSquareBrace),
+        ']' => Some(Token::CloseSquareBrace),
+        ',' => Some(Token::Comma),
+        ';' => Some(Token::SemiColon),
+        '\n' => Some(Token::Newline),
+        // Operator - OneCharOperator(OneCharOperator),
+        '+' => Some(Token::Add),
+        '-' => Some(Token::Substract),
+        '*' => Some(Token::Multiply),
+        '%' => Some(Token::Modulus),
+        '^' => Some(Token::RaiseTo),
+        ':' => Some(Token::Colon),
+        '~' => Some(Token::Tilde),
+        '$' => Some(Token::Dollar),
+        '?' => Some(Token::Question),
+        '!' => Some(Token::Invert),
+        '<' => Some(Token::LessThan),
+        '>' => Some(Token::GreaterThan),
+        '|' => Some(Token::Bar),
+        '=' => Some(Token::Equal),
+        _ => None,
+    }
+}
+
+// decimal numbers with an optional fraction and exponent (1, 1.5, .5, 1e-3) and hex (0x1F)
+fn parse_number(text: &str) -> Option<f64> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return i64::from_str_radix(hex, 16).ok().map(|number| number as f64);
+    }
+    // parse would also take inf and nan
+    let starts_numeric = text
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_digit() || c == '.');
+    if !starts_numeric || text == "." {
+        return None;
+    }
+    text.parse::<f64>().ok()
+}
+
+// escape sequences in string literals, -v assignments, -F and var=value operands
+pub fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        // up to three octal digits: \033
+        if chars.peek().is_some_and(|c| c.is_digit(8)) {
+            let mut code = 0;
+            for _ in 0..3 {
+                match chars.peek().and_then(|c| c.to_digit(8)) {
+                    Some(digit) => {
+                        code = code * 8 + digit;
+                        chars.next();
+                    }
+                    None => break,
+                }
+            }
+            result.extend(char::from_u32(code));
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('\\') => result.push('\\'),
+            Some('"') => result.push('"'),
+            Some('/') => result.push('/'),
+            Some('a') => result.push('\x07'),
+            Some('b') => result.push('\x08'),
+            Some('f') => result.push('\x0c'),
+            Some('v') => result.push('\x0b'),
+            // continues a string on the next line
+            Some('\n') => {}
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
             }
-            Some(Token::Unknown)
+            None => result.push('\\'),
         }
     }
+    result
 }
 
-pub struct LookupResult {
-    token: Option<Token>,
-    prev: Option<Token>,
-    partial: Vec<char>,
+// '/' is a division after anything that ends an operand, a regex everywhere else
+fn starts_regex(previous: Option<&Token>) -> bool {
+    !matches!(
+        previous,
+        Some(
+            Token::Name(_)
+                | Token::Number(_)
+                | Token::Literal(_)
+                | Token::Ere(_)
+                | Token::CloseBrace
+                | Token::CloseSquareBrace
+                | Token::Incr
+                | Token::Decr
+                | Token::Length
+        )
+    )
 }
-pub fn lookup(current: char, partial: Vec<char>) -> LookupResult {
-    let mut result = LookupResult {
-        token: None,
-        prev: None,
-        partial: partial.clone(),
-    };
-    // handle string literal
-    if partial.first() == Some(&'"') {
-        if current == '\n' {
-            result.token = Some(Token::Error);
-            return result;
-        }
-        // we donot support multiline string - scopedout
-        if current == '"' {
-            let mut cleaned_partial = partial.clone();
-            cleaned_partial.remove(0);
-            result.token = Some(Token::Literal(cleaned_partial));
-            return result;
+
+// a # comment, kept for --pretty-print, `trailing` when it ends a line that has
+// tokens before it and `after_brace` the index of the '}' right before it, if any
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub span: Span,
+    pub text: String,
+    pub trailing: bool,
+    pub after_brace: Option<usize>,
+}
+
+// longest match scanner, each token is the longest run of chars that forms one
+struct Lexer {
+    chars: Vec<char>,
+    position: usize,
+    span: Span,
+    tokens: Vec<SpannedToken>,
+    comments: Vec<Comment>,
+    // --gawk: its keywords, builtins and ** are lexed too
+    gawk: bool,
+}
+
+impl Lexer {
+    fn peek(&self) -> Option<char> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.chars.get(self.position + n).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        self.span = if c == '\n' {
+            Span::new(self.span.line + 1, 1)
         } else {
-            result.partial.push(current);
-            return result;
+            Span::new(self.span.line, self.span.column + 1)
+        };
+        Some(c)
+    }
+
+    fn bump_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let mut text = String::new();
+        while let Some(c) = self.peek().filter(|c| predicate(*c)) {
+            text.push(c);
+            self.bump();
         }
+        text
     }
 
-    // handle others
-    let is_delimiter = is_delimiter_token(current);
-    match is_delimiter {
-        Some(token) => {
-            result.token = Some(token);
-            if !partial.is_empty() {
-                result.prev = deduce_partial(partial);
+    fn push(&mut self, token: Token, span: Span) {
+        self.tokens.push(SpannedToken { token, span });
+    }
+
+    // the chars up to an unescaped `end`, which is skipped, escapes are kept as they are
+    fn delimited(&mut self, end: char, start: Span, unterminated: &str) -> Result<String, Diagnostic> {
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                Some(c) if c == end => return Ok(text),
+                Some('\\') => {
+                    text.push('\\');
+                    match self.bump() {
+                        Some(escaped) => text.push(escaped),
+                        None => return Err(Diagnostic::new(unterminated, start)),
+                    }
+                }
+                Some('\n') | None => return Err(Diagnostic::new(unterminated, start)),
+                Some(c) => text.push(c),
             }
         }
-        None => {
-            result.partial.push(current);
+    }
+
+    fn number(&mut self) -> Token {
+        let mut text = String::new();
+        if self.peek() == Some('0') && matches!(self.peek_nth(1), Some('x') | Some('X'))
+            && self.peek_nth(2).is_some_and(|c| c.is_ascii_hexdigit())
+        {
+            self.bump();
+            self.bump();
+            let hex = self.bump_while(|c| c.is_ascii_hexdigit());
+            return Token::Number(i64::from_str_radix(&hex, 16).unwrap_or(i64::MAX) as f64);
+        }
+        text.push_str(&self.bump_while(|c| c.is_ascii_digit()));
+        if self.peek() == Some('.') {
+            self.bump();
+            text.push('.');
+            text.push_str(&self.bump_while(|c| c.is_ascii_digit()));
         }
+        // the exponent needs digits, `1e` is 1 followed by the name e
+        let signed = matches!(self.peek_nth(1), Some('+') | Some('-'));
+        let digit_at = if signed { 2 } else { 1 };
+        if matches!(self.peek(), Some('e') | Some('E'))
+            && self.peek_nth(digit_at).is_some_and(|c| c.is_ascii_digit())
+        {
+            for _ in 0..digit_at {
+                text.push(self.bump().unwrap());
+            }
+            text.push_str(&self.bump_while(|c| c.is_ascii_digit()));
+        }
+        Token::Number(parse_number(&text).unwrap_or(0.0))
     }
-    result
-}
 
-pub fn tokenize(input: String) -> Vec<Token> {
-    let mut chars = input.chars();
-    let mut tokens: Vec<Token> = vec![];
+    fn word(&mut self) -> Token {
+        let word = self.bump_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        if let Some(token) = keyword(&word) {
+            return token;
+        }
+        if let Some(token) = gawk_keyword(&word).filter(|_| self.gawk) {
+            return token;
+        }
+        // FUNC_NAME is a name immediately followed by '('
+        if self.peek() == Some('(') {
+            return Token::FuncName(word.chars().collect());
+        }
+        Token::Name(word.chars().collect())
+    }
 
-    let mut partial: Vec<char> = vec![];
-    while let Some(current) = chars.next() {
-        let lookup_result = lookup(current, partial);
-        if let Some(token) = lookup_result.token {
-            if let Some(prev_token) = lookup_result.prev {
-                tokens.push(prev_token);
+    fn next_token(&mut self, c: char, start: Span) -> Result<Option<Token>, Diagnostic> {
+        let token = match c {
+            ' ' | '\t' | '\r' => {
+                self.bump();
+                return Ok(None);
             }
-            partial = vec![];
-            // no need to add whitespace token if it is already there
-            if tokens.last() == Some(&Token::WhiteSpace) && token == Token::WhiteSpace {
-                continue;
+            // backslash newline continues the line
+            '\\' if self.peek_nth(1) == Some('\n')
+                || (self.peek_nth(1) == Some('\r') && self.peek_nth(2) == Some('\n')) =>
+            {
+                self.bump_while(|c| c != '\n');
+                self.bump();
+                return Ok(None);
             }
-            tokens.push(token);
-        } else {
-            partial = lookup_result.partial;
+            // a comment, the newline ending it is still a token
+            '#' => {
+                let text = self.bump_while(|c| c != '\n');
+                let trailing = self.tokens.last().is_some_and(|token| {
+                    token.span.line == start.line && token.token != Token::Newline
+                });
+                let is_brace = |token: &SpannedToken| token.token == Token::CloseCurlyBrace;
+                let after_brace = (trailing && self.tokens.last().is_some_and(is_brace))
+                    .then(|| self.tokens.iter().filter(|token| is_brace(token)).count() - 1);
+                self.comments.push(Comment {
+                    span: start,
+                    text,
+                    trailing,
+                    after_brace,
+                });
+                return Ok(None);
+            }
+            '"' => {
+                self.bump();
+                let text = self.delimited('"', start, "unterminated string")?;
+                Token::Literal(unescape(&text).chars().collect())
+            }
+            '/' if starts_regex(self.tokens.last().map(|last| &last.token)) => {
+                self.bump();
+                let text = self.delimited('/', start, "unterminated regular expression")?;
+                Token::Ere(text.chars().collect())
+            }
+            '/' => {
+                self.bump();
+                if self.peek() == Some('=') {
+                    self.bump();
+                    Token::DivideAssign
+                } else {
+                    Token::Divide
+                }
+            }
+            c if c.is_ascii_digit()
+                || (c == '.' && self.peek_nth(1).is_some_and(|c| c.is_ascii_digit())) =>
+            {
+                self.number()
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => self.word(),
+            // gawk's spelling of ^ and ^=
+            '*' if self.gawk && self.peek_nth(1) == Some('*') => {
+                self.bump();
+                self.bump();
+                if self.peek() == Some('=') {
+                    self.bump();
+                    Token::RaiseToAssign
+                } else {
+                    Token::RaiseTo
+                }
+            }
+            c => {
+                if let Some(token) = self.peek_nth(1).and_then(|next| two_char_operator(c, next)) {
+                    self.bump();
+                    self.bump();
+                    token
+                } else if let Some(token) = one_char_token(c) {
+                    self.bump();
+                    token
+                } else {
+                    return Err(Diagnostic::new(format!("unexpected '{}'", c), start));
+                }
+            }
+        };
+        Ok(Some(token))
+    }
+}
+
+// POSIX awk, as the tests lex it
+#[cfg(test)]
+pub fn tokenize(input: String) -> Result<Vec<SpannedToken>, Diagnostic> {
+    tokenize_with(input, false)
+}
+
+// with `gawk` BEGINFILE, ENDFILE, switch, case, default, the gawk builtins and **
+// are tokens of their own
+pub fn tokenize_with(input: String, gawk: bool) -> Result<Vec<SpannedToken>, Diagnostic> {
+    tokenize_with_comments(input, gawk).map(|(tokens, _)| tokens)
+}
+
+// the tokens and the comments between them, in the order they appear
+pub fn tokenize_with_comments(
+    input: String,
+    gawk: bool,
+) -> Result<(Vec<SpannedToken>, Vec<Comment>), Diagnostic> {
+    let mut lexer = Lexer {
+        chars: input.chars().collect(),
+        position: 0,
+        span: Span::new(1, 1),
+        tokens: vec![],
+        comments: vec![],
+        gawk,
+    };
+    while let Some(c) = lexer.peek() {
+        let start = lexer.span;
+        if let Some(token) = lexer.next_token(c, start)? {
+            lexer.push(token, start);
         }
     }
-    tokens
+    Ok((lexer.tokens, lexer.comments))
 }
 
+// the tokens without their spans, for the snapshots
+#[cfg(test)]
+fn lex(source: &str) -> Vec<Token> {
+    tokenize(source.to_string())
+        .expect("source should lex")
+        .into_iter()
+        .map(|spanned| spanned.token)
+        .collect()
+}
 
 #[test]
 fn test_sample_1() {
     let sample_1 = fs::read_to_string("./tests/mocks/sample_1.awk").expect("Unable to read sample_1.txt");
-    let lexed_1 = tokenize(sample_1.clone());
-    insta::assert_compact_json_snapshot!(lexed_1);
+    let lexed_1 = lex(&sample_1);
+    insta::assert_json_snapshot!(lexed_1);
 }
 
 #[test]
 fn test_sample_2() {
     let sample_2 = fs::read_to_string("./tests/mocks/sample_2.awk").expect("Unable to read sample_2.txt");
-    let lexed_2 = tokenize(sample_2.clone());
-    insta::assert_compact_json_snapshot!(lexed_2);
+    let lexed_2 = lex(&sample_2);
+    insta::assert_json_snapshot!(lexed_2);
 }
 
 #[test]
 fn test_sample_3() {
     let sample_3 = fs::read_to_string("./tests/mocks/sample_3.awk").expect("Unable to read sample_3.txt");
-    let lexed_3 = tokenize(sample_3.clone());
-    insta::assert_compact_json_snapshot!(lexed_3);
+    let lexed_3 = lex(&sample_3);
+    insta::assert_json_snapshot!(lexed_3);
 }
 
 #[test]
 fn test_sample_4() {
     let sample_4 = fs::read_to_string("./tests/mocks/sample_4.awk").expect("Unable to read sample_4.txt");
-    let lexed_4 = tokenize(sample_4.clone());
-    insta::assert_compact_json_snapshot!(lexed_4);
+    let lexed_4 = lex(&sample_4);
+    insta::assert_json_snapshot!(lexed_4);
 }
 
 #[test]
 fn test_sample_5() {
     let sample_5 = fs::read_to_string("./tests/mocks/sample_5.awk").expect("Unable to read sample_5.txt");
-    let lexed_5 = tokenize(sample_5.clone());
-    insta::assert_compact_json_snapshot!(lexed_5);
+    let lexed_5 = lex(&sample_5);
+    insta::assert_json_snapshot!(lexed_5);
+}
+
+#[test]
+fn test_unescape() {
+    assert_eq!(unescape("a\\tb\\n"), "a\tb\n");
+    assert_eq!(unescape("\\\\ \\\" \\/"), "\\ \" /");
+    assert_eq!(unescape("\\q\\"), "\\q\\");
+    assert_eq!(unescape("\\033[0m\\1010"), "\x1b[0mA0");
+}
+
+#[test]
+fn test_parse_number() {
+    assert_eq!(parse_number("42"), Some(42.0));
+    assert_eq!(parse_number("2.5"), Some(2.5));
+    assert_eq!(parse_number(".5"), Some(0.5));
+    assert_eq!(parse_number("1e6"), Some(1e6));
+    assert_eq!(parse_number("0x1F"), Some(31.0));
+    assert_eq!(parse_number("inf"), None);
+    assert_eq!(parse_number("."), None);
+}
+
+#[test]
+fn test_sample_6() {
+    let sample_6 = fs::read_to_string("./tests/mocks/sample_6.awk").expect("Unable to read sample_6.txt");
+    let lexed_6 = lex(&sample_6);
+    insta::assert_json_snapshot!(lexed_6);
+}
+
+#[test]
+fn test_regex_and_division() {
+    let lexed = lex("$1 ~ /a\\/b/ && n / 2 > 1 || $0 !~ /x/");
+    insta::assert_compact_json_snapshot!(lexed);
+}
+
+#[test]
+fn test_comments() {
+    let lexed = lex("x# comment with \"quotes\" and /slashes/\n$1 ~ /#/ { print \"#\" } # last");
+    insta::assert_compact_json_snapshot!(lexed);
+}
+
+#[test]
+fn test_numbers_strings_and_continuation() {
+    let lexed = lex("x = 1.5e-3 + .25 * 0x10 \\\n  - 2E+2\nprint \"tab\\there \\\"quoted\\\" \\101\"");
+    insta::assert_compact_json_snapshot!(lexed);
+}
+
+#[test]
+fn test_spans() {
+    let spanned = tokenize("BEGIN {\n  x = \"a\" !~ /b/\n}".to_string()).unwrap();
+    insta::assert_compact_json_snapshot!(spanned);
+}
+
+#[test]
+fn test_errors() {
+    let error = tokenize("{ print \"abc\n}".to_string()).unwrap_err();
+    assert_eq!(error, Diagnostic::new("unterminated string", Span::new(1, 9)));
+    let error = tokenize("$0 ~ /abc".to_string()).unwrap_err();
+    assert_eq!(error, Diagnostic::new("unterminated regular expression", Span::new(1, 6)));
+}
+
+#[test]
+fn test_maximal_munch() {
+    let lexed = lex("\tif (a==b && x!=y) print $NF, a[i,j]\r\nf(1); g (2)\r\n");
+    insta::assert_compact_json_snapshot!(lexed);
+}
+
+#[test]
+fn test_tab_spans() {
+    let spanned = tokenize("{\n\tx>=1\r\n}".to_string()).unwrap();
+    let spans: Vec<Span> = spanned.iter().map(|token| token.span).collect();
+    assert_eq!(
+        spans,
+        vec![Span::new(1, 1), Span::new(1, 2), Span::new(2, 2), Span::new(2, 3), Span::new(2, 5), Span::new(2, 7), Span::new(3, 1)]
+    );
+}
+
+#[test]
+fn test_gawk_tokens() {
+    let source = "BEGINFILE { x **= 2 ** 3 } switch";
+    let gawk: Vec<Token> = tokenize_with(source.to_string(), true)
+        .unwrap()
+        .into_iter()
+        .map(|spanned| spanned.token)
+        .collect();
+    let name = |name: &str| Token::Name(name.chars().collect());
+    assert_eq!(
+        gawk,
+        vec![
+            Token::BeginFile,
+            Token::OpenCurlyBrace,
+            name("x"),
+            Token::RaiseToAssign,
+            Token::Number(2.0),
+            Token::RaiseTo,
+            Token::Number(3.0),
+            Token::CloseCurlyBrace,
+            Token::Switch,
+        ]
+    );
+    // POSIX awk has none of them
+    let posix = lex(source);
+    assert_eq!(posix[0], name("BEGINFILE"));
+    assert_eq!(posix[3..6], [Token::Multiply, Token::MultiplyAssign, Token::Number(2.0)]);
+    assert_eq!(posix.last(), Some(&name("switch")));
 }
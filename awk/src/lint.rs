@@ -0,0 +1,607 @@
+// Static checks for --lint. The syntax tree only keeps where statements start, so
+// findings are placed by walking it and taking, for every name, operator and
+// builtin met on the way, the next token of the same text from the start of the
+// statement being walked, or the start itself when there is none.
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::diagnostic::{locate, Diagnostic, Span};
+use crate::interpreter::{builtin_name, check_arity, RuntimeError};
+use crate::syntax_tree::{
+    Action, BinaryOperator, BuiltinFuncName, Expr, FunctionDeclaration, InputFunction, Item,
+    LValue, OutputRedirection, Pattern, PrintStatement, Program, SimpleGet, SimpleStatement,
+    SpannedStatement, Statement,
+};
+use crate::tokens::{SpannedToken, Token};
+
+// --lint prints one line per finding, --lint=json a JSON array
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    // fails when the program runs
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub span: Span,
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+}
+
+// variables awk gives a value before the program runs
+const SPECIAL_VARIABLES: [&str; 16] = [
+    "ARGC", "ARGV", "CONVFMT", "ENVIRON", "FILENAME", "FNR", "FS", "NF", "NR", "OFMT", "OFS",
+    "ORS", "RLENGTH", "RS", "RSTART", "SUBSEP",
+];
+
+impl Finding {
+    fn new(span: Span, severity: Severity, code: &'static str, message: String) -> Finding {
+        Finding {
+            span,
+            severity,
+            code,
+            message,
+        }
+    }
+
+    pub fn syntax(diagnostic: Diagnostic) -> Finding {
+        Finding::new(diagnostic.span, Severity::Error, "syntax", diagnostic.message)
+    }
+
+    // `files` are the names and sources of the program files in the order they were
+    // joined, each followed by a newline, the line is counted from the start of its file
+    pub fn report(self, files: &[(String, String)]) -> Report {
+        let (index, line) = locate(self.span.line, files);
+        Report {
+            file: files.get(index).map_or(String::new(), |(name, _)| name.clone()),
+            line,
+            column: self.span.column,
+            severity: self.severity,
+            code: self.code,
+            message: self.message,
+        }
+    }
+}
+
+// a finding as printed, in the file:line:column: form editors understand
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Report {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}: {} [{}]",
+            self.file, self.line, self.column, self.severity, self.message, self.code
+        )
+    }
+}
+
+// the spans of the tokens left to pair with the tree, by their text
+struct Locator {
+    spans: HashMap<String, VecDeque<Span>>,
+    // the start of the statement being walked
+    floor: Span,
+}
+
+impl Locator {
+    fn new(tokens: &[SpannedToken]) -> Locator {
+        let mut spans: HashMap<String, VecDeque<Span>> = HashMap::new();
+        for spanned in tokens {
+            let key = match &spanned.token {
+                Token::Name(name) | Token::FuncName(name) => name.iter().collect(),
+                token => token.to_string(),
+            };
+            spans.entry(key).or_default().push_back(spanned.span);
+        }
+        Locator {
+            spans,
+            floor: Span::default(),
+        }
+    }
+
+    // tokens before `span` belong to statements already walked
+    fn start(&mut self, span: Span) {
+        if (span.line, span.column) > (self.floor.line, self.floor.column) {
+            self.floor = span;
+        }
+    }
+
+    fn next(&mut self, key: &str) -> Span {
+        let floor = self.floor;
+        let Some(spans) = self.spans.get_mut(key) else {
+            return floor;
+        };
+        while spans.front().is_some_and(|span| (span.line, span.column) < (floor.line, floor.column)) {
+            spans.pop_front();
+        }
+        spans.pop_front().unwrap_or(floor)
+    }
+}
+
+// how an lvalue is used
+#[derive(Clone, Copy, PartialEq)]
+enum Access {
+    Read,
+    Write,
+    // ++, += and the like, which read a variable but are fine on an uninitialized one
+    Update,
+}
+
+struct Linter<'a> {
+    locator: Locator,
+    functions: HashMap<&'a str, &'a FunctionDeclaration>,
+    // the parameters of the function being walked
+    locals: &'a [String],
+    function: Option<&'a str>,
+    in_end: bool,
+    // first reads of globals in source order, checked once every assignment is known
+    reads: Vec<(String, Span)>,
+    assigned: HashSet<String>,
+    // functions called from anywhere but their own body
+    called: HashSet<String>,
+    declarations: Vec<(&'a str, Span)>,
+    findings: Vec<Finding>,
+}
+
+// `text` == "^a.*" compares strings, it was probably meant as `text` ~ /^a.*/
+fn looks_like_regex(text: &str) -> bool {
+    text.starts_with('^')
+        || (text.len() > 1 && text.ends_with('$'))
+        || text.contains(".*")
+        || text.contains(".+")
+        || (text.contains('[') && text.contains(']'))
+}
+
+pub fn lint(program: &Program, tokens: &[SpannedToken]) -> Vec<Finding> {
+    let mut linter = Linter {
+        locator: Locator::new(tokens),
+        functions: HashMap::new(),
+        locals: &[],
+        function: None,
+        in_end: false,
+        reads: vec![],
+        assigned: HashSet::new(),
+        called: HashSet::new(),
+        declarations: vec![],
+        findings: vec![],
+    };
+    for item in &program.items {
+        if let Item::FunctionDeclaration(function) = item {
+            linter.functions.insert(&function.name, function);
+        }
+    }
+    for item in &program.items {
+        match item {
+            Item::FunctionDeclaration(function) => linter.function_declaration(function),
+            Item::Action(action) => linter.block(action),
+            Item::PatternAction(pattern, action) => {
+                linter.pattern(pattern);
+                linter.in_end = *pattern == Pattern::End;
+                linter.block(action);
+                linter.in_end = false;
+            }
+            Item::Pattern(pattern) => linter.pattern(pattern),
+        }
+    }
+
+    let mut reported = HashSet::new();
+    for (name, span) in std::mem::take(&mut linter.reads) {
+        if !linter.assigned.contains(&name) && reported.insert(name.clone()) {
+            let message = format!("{} is used but never assigned", name);
+            linter.warn(span, "uninitialized", message);
+        }
+    }
+    for (name, span) in std::mem::take(&mut linter.declarations) {
+        if !linter.called.contains(name) {
+            linter.warn(span, "unused-function", format!("function {} is never called", name));
+        }
+    }
+    let mut findings = linter.findings;
+    findings.sort_by_key(|finding| (finding.span.line, finding.span.column));
+    findings
+}
+
+impl<'a> Linter<'a> {
+    fn warn(&mut self, span: Span, code: &'static str, message: String) {
+        self.findings.push(Finding::new(span, Severity::Warning, code, message));
+    }
+
+    fn error(&mut self, span: Span, code: &'static str, message: String) {
+        self.findings.push(Finding::new(span, Severity::Error, code, message));
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.locals.iter().any(|local| local == name)
+    }
+
+    fn read(&mut self, name: &str) {
+        let span = self.locator.next(name);
+        if !self.is_local(name) && !SPECIAL_VARIABLES.contains(&name) {
+            self.reads.push((name.to_string(), span));
+        }
+    }
+
+    fn write(&mut self, name: &str) {
+        let span = self.locator.next(name);
+        if self.is_local(name) {
+            return;
+        }
+        if name == "NF" && self.in_end {
+            // the last record is still there in END, but changing it there is most likely a mistake
+            self.warn(span, "nf-in-end", "NF is assigned in END".to_string());
+        }
+        self.assigned.insert(name.to_string());
+    }
+
+    fn pass(&mut self, name: &str) {
+        self.locator.next(name);
+        if !self.is_local(name) {
+            self.assigned.insert(name.to_string());
+        }
+    }
+
+    fn function_declaration(&mut self, function: &'a FunctionDeclaration) {
+        let span = self.locator.next(&function.name);
+        self.declarations.push((&function.name, span));
+        if SPECIAL_VARIABLES.contains(&function.name.as_str()) {
+            let message = format!("function {} has the name of a special variable", function.name);
+            self.warn(span, "shadowed-builtin", message);
+        }
+        for param in &function.params {
+            let span = self.locator.next(param);
+            if SPECIAL_VARIABLES.contains(&param.as_str()) {
+                let message = format!("parameter {} of {} shadows the special variable", param, function.name);
+                self.warn(span, "shadowed-builtin", message);
+            } else if self.functions.contains_key(param.as_str()) {
+                let message = format!("parameter {} of {} shadows the function {}", param, function.name, param);
+                self.warn(span, "shadowed-builtin", message);
+            }
+        }
+        self.locals = &function.params;
+        self.function = Some(&function.name);
+        self.block(&function.body);
+        self.locals = &[];
+        self.function = None;
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Begin | Pattern::End | Pattern::BeginFile | Pattern::EndFile => {}
+            Pattern::Expr(expr) => self.expr(expr),
+            Pattern::ExprCommaNewlineOptExpr(start, end) => {
+                self.expr(start);
+                self.expr(end);
+            }
+        }
+    }
+
+    fn block(&mut self, action: &Action) {
+        for statement in &action.statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &SpannedStatement) {
+        self.locator.start(statement.span);
+        match &statement.statement {
+            Statement::Action(action) => self.block(action),
+            Statement::If(condition, then_branch, else_branch) => {
+                self.expr(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Statement::While(condition, body) => {
+                self.expr(condition);
+                self.statement(body);
+            }
+            Statement::Do(body, condition) => {
+                self.statement(body);
+                self.expr(condition);
+            }
+            Statement::For(init, condition, update, body) => {
+                if let Some(init) = init {
+                    self.simple(init);
+                }
+                if let Some(condition) = condition {
+                    self.expr(condition);
+                }
+                if let Some(update) = update {
+                    self.simple(update);
+                }
+                self.statement(body);
+            }
+            Statement::ForIn(key, array, body) => {
+                self.write(key);
+                self.read(array);
+                self.statement(body);
+            }
+            Statement::Switch(subject, cases) => {
+                self.expr(subject);
+                for case in cases {
+                    if let Some(label) = &case.label {
+                        self.expr(label);
+                    }
+                    for statement in &case.statements {
+                        self.statement(statement);
+                    }
+                }
+            }
+            Statement::Exit(Some(expr)) | Statement::Return(Some(expr)) => self.expr(expr),
+            Statement::Empty
+            | Statement::Break
+            | Statement::Continue
+            | Statement::Next
+            | Statement::Exit(None)
+            | Statement::Return(None) => {}
+            Statement::SimpleStatement(simple) => self.simple(simple),
+        }
+    }
+
+    fn simple(&mut self, statement: &SimpleStatement) {
+        match statement {
+            SimpleStatement::Expr(expr) => self.expr(expr),
+            SimpleStatement::Delete(name, subscripts) => {
+                self.locator.next(name);
+                self.exprs(subscripts);
+            }
+            SimpleStatement::PrintStatement(
+                PrintStatement::Print(exprs, redirection) | PrintStatement::Printf(exprs, redirection),
+            ) => {
+                self.exprs(exprs);
+                match redirection {
+                    Some(
+                        OutputRedirection::GreaterThan(target)
+                        | OutputRedirection::Append(target)
+                        | OutputRedirection::Pipe(target),
+                    ) => self.expr(target),
+                    None => {}
+                }
+            }
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn lvalue(&mut self, lvalue: &LValue, access: Access) {
+        match lvalue {
+            LValue::Name(name) | LValue::NameOpenSquareBraceExprListCloseSquareBrace(name, _) => {
+                match access {
+                    Access::Read => self.read(name),
+                    Access::Write | Access::Update => self.write(name),
+                }
+            }
+            LValue::DollarExpr(index) => self.expr(index),
+        }
+        if let LValue::NameOpenSquareBraceExprListCloseSquareBrace(_, subscripts) = lvalue {
+            self.exprs(subscripts);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Number(_) | Expr::String(_) | Expr::Ere(_) => {}
+            Expr::LValue(lvalue) => self.lvalue(lvalue, Access::Read),
+            Expr::Grouping(expr) | Expr::Unary(_, expr) => self.expr(expr),
+            Expr::Binary(left, operator @ (BinaryOperator::Eq | BinaryOperator::Ne), right) => {
+                self.expr(left);
+                let symbol = if *operator == BinaryOperator::Eq { "==" } else { "!=" };
+                let span = self.locator.next(&format!("'{}'", symbol));
+                for side in [left, right] {
+                    if let Expr::String(text) = side.as_ref() {
+                        if looks_like_regex(text) {
+                            let matching = if *operator == BinaryOperator::Eq { "~" } else { "!~" };
+                            let message = format!(
+                                "\"{}\" looks like a regular expression but {} compares strings, use {}",
+                                text, symbol, matching
+                            );
+                            self.warn(span, "regex-comparison", message);
+                        }
+                    }
+                }
+                self.expr(right);
+            }
+            Expr::Binary(left, _, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Conditional(condition, then_branch, else_branch) => {
+                self.expr(condition);
+                self.expr(then_branch);
+                self.expr(else_branch);
+            }
+            Expr::In(subscript, array) => {
+                self.expr(subscript);
+                self.read(array);
+            }
+            Expr::BracketMultipleExprListBracket(subscripts, array) => {
+                self.exprs(subscripts);
+                self.read(array);
+            }
+            Expr::Assign(lvalue, value) => {
+                self.lvalue(lvalue, Access::Write);
+                self.expr(value);
+            }
+            Expr::CompoundAssign(lvalue, _, value) => {
+                self.lvalue(lvalue, Access::Update);
+                self.expr(value);
+            }
+            Expr::LValueIncr(lvalue)
+            | Expr::LValueDecr(lvalue)
+            | Expr::IncrLValue(lvalue)
+            | Expr::DecrLValue(lvalue) => self.lvalue(lvalue, Access::Update),
+            Expr::FuncCall(name, args) => self.call(name, args),
+            Expr::BuiltinFuncCall(builtin, args) => self.builtin(builtin, args),
+            Expr::InputFunction(function) => match function {
+                InputFunction::SimpleGet(get) => self.get(get),
+                InputFunction::SimpleGetLessThanExpr(get, file) => {
+                    self.get(get);
+                    self.expr(file);
+                }
+                InputFunction::ExprBarSimpleGet(command, get) => {
+                    self.expr(command);
+                    self.get(get);
+                }
+            },
+        }
+    }
+
+    fn get(&mut self, get: &SimpleGet) {
+        if let SimpleGet::GetlineLValue(lvalue) = get {
+            self.lvalue(lvalue, Access::Write);
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr]) {
+        let span = self.locator.next(name);
+        match self.functions.get(name) {
+            None => self.error(span, "undefined-function", format!("function {} is never defined", name)),
+            Some(function) if args.len() > function.params.len() => {
+                let message = format!(
+                    "function {} called with {} args, accepts only {}",
+                    name,
+                    args.len(),
+                    function.params.len()
+                );
+                self.error(span, "arity", message);
+            }
+            Some(_) => {}
+        }
+        if self.function != Some(name) {
+            self.called.insert(name.to_string());
+        }
+        for arg in args {
+            match arg {
+                // a bare name may be an array the function fills
+                Expr::LValue(LValue::Name(name)) => self.pass(name),
+                arg => self.expr(arg),
+            }
+        }
+    }
+
+    fn builtin(&mut self, builtin: &BuiltinFuncName, args: &[Expr]) {
+        let span = self.locator.next(&format!("'{}'", builtin_name(builtin)));
+        if let Err(RuntimeError(message)) = check_arity(builtin, args.len()) {
+            self.error(span, "arity", message);
+        }
+        for (index, arg) in args.iter().enumerate() {
+            match (builtin, index, arg) {
+                (BuiltinFuncName::Split, 1, Expr::LValue(lvalue)) => self.lvalue(lvalue, Access::Write),
+                (BuiltinFuncName::Sub | BuiltinFuncName::Gsub, 2, Expr::LValue(lvalue)) => {
+                    self.lvalue(lvalue, Access::Update)
+                }
+                (_, _, arg) => self.expr(arg),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn lint_source(source: &str) -> Vec<(usize, usize, &'static str)> {
+    let tokens = crate::lexer::tokenize(source.to_string()).expect("program should lex");
+    let program = crate::parser::parse(tokens.clone()).expect("program should parse");
+    lint(&program, &tokens)
+        .into_iter()
+        .map(|finding| (finding.span.line, finding.span.column, finding.code))
+        .collect()
+}
+
+#[test]
+fn test_uninitialized_and_functions() {
+    let source = "function used(a, n) { return a + n + total }
+function unused() { return 1 }
+function rec(n) { return n ? rec(n - 1) : 0 }
+{ total += $1; seen[$2]++; print used(x, 1), count }
+END { for (k in seen) print k; print used(1, 2, 3), missing(), length(1, 2) }";
+    assert_eq!(
+        lint_source(source),
+        [
+            (2, 10, "unused-function"),
+            (3, 10, "unused-function"),
+            (4, 46, "uninitialized"),
+            (5, 38, "arity"),
+            (5, 53, "undefined-function"),
+            (5, 64, "arity"),
+        ]
+    );
+}
+
+#[test]
+fn test_suspicious_code() {
+    let source = "function f(NR, f) { return NR }
+BEGIN { f(1) }
+$1 == \"^a.*\" || $2 != \"b\" || \"[0-9]+\" != $3 { print }
+END { NF = 2; $0 = \"x\"; print }";
+    assert_eq!(
+        lint_source(source),
+        [
+            (1, 12, "shadowed-builtin"),
+            (1, 16, "shadowed-builtin"),
+            (3, 4, "regex-comparison"),
+            (3, 39, "regex-comparison"),
+            (4, 7, "nf-in-end"),
+        ]
+    );
+    assert!(lint_source("BEGIN { x = 1 } { if (x == \"a\") print x }").is_empty());
+}
+
+#[test]
+fn test_locator_starts_at_the_statement() {
+    let tokens = crate::lexer::tokenize("{ x = 1; y = x }\n{ print x }".to_string()).unwrap();
+    let mut locator = Locator::new(&tokens);
+    assert_eq!(locator.next("x"), Span::new(1, 3));
+    // the `x` of `y = x` was never asked for, the second statement still finds its own
+    locator.start(Span::new(2, 3));
+    assert_eq!(locator.next("x"), Span::new(2, 9));
+    // past the last `x` a finding is put at the start of its statement
+    assert_eq!(locator.next("x"), Span::new(2, 3));
+    assert_eq!(locator.next("z"), Span::new(2, 3));
+}
+
+#[test]
+fn test_report() {
+    let files = [
+        ("a.awk".to_string(), "BEGIN {\n}\n".to_string()),
+        ("b.awk".to_string(), "{ print x }".to_string()),
+    ];
+    let finding = Finding::new(Span::new(4, 9), Severity::Warning, "uninitialized", "x is used but never assigned".to_string());
+    let report = finding.report(&files);
+    assert_eq!(report.to_string(), "b.awk:1:9: warning: x is used but never assigned [uninitialized]");
+    assert_eq!(
+        serde_json::to_string(&report).unwrap(),
+        r#"{"file":"b.awk","line":1,"column":9,"severity":"warning","code":"uninitialized","message":"x is used but never assigned"}"#
+    );
+}
@@ -1,13 +1,362 @@
-use std::fs;
-
+mod builtins;
+mod bytecode;
+mod debugger;
+mod diagnostic;
+mod ere;
+mod format;
+mod gawk;
+mod interpreter;
+mod json;
 mod lexer;
-mod tokens;
+mod lint;
+mod meta;
+mod parallel;
+mod parser;
+mod pretty;
+mod profile;
+mod record;
+mod repl;
+mod streams;
 mod syntax_tree;
+mod tokens;
+mod value;
+mod vm;
+
+use debugger::Debugger;
+use interpreter::Interpreter;
+use meta::{get_help, Params, EXIT_FAILURE, EXIT_SUCCESS, USAGE, VERSION};
+use std::{
+    env, fs,
+    io::{self, BufWriter},
+    process::exit,
+    thread,
+};
+use value::Value;
+
+// the names and texts of the program files, the program text is named cmdline;
+// with --gawk the files they @include come first
+fn read_sources(params: &Params) -> Vec<(String, String)> {
+    let sources = read_program_files(params);
+    if !params.gawk {
+        return sources;
+    }
+    match gawk::include_files(sources) {
+        Ok(sources) => sources,
+        Err(e) => {
+            eprintln!("awk: {}", e);
+            exit(EXIT_FAILURE)
+        }
+    }
+}
+
+fn read_program_files(params: &Params) -> Vec<(String, String)> {
+    if params.program_files.is_empty() {
+        let text = params.program_text.clone().unwrap_or_default();
+        return vec![("cmdline".to_string(), text)];
+    }
+    let mut sources = vec![];
+    for path in &params.program_files {
+        let source = if path == "-" {
+            io::read_to_string(io::stdin())
+        } else {
+            fs::read_to_string(path)
+        };
+        match source {
+            Ok(source) => sources.push((path.clone(), source)),
+            Err(e) => {
+                eprintln!("awk: can't open file {}: {}", path, e);
+                exit(EXIT_FAILURE)
+            }
+        }
+    }
+    sources
+}
+
+fn join_sources(sources: &[(String, String)]) -> String {
+    let texts: Vec<&str> = sources.iter().map(|(_, source)| source.as_str()).collect();
+    texts.join("\n")
+}
+
+// checks the program without running it, exits with 1 when something was found
+fn lint_program(params: &Params, format: lint::Format) -> ! {
+    let sources = read_sources(params);
+    let source = join_sources(&sources);
+    let findings = match lexer::tokenize_with(source, params.gawk) {
+        Ok(tokens) => match parser::parse(tokens.clone()) {
+            Ok(program) => lint::lint(&program, &tokens),
+            Err(diagnostic) => vec![lint::Finding::syntax(diagnostic)],
+        },
+        Err(diagnostic) => vec![lint::Finding::syntax(diagnostic)],
+    };
+    let code = if findings.iter().any(|finding| finding.code == "syntax") {
+        EXIT_FAILURE
+    } else if findings.is_empty() {
+        EXIT_SUCCESS
+    } else {
+        1
+    };
+    let reports: Vec<lint::Report> = findings.into_iter().map(|finding| finding.report(&sources)).collect();
+    match format {
+        lint::Format::Text => {
+            for report in reports {
+                println!("{}", report);
+            }
+        }
+        lint::Format::Json => println!("{}", serde_json::to_string(&reports).expect("reports serialize")),
+    }
+    exit(code)
+}
+
+// prints the program laid out again, with its comments, without running it
+fn pretty_print_program(params: &Params) -> ! {
+    let sources = read_sources(params);
+    let source = join_sources(&sources);
+    let parsed = lexer::tokenize_with_comments(source, params.gawk).and_then(|(tokens, comments)| {
+        parser::parse_with_item_spans(tokens).map(|(program, item_spans)| (program, item_spans, comments))
+    });
+    match parsed {
+        Ok((program, item_spans, comments)) => {
+            print!("{}", pretty::pretty_print_with_comments(&program, &item_spans, &comments));
+            exit(EXIT_SUCCESS)
+        }
+        Err(diagnostic) => {
+            eprintln!("awk: {}", diagnostic.render_files(&sources));
+            exit(EXIT_FAILURE)
+        }
+    }
+}
+
+fn work(params: Params) {
+    if let Some(format) = params.lint {
+        lint_program(&params, format)
+    }
+    if params.pretty_print {
+        pretty_print_program(&params)
+    }
+    let sources = read_sources(&params);
+    let source = join_sources(&sources);
+    let program = match lexer::tokenize_with(source.clone(), params.gawk).and_then(parser::parse) {
+        Ok(program) => program,
+        Err(diagnostic) => {
+            eprintln!("awk: {}", diagnostic.render_files(&sources));
+            exit(EXIT_FAILURE)
+        }
+    };
+
+    let stdout = Box::new(BufWriter::new(io::stdout()));
+    let mut interpreter = Interpreter::new(&program, stdout);
+    // the REPL and the debugger read their commands from stdin, it can't stay locked
+    if !params.repl && !params.debug {
+        interpreter.set_stdin(Box::new(io::stdin().lock()));
+    }
+    if let Some(fs) = params.field_separator {
+        // -Ft is a tab, like in the other awks
+        let fs = if fs == "t" { "\t".to_string() } else { lexer::unescape(&fs) };
+        interpreter.set_global("FS", Value::String(fs));
+    }
+    if params.csv {
+        interpreter.set_csv();
+    }
+    if params.json {
+        interpreter.set_json();
+    }
+    if params.gawk {
+        interpreter.set_gawk();
+    }
+    if params.tsv {
+        interpreter.set_global("FS", Value::String("\t".to_string()));
+        interpreter.set_global("OFS", Value::String("\t".to_string()));
+    }
+    for assignment in &params.assignments {
+        if !interpreter.command_line_assignment(assignment) {
+            eprintln!("awk: invalid -v argument: {}", assignment);
+            exit(EXIT_FAILURE)
+        }
+    }
+    let mut parallel = false;
+    if let Some(jobs) = params.parallel {
+        match parallel::analyze(&program) {
+            // chunks are cut at newlines, which can be inside a quoted field
+            Ok(_) if params.csv => eprintln!("awk: running sequentially: CSV records can span lines"),
+            Ok(plan) => {
+                interpreter.set_parallel(plan, jobs);
+                parallel = true;
+            }
+            Err(reason) => eprintln!("awk: running sequentially: {}", reason),
+        }
+    }
+    // the chunks of --parallel, the REPL, the debugger, the profiler and the gawk
+    // extensions are run by the tree-walker
+    let tree_walker = params.tree_walker || params.repl || params.debug || params.profile.is_some() || params.gawk;
+    if !parallel && !tree_walker {
+        interpreter.set_bytecode(bytecode::compile(&program));
+    }
+    let mut argv = vec!["awk".to_string()];
+    argv.extend(params.operands);
+    interpreter.set_argv(&argv);
+    if params.repl {
+        exit(repl::run(interpreter, program))
+    }
+    if params.debug {
+        match debugger::terminal() {
+            Ok(commands) => {
+                let debugger = Debugger::new(&program, &source, commands, Box::new(io::stdout()));
+                interpreter.set_debugger(debugger);
+            }
+            Err(e) => {
+                eprintln!("awk: can't start the debugger: {}", e);
+                exit(EXIT_FAILURE)
+            }
+        }
+    }
+
+    if params.profile.is_some() {
+        interpreter.set_profile();
+    }
+
+    let result = interpreter.run(&program);
+    // the counts up to a runtime error are written too
+    if let (Some(path), Some(profile)) = (&params.profile, interpreter.take_profile()) {
+        if let Err(e) = fs::write(path, profile.report(&program)) {
+            eprintln!("awk: can't write the profile to {}: {}", path, e);
+        }
+    }
+    match result {
+        Ok(code) => exit(code),
+        Err(e) => {
+            eprintln!("awk: {}", e);
+            exit(EXIT_FAILURE)
+        }
+    }
+}
+
+// the value of an option, either attached (-F:) or the next argument (-F :)
+fn option_value(args: &[String], i: &mut usize, option: &str) -> String {
+    if args[*i].len() > option.len() {
+        return args[*i][option.len()..].to_string();
+    }
+    *i += 1;
+    match args.get(*i) {
+        Some(value) => value.clone(),
+        None => {
+            eprintln!("awk: option {} requires an argument\n{}", option, USAGE);
+            exit(EXIT_FAILURE)
+        }
+    }
+}
 
 fn main() {
-    // let sample_1 = fs::read_to_string("./tests/sample_5.awk").expect("Unable to read sample_1.txt");
-    let sample_1 = fs::read_to_string("./tests/sample_4.awk").expect("Unable to read sample_1.txt");
-    let lexed_1 = lexer::tokenize(sample_1.clone());
-    dbg!(sample_1);
-    dbg!(lexed_1);
+    let args: Vec<String> = env::args().collect();
+
+    let mut params = Params {
+        program_files: Vec::new(),
+        program_text: None,
+        field_separator: None,
+        assignments: Vec::new(),
+        operands: Vec::new(),
+        parallel: None,
+        csv: false,
+        tsv: false,
+        json: false,
+        tree_walker: false,
+        lint: None,
+        pretty_print: false,
+        repl: false,
+        debug: false,
+        profile: None,
+        gawk: false,
+    };
+
+    let mut i = 1;
+    while i < args.len() {
+        if args[i] == "--version" {
+            println!("version: {}\n", VERSION);
+            exit(EXIT_SUCCESS)
+        } else if args[i] == "--help" {
+            println!("{}\n", get_help());
+            exit(EXIT_SUCCESS)
+        } else if args[i] == "--parallel" {
+            params.parallel = Some(thread::available_parallelism().map_or(1, |jobs| jobs.get()));
+        } else if let Some(jobs) = args[i].strip_prefix("--parallel=") {
+            params.parallel = match jobs.parse() {
+                Ok(jobs) if jobs > 0 => Some(jobs),
+                _ => {
+                    eprintln!("awk: invalid number of jobs {}\n{}", jobs, USAGE);
+                    exit(EXIT_FAILURE)
+                }
+            };
+        } else if args[i] == "--csv" {
+            params.csv = true;
+        } else if args[i] == "--tsv" {
+            params.tsv = true;
+        } else if args[i] == "--json" {
+            params.json = true;
+        } else if args[i] == "--tree-walker" {
+            params.tree_walker = true;
+        } else if args[i] == "--lint" {
+            params.lint = Some(lint::Format::Text);
+        } else if args[i] == "--lint=json" {
+            params.lint = Some(lint::Format::Json);
+        } else if args[i] == "--pretty-print" {
+            params.pretty_print = true;
+        } else if args[i] == "--repl" {
+            params.repl = true;
+        } else if args[i] == "--debug" {
+            params.debug = true;
+        } else if args[i] == "--gawk" {
+            params.gawk = true;
+        } else if args[i] == "--profile" {
+            params.profile = Some(profile::DEFAULT_FILE.to_string());
+        } else if let Some(path) = args[i].strip_prefix("--profile=") {
+            params.profile = Some(path.to_string());
+        } else if args[i] == "--" {
+            i += 1;
+            break;
+        } else if args[i].starts_with("-f") {
+            params.program_files.push(option_value(&args, &mut i, "-f"));
+        } else if args[i].starts_with("-v") {
+            params.assignments.push(option_value(&args, &mut i, "-v"));
+        } else if args[i].starts_with("-F") {
+            params.field_separator = Some(option_value(&args, &mut i, "-F"));
+        } else if args[i].starts_with('-') && args[i] != "-" {
+            eprintln!("awk: unknown option {}\n{}", args[i], USAGE);
+            exit(EXIT_FAILURE)
+        } else {
+            break;
+        }
+        i += 1;
+    }
+
+    // the debugger and the profiler only see the statements run on the main thread
+    if params.debug || params.profile.is_some() {
+        params.parallel = None;
+    }
+
+    if params.csv && params.tsv {
+        eprintln!("awk: --csv and --tsv can't be used together\n{}", USAGE);
+        exit(EXIT_FAILURE)
+    }
+
+    // without -f the first operand is the program text, or all of them are program files
+    // with --lint and --pretty-print
+    let mut operands = args[i..].iter().cloned();
+    if params.program_files.is_empty() && (params.lint.is_some() || params.pretty_print) {
+        params.program_files = operands.by_ref().collect();
+        if params.program_files.is_empty() {
+            eprintln!("{}", USAGE);
+            exit(EXIT_FAILURE)
+        }
+    } else if params.program_files.is_empty() && !params.repl {
+        params.program_text = match operands.next() {
+            Some(text) => Some(text),
+            None => {
+                eprintln!("{}", USAGE);
+                exit(EXIT_FAILURE)
+            }
+        };
+    }
+    params.operands = operands.collect();
+    // uncomment to debug params
+    // dbg!(params.clone());
+    work(params);
 }
@@ -0,0 +1,32 @@
+#[derive(Debug, Clone)]
+pub struct Params {
+    pub program_files: Vec<String>,     // -f progfile, can be repeated
+    pub program_text: Option<String>,   // first operand when there is no -f
+    pub field_separator: Option<String>, // -F fs
+    pub assignments: Vec<String>,       // -v var=value, applied before BEGIN
+    pub operands: Vec<String>,          // input files and var=value assignments
+    pub parallel: Option<usize>,        // --parallel[=jobs], number of worker threads
+    pub csv: bool,                      // --csv, records and fields are CSV
+    pub tsv: bool,                      // --tsv, fields are separated by tabs
+    pub json: bool,                     // --json, $"key" looks up keys of JSON lines
+    pub tree_walker: bool,              // --tree-walker, runs without compiling to bytecode
+    pub lint: Option<crate::lint::Format>, // --lint[=json], checks the program instead of running it
+    pub pretty_print: bool,             // --pretty-print, prints the program formatted instead of running it
+    pub repl: bool,                     // --repl, reads statements and pattern-actions interactively
+    pub debug: bool,                    // --debug, stops before statements to take debugger commands
+    pub profile: Option<String>,        // --profile[=file], writes execution counts and times to file
+    pub gawk: bool,                     // --gawk, enables the gawk extensions
+}
+
+pub const VERSION: &str = "0.0.1";
+pub const EXIT_SUCCESS: i32 = 0;
+// awk reports usage, syntax and runtime errors with 2
+pub const EXIT_FAILURE: i32 = 2;
+
+pub const USAGE: &str = "usage: awk [--parallel[=jobs]][--csv | --tsv][--json][--tree-walker][--lint[=json]][--pretty-print][--repl][--debug][--profile[=file]][--gawk][-F fs][-v var=value][prog | -f progfile ...][file ...]";
+
+pub fn get_help() -> String {
+    let specs_u8 = include_bytes!("../README.md");
+    let specs: String = String::from_utf8_lossy(specs_u8).to_string();
+    specs
+}
@@ -0,0 +1,908 @@
+// Map-reduce execution: input files are cut into record aligned chunks, the
+// pattern-actions run over each chunk on a worker thread starting from the state
+// left by BEGIN, and the variables the chunks wrote are merged back before END.
+//
+// This only gives the sequential result when records do not depend on each other,
+// which `analyze` checks for before anything runs.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, BufRead, Cursor, Read};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use crate::interpreter::{
+    split_assignment, Interpreter, RuntimeError, SharedOutput, Snapshot, VariableSnapshot,
+};
+use crate::syntax_tree::{
+    Action, BinaryOperator, BuiltinFuncName, Expr, FunctionDeclaration, Item, LValue, Pattern,
+    PrintStatement, Program, SimpleStatement, Statement,
+};
+use crate::value::Value;
+
+pub const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+// variables which change how later records are read or printed
+const INPUT_VARIABLES: [&str; 12] = [
+    "FS", "RS", "OFS", "ORS", "SUBSEP", "CONVFMT", "OFMT", "FILENAME", "NR", "FNR", "ARGV",
+    "ARGC",
+];
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParallelPlan {
+    // only updated with x = x + e, x += e, x++ or their subtractions,
+    // merged by adding up what each chunk added
+    pub accumulators: HashSet<String>,
+    // only assigned and never read, merged by taking the value from the last chunk which set it
+    pub assigned: HashSet<String>,
+    pub chunk_size: usize,
+}
+
+#[derive(Default)]
+struct Usage<'a> {
+    reads: HashSet<String>,
+    accumulated: HashSet<String>,
+    assigned: HashSet<String>,
+    functions: HashMap<&'a str, &'a FunctionDeclaration>,
+    analyzed_functions: HashSet<&'a str>,
+}
+
+// Scope of one pattern-action: `locals` are the parameters of the function being
+// analyzed, `defined` the globals assigned earlier in the same record, which makes
+// reading them order independent
+#[derive(Clone, Default)]
+struct Scope {
+    locals: HashSet<String>,
+    defined: HashSet<String>,
+}
+
+type Analysis<T> = Result<T, String>;
+
+// the variable and the added expression of x = x + e, x += e, x++ and their
+// subtractions, also on array elements; None for the increments by one
+fn accumulation(expr: &Expr) -> Option<(&LValue, Option<&Expr>)> {
+    let (target, increment) = match expr {
+        Expr::Assign(target, value) => match value.as_ref() {
+            Expr::Binary(left, BinaryOperator::Add | BinaryOperator::Substract, right)
+                if left.as_ref() == &Expr::LValue(target.clone()) =>
+            {
+                (target, Some(right.as_ref()))
+            }
+            _ => return None,
+        },
+        Expr::CompoundAssign(target, BinaryOperator::Add | BinaryOperator::Substract, value) => {
+            (target, Some(value.as_ref()))
+        }
+        Expr::LValueIncr(target)
+        | Expr::LValueDecr(target)
+        | Expr::IncrLValue(target)
+        | Expr::DecrLValue(target) => (target, None),
+        _ => return None,
+    };
+    match target {
+        LValue::DollarExpr(_) => None,
+        target => Some((target, increment)),
+    }
+}
+
+// the variable changed by an assignment, ++ or --
+fn assigned_lvalue(expr: &Expr) -> Option<&LValue> {
+    match expr {
+        Expr::Assign(lvalue, _)
+        | Expr::CompoundAssign(lvalue, _, _)
+        | Expr::LValueIncr(lvalue)
+        | Expr::LValueDecr(lvalue)
+        | Expr::IncrLValue(lvalue)
+        | Expr::DecrLValue(lvalue) => Some(lvalue),
+        _ => None,
+    }
+}
+
+fn lvalue_children(lvalue: &LValue) -> Vec<&Expr> {
+    match lvalue {
+        LValue::Name(_) => vec![],
+        LValue::NameOpenSquareBraceExprListCloseSquareBrace(_, subscripts) => {
+            subscripts.iter().collect()
+        }
+        LValue::DollarExpr(index) => vec![index.as_ref()],
+    }
+}
+
+// the parameters of a function which it uses as arrays
+fn array_params(function: &FunctionDeclaration) -> HashSet<String> {
+    let params: HashSet<String> = function.params.iter().cloned().collect();
+    let mut arrays = HashSet::new();
+    for statement in &function.body.statements {
+        array_params_in_statement(statement, &params, &mut arrays);
+    }
+    arrays
+}
+
+fn array_params_in_statement(
+    statement: &Statement,
+    params: &HashSet<String>,
+    arrays: &mut HashSet<String>,
+) {
+    if let Statement::ForIn(_, name, _)
+    | Statement::SimpleStatement(SimpleStatement::Delete(name, _)) = statement
+    {
+        if params.contains(name) {
+            arrays.insert(name.clone());
+        }
+    }
+    let (exprs, statements) = statement_children(statement);
+    for expr in exprs {
+        array_params_in_expr(expr, params, arrays);
+    }
+    for statement in statements {
+        array_params_in_statement(statement, params, arrays);
+    }
+}
+
+fn array_params_in_expr(expr: &Expr, params: &HashSet<String>, arrays: &mut HashSet<String>) {
+    let lvalue = match expr {
+        Expr::LValue(lvalue) => Some(lvalue),
+        expr => assigned_lvalue(expr),
+    };
+    if let Some(LValue::NameOpenSquareBraceExprListCloseSquareBrace(name, _)) = lvalue {
+        if params.contains(name) {
+            arrays.insert(name.clone());
+        }
+    }
+    match expr {
+        Expr::In(_, name)
+        | Expr::BracketMultipleExprListBracket(_, name)
+            if params.contains(name) =>
+        {
+            arrays.insert(name.clone());
+        }
+        _ => {}
+    }
+    for child in children(expr) {
+        array_params_in_expr(child, params, arrays);
+    }
+}
+
+// direct sub-expressions, for the cases the analysis treats uniformly
+fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Number(_) | Expr::String(_) | Expr::Ere(_) => vec![],
+        Expr::LValue(lvalue)
+        | Expr::LValueIncr(lvalue)
+        | Expr::LValueDecr(lvalue)
+        | Expr::IncrLValue(lvalue)
+        | Expr::DecrLValue(lvalue) => lvalue_children(lvalue),
+        Expr::Grouping(expr) | Expr::Unary(_, expr) | Expr::In(expr, _) => vec![expr],
+        Expr::Binary(left, _, right) => vec![left, right],
+        Expr::Conditional(condition, then_branch, else_branch) => {
+            vec![condition, then_branch, else_branch]
+        }
+        Expr::BracketMultipleExprListBracket(exprs, _)
+        | Expr::FuncCall(_, exprs)
+        | Expr::BuiltinFuncCall(_, exprs) => exprs.iter().collect(),
+        Expr::Assign(lvalue, value) | Expr::CompoundAssign(lvalue, _, value) => {
+            let mut exprs = lvalue_children(lvalue);
+            exprs.push(value);
+            exprs
+        }
+        Expr::InputFunction(_) => vec![],
+    }
+}
+
+fn simple_statement_children(statement: &SimpleStatement) -> Vec<&Expr> {
+    match statement {
+        SimpleStatement::Delete(_, subscripts) => subscripts.iter().collect(),
+        SimpleStatement::Expr(expr) => vec![expr],
+        SimpleStatement::PrintStatement(PrintStatement::Print(exprs, _))
+        | SimpleStatement::PrintStatement(PrintStatement::Printf(exprs, _)) => {
+            exprs.iter().collect()
+        }
+    }
+}
+
+fn statement_children(statement: &Statement) -> (Vec<&Expr>, Vec<&Statement>) {
+    match statement {
+        Statement::Action(action) => {
+            (vec![], action.statements.iter().map(|statement| &statement.statement).collect())
+        }
+        Statement::If(condition, then_branch, else_branch) => {
+            let mut statements: Vec<&Statement> = vec![then_branch];
+            if let Some(else_branch) = else_branch {
+                statements.push(else_branch);
+            }
+            (vec![condition], statements)
+        }
+        Statement::While(condition, body) | Statement::Do(body, condition) => {
+            (vec![condition], vec![body])
+        }
+        Statement::For(init, condition, update, body) => {
+            let mut exprs = vec![];
+            for simple in [init, update].into_iter().flatten() {
+                exprs.extend(simple_statement_children(simple));
+            }
+            exprs.extend(condition);
+            (exprs, vec![body])
+        }
+        Statement::ForIn(_, _, body) => (vec![], vec![body]),
+        Statement::Switch(subject, cases) => {
+            let mut exprs = vec![subject];
+            exprs.extend(cases.iter().filter_map(|case| case.label.as_ref()));
+            let statements = cases
+                .iter()
+                .flat_map(|case| &case.statements)
+                .map(|statement| &statement.statement)
+                .collect();
+            (exprs, statements)
+        }
+        Statement::Exit(expr) | Statement::Return(expr) => (expr.iter().collect(), vec![]),
+        Statement::SimpleStatement(simple) => (simple_statement_children(simple), vec![]),
+        Statement::Empty | Statement::Break | Statement::Continue | Statement::Next => {
+            (vec![], vec![])
+        }
+    }
+}
+
+impl<'a> Usage<'a> {
+    fn read(&mut self, name: &str, scope: &Scope) -> Analysis<()> {
+        if scope.locals.contains(name) || scope.defined.contains(name) {
+            return Ok(());
+        }
+        if name == "NR" || name == "FNR" {
+            return Err(format!("{} depends on the order of records", name));
+        }
+        self.reads.insert(name.to_string());
+        Ok(())
+    }
+
+    fn assign(&mut self, name: &str, scope: &Scope) -> Analysis<()> {
+        if scope.locals.contains(name) {
+            return Ok(());
+        }
+        if INPUT_VARIABLES.contains(&name) {
+            return Err(format!("assigning {} changes how later records are read", name));
+        }
+        self.assigned.insert(name.to_string());
+        Ok(())
+    }
+
+    fn assign_lvalue(&mut self, lvalue: &'a LValue, scope: &mut Scope) -> Analysis<()> {
+        match lvalue {
+            LValue::Name(name) => {
+                self.assign(name, scope)?;
+                scope.defined.insert(name.clone());
+            }
+            LValue::NameOpenSquareBraceExprListCloseSquareBrace(name, subscripts) => {
+                for subscript in subscripts {
+                    self.expr(subscript, scope)?;
+                }
+                self.assign(name, scope)?;
+            }
+            LValue::DollarExpr(index) => self.expr(index, scope)?,
+        }
+        Ok(())
+    }
+
+    fn read_lvalue(&mut self, lvalue: &'a LValue, scope: &mut Scope) -> Analysis<()> {
+        if let LValue::Name(name) | LValue::NameOpenSquareBraceExprListCloseSquareBrace(name, _) =
+            lvalue
+        {
+            self.read(name, scope)?;
+        }
+        for child in lvalue_children(lvalue) {
+            self.expr(child, scope)?;
+        }
+        Ok(())
+    }
+
+    // an expression whose value is not used, where adding to a global makes it an accumulator
+    fn effect(&mut self, expr: &'a Expr, scope: &mut Scope) -> Analysis<()> {
+        let (lvalue, increment) = match accumulation(expr) {
+            Some(accumulation) => accumulation,
+            None => return self.expr(expr, scope),
+        };
+        let name = match lvalue {
+            LValue::Name(name) | LValue::NameOpenSquareBraceExprListCloseSquareBrace(name, _) => {
+                name
+            }
+            LValue::DollarExpr(_) => return self.expr(expr, scope),
+        };
+        if scope.locals.contains(name) || scope.defined.contains(name) {
+            return self.expr(expr, scope);
+        }
+        if INPUT_VARIABLES.contains(&name.as_str()) {
+            return Err(format!("assigning {} changes how later records are read", name));
+        }
+        for child in lvalue_children(lvalue).into_iter().chain(increment) {
+            self.expr(child, scope)?;
+        }
+        self.accumulated.insert(name.clone());
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &'a Expr, scope: &mut Scope) -> Analysis<()> {
+        match expr {
+            Expr::LValue(LValue::Name(name)) => self.read(name, scope),
+            Expr::LValue(LValue::NameOpenSquareBraceExprListCloseSquareBrace(name, _))
+            | Expr::In(_, name)
+            | Expr::BracketMultipleExprListBracket(_, name) => {
+                self.read(name, scope)?;
+                for child in children(expr) {
+                    self.expr(child, scope)?;
+                }
+                Ok(())
+            }
+            Expr::Assign(lvalue, value) => {
+                self.expr(value, scope)?;
+                self.assign_lvalue(lvalue, scope)
+            }
+            Expr::CompoundAssign(lvalue, _, value) => {
+                self.read_lvalue(lvalue, scope)?;
+                self.expr(value, scope)?;
+                self.assign_lvalue(lvalue, scope)
+            }
+            Expr::LValueIncr(lvalue)
+            | Expr::LValueDecr(lvalue)
+            | Expr::IncrLValue(lvalue)
+            | Expr::DecrLValue(lvalue) => {
+                self.read_lvalue(lvalue, scope)?;
+                self.assign_lvalue(lvalue, scope)
+            }
+            Expr::FuncCall(name, args) => {
+                let function = match self.functions.get(name.as_str()) {
+                    Some(function) => *function,
+                    None => return Err(format!("function {} is never defined", name)),
+                };
+                let arrays = array_params(function);
+                for (param, arg) in function.params.iter().zip(args) {
+                    if let Expr::LValue(LValue::Name(arg_name)) = arg {
+                        if arrays.contains(param) && !scope.locals.contains(arg_name) {
+                            return Err(format!(
+                                "array {} is passed to function {}",
+                                arg_name, name
+                            ));
+                        }
+                    }
+                    self.expr(arg, scope)?;
+                }
+                self.function(function)
+            }
+            Expr::BuiltinFuncCall(builtin, args) => {
+                match builtin {
+                    BuiltinFuncName::Rand | BuiltinFuncName::Srand => {
+                        return Err("rand and srand depend on the order of records".to_string())
+                    }
+                    BuiltinFuncName::System | BuiltinFuncName::Close | BuiltinFuncName::Fflush => {
+                        return Err("system, close and fflush have side effects outside the program".to_string())
+                    }
+                    // split empties the array first, which no merge can redo
+                    BuiltinFuncName::Split => {
+                        if let Some(Expr::LValue(LValue::Name(name))) = args.get(1) {
+                            if !scope.locals.contains(name) {
+                                return Err(format!("split into {} depends on the order of records", name));
+                            }
+                        }
+                    }
+                    // like split, asort and asorti empty the array they sort into
+                    BuiltinFuncName::Asort | BuiltinFuncName::Asorti => {
+                        if let Some(Expr::LValue(LValue::Name(name))) = args.last() {
+                            if !scope.locals.contains(name) {
+                                return Err(format!("sorting {} depends on the order of records", name));
+                            }
+                        }
+                    }
+                    // sub and gsub read and write their target
+                    BuiltinFuncName::Sub | BuiltinFuncName::Gsub => {
+                        if let Some(Expr::LValue(target)) = args.get(2) {
+                            self.expr(&args[2], scope)?;
+                            self.assign_lvalue(target, scope)?;
+                        }
+                    }
+                    _ => {}
+                }
+                for arg in args {
+                    self.expr(arg, scope)?;
+                }
+                Ok(())
+            }
+            Expr::InputFunction(_) => Err("getline reads records out of order".to_string()),
+            expr => {
+                for child in children(expr) {
+                    self.expr(child, scope)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn function(&mut self, function: &'a FunctionDeclaration) -> Analysis<()> {
+        if !self.analyzed_functions.insert(&function.name) {
+            return Ok(());
+        }
+        let mut scope = Scope {
+            locals: function.params.iter().cloned().collect(),
+            defined: HashSet::new(),
+        };
+        self.action(&function.body, &mut scope)
+    }
+
+    fn action(&mut self, action: &'a Action, scope: &mut Scope) -> Analysis<()> {
+        for statement in &action.statements {
+            self.statement(statement, scope)?;
+        }
+        Ok(())
+    }
+
+    fn simple_statement(&mut self, statement: &'a SimpleStatement, scope: &mut Scope) -> Analysis<()> {
+        match statement {
+            SimpleStatement::Delete(name, _) if !scope.locals.contains(name) => {
+                Err(format!("delete {} depends on the order of records", name))
+            }
+            SimpleStatement::PrintStatement(PrintStatement::Print(_, Some(_)))
+            | SimpleStatement::PrintStatement(PrintStatement::Printf(_, Some(_))) => {
+                Err("output redirection is written to by every chunk".to_string())
+            }
+            SimpleStatement::Expr(expr) => self.effect(expr, scope),
+            statement => {
+                for expr in simple_statement_children(statement) {
+                    self.expr(expr, scope)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // assignments only count as `defined` at the top level of an action,
+    // anything nested might not run for every record
+    fn statement(&mut self, statement: &'a Statement, scope: &mut Scope) -> Analysis<()> {
+        match statement {
+            Statement::Exit(_) => Err("exit stops at a record which depends on the order".to_string()),
+            Statement::SimpleStatement(simple) => self.simple_statement(simple, scope),
+            Statement::Action(action) => self.action(action, scope),
+            Statement::ForIn(key, array, body) => {
+                self.read(array, scope)?;
+                self.assign(key, scope)?;
+                let mut nested = scope.clone();
+                nested.defined.insert(key.clone());
+                self.statement(body, &mut nested)
+            }
+            Statement::For(init, condition, update, body) => {
+                if let Some(init) = init {
+                    self.simple_statement(init, scope)?;
+                }
+                let mut nested = scope.clone();
+                if let Some(condition) = condition {
+                    self.expr(condition, &mut nested)?;
+                }
+                self.statement(body, &mut nested)?;
+                if let Some(update) = update {
+                    self.simple_statement(update, &mut nested)?;
+                }
+                Ok(())
+            }
+            statement => {
+                let (exprs, statements) = statement_children(statement);
+                let mut nested = scope.clone();
+                for expr in exprs {
+                    self.expr(expr, &mut nested)?;
+                }
+                for statement in statements {
+                    let mut branch = nested.clone();
+                    self.statement(statement, &mut branch)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Decides whether the pattern-actions can run over chunks of the input in any
+// order, the error explains why not
+pub fn analyze(program: &Program) -> Result<ParallelPlan, String> {
+    let mut usage = Usage::default();
+    for item in &program.items {
+        if let Item::FunctionDeclaration(function) = item {
+            usage.functions.insert(&function.name, function);
+        }
+    }
+
+    for item in &program.items {
+        let (pattern, action) = match item {
+            Item::Action(action) => (None, Some(action)),
+            Item::PatternAction(Pattern::Begin | Pattern::End, _) => continue,
+            Item::PatternAction(Pattern::BeginFile | Pattern::EndFile, _) => {
+                return Err("BEGINFILE and ENDFILE run where the files start and end".to_string())
+            }
+            Item::PatternAction(pattern, action) => (Some(pattern), Some(action)),
+            Item::Pattern(pattern) => (Some(pattern), None),
+            Item::FunctionDeclaration(_) => continue,
+        };
+        let mut scope = Scope::default();
+        match pattern {
+            Some(Pattern::ExprCommaNewlineOptExpr(_, _)) => {
+                return Err("range patterns depend on the order of records".to_string())
+            }
+            Some(Pattern::Expr(expr)) => usage.expr(expr, &mut scope)?,
+            _ => {}
+        }
+        if let Some(action) = action {
+            usage.action(action, &mut scope)?;
+        }
+    }
+
+    for name in &usage.accumulated {
+        if usage.reads.contains(name) || usage.assigned.contains(name) {
+            return Err(format!("{} is accumulated and also used otherwise", name));
+        }
+    }
+    for name in &usage.assigned {
+        if usage.reads.contains(name) {
+            return Err(format!("{} is carried over from one record to the next", name));
+        }
+    }
+    Ok(ParallelPlan {
+        accumulators: usage.accumulated,
+        assigned: usage.assigned,
+        chunk_size: CHUNK_SIZE,
+    })
+}
+
+// up to `size` bytes, extended to the end of the record they stop in
+fn next_chunk(input: &mut dyn BufRead, separator: u8, size: usize) -> io::Result<Option<Vec<u8>>> {
+    let mut chunk = Vec::with_capacity(size);
+    input.take(size as u64).read_to_end(&mut chunk)?;
+    if chunk.is_empty() {
+        return Ok(None);
+    }
+    if chunk.last() != Some(&separator) {
+        input.read_until(separator, &mut chunk)?;
+    }
+    Ok(Some(chunk))
+}
+
+struct Job {
+    index: usize,
+    // which of the input files the chunk comes from
+    file: usize,
+    chunk: Vec<u8>,
+    filename: String,
+    // the state before the chunk, without the assigned variables so that the ones
+    // in its result are the ones it wrote
+    initial: Arc<Snapshot>,
+}
+
+struct ChunkResult {
+    index: usize,
+    file: usize,
+    output: Vec<u8>,
+    records: f64,
+    // the accumulators as the chunk left them and the assigned variables it wrote
+    variables: Result<Snapshot, RuntimeError>,
+}
+
+fn run_job(program: &Program, plan: &ParallelPlan, (json, gawk): (bool, bool), job: Job) -> ChunkResult {
+    let output = SharedOutput::default();
+    let mut interpreter = Interpreter::new(program, Box::new(output.clone()));
+    if json {
+        interpreter.set_json();
+    }
+    if gawk {
+        interpreter.set_gawk();
+    }
+    interpreter.restore(job.initial.as_ref().clone());
+    let start = interpreter.global_number("NR");
+    let result = interpreter.run_chunk(program, Box::new(Cursor::new(job.chunk)), &job.filename);
+    let variables = result.map(|()| {
+        interpreter
+            .snapshot()
+            .into_iter()
+            .filter(|(name, _)| plan.accumulators.contains(name) || plan.assigned.contains(name))
+            .collect()
+    });
+    let records = interpreter.global_number("NR") - start;
+    let output = output.0.take();
+    ChunkResult {
+        index: job.index,
+        file: job.file,
+        output,
+        records,
+        variables,
+    }
+}
+
+fn add_difference(merged: &Value, initial: &Value, chunk: &Value) -> Value {
+    if chunk == initial {
+        return merged.clone();
+    }
+    Value::Number(merged.to_number() + chunk.to_number() - initial.to_number())
+}
+
+// folds the variables of one chunk into `merged`, chunks arrive in input order
+fn merge(plan: &ParallelPlan, initial: &Snapshot, merged: &mut Snapshot, chunk: Snapshot) {
+    for (name, variable) in chunk {
+        let accumulator = plan.accumulators.contains(&name);
+        let merged_variable = merged.entry(name.clone()).or_insert_with(|| match variable {
+            VariableSnapshot::Value(_) => VariableSnapshot::Value(Value::Uninitialized),
+            VariableSnapshot::Array(_) => VariableSnapshot::Array(HashMap::new()),
+        });
+        match (merged_variable, variable, initial.get(&name)) {
+            (VariableSnapshot::Value(merged), VariableSnapshot::Value(chunk), initial) => {
+                let initial = match initial {
+                    Some(VariableSnapshot::Value(initial)) => initial.clone(),
+                    _ => Value::Uninitialized,
+                };
+                if accumulator {
+                    *merged = add_difference(merged, &initial, &chunk);
+                } else {
+                    *merged = chunk;
+                }
+            }
+            (VariableSnapshot::Array(merged), VariableSnapshot::Array(chunk), initial) => {
+                let empty = HashMap::new();
+                let initial = match initial {
+                    Some(VariableSnapshot::Array(initial)) => initial,
+                    _ => &empty,
+                };
+                for (key, value) in chunk {
+                    if accumulator {
+                        let initial_value = initial.get(&key).cloned().unwrap_or(Value::Uninitialized);
+                        let merged_value = merged.entry(key).or_insert(initial_value.clone());
+                        *merged_value = add_difference(merged_value, &initial_value, &value);
+                    } else {
+                        merged.insert(key, value);
+                    }
+                }
+            }
+            // used as a scalar by one chunk and as an array by another, the interpreter
+            // reports that when it happens inside a single chunk
+            (merged_variable, variable, _) => *merged_variable = variable,
+        }
+    }
+}
+
+struct Reducer<'p> {
+    plan: &'p ParallelPlan,
+    initial: Arc<Snapshot>,
+    // what the jobs start from
+    start: Arc<Snapshot>,
+    merged: Snapshot,
+    // results which arrived before the chunks preceding them
+    pending: BTreeMap<usize, ChunkResult>,
+    next: usize,
+    records: f64,
+    // the file of the last merged chunk and its records so far, for FNR
+    file: Option<usize>,
+    file_records: f64,
+}
+
+impl<'p> Reducer<'p> {
+    fn new(plan: &'p ParallelPlan, initial: Arc<Snapshot>) -> Reducer<'p> {
+        let start = initial
+            .iter()
+            .filter(|(name, _)| !plan.assigned.contains(*name))
+            .map(|(name, variable)| (name.clone(), variable.clone()))
+            .collect();
+        Reducer {
+            plan,
+            merged: initial.as_ref().clone(),
+            initial,
+            start: Arc::new(start),
+            pending: BTreeMap::new(),
+            next: 0,
+            records: 0.0,
+            file: None,
+            file_records: 0.0,
+        }
+    }
+
+    // writes and merges every result which is next in line
+    fn reduce(&mut self, interpreter: &mut Interpreter, result: ChunkResult) -> Result<(), RuntimeError> {
+        self.pending.insert(result.index, result);
+        while let Some(result) = self.pending.remove(&self.next) {
+            self.next += 1;
+            interpreter.write_output(&result.output)?;
+            if self.file != Some(result.file) {
+                self.file = Some(result.file);
+                self.file_records = 0.0;
+            }
+            self.records += result.records;
+            self.file_records += result.records;
+            merge(self.plan, &self.initial, &mut self.merged, result.variables?);
+        }
+        Ok(())
+    }
+
+    // hands the merged state to `interpreter`, as if it had read the records itself,
+    // `opened` is the number of files opened so far
+    fn finish(self, interpreter: &mut Interpreter, opened: usize) {
+        let start = interpreter.global_number("NR");
+        let changed: Snapshot = self
+            .merged
+            .into_iter()
+            .filter(|(name, _)| self.plan.accumulators.contains(name) || self.plan.assigned.contains(name))
+            .collect();
+        interpreter.restore(changed);
+        interpreter.set_global("NR", Value::Number(start + self.records));
+        // FNR is already 0 when the last file had no records
+        if self.file.is_some() && self.file == opened.checked_sub(1) {
+            interpreter.set_global("FNR", Value::Number(self.file_records));
+        }
+    }
+}
+
+// Replaces Interpreter::run_records: reads the operands in ARGV, sends chunks of
+// each file to `jobs` worker threads and merges the results in input order.
+// A var=value operand waits for all chunks so far, as the files after it see the new value.
+pub fn run_records(
+    interpreter: &mut Interpreter,
+    program: &Program,
+    plan: &ParallelPlan,
+    jobs: usize,
+) -> Result<(), RuntimeError> {
+    let separator = interpreter.global_string("RS").bytes().next().unwrap_or(b'\n');
+    let modes = (interpreter.json(), interpreter.gawk());
+    let (job_sender, job_receiver) = mpsc::sync_channel::<Job>(jobs * 2);
+    let job_receiver = Mutex::new(job_receiver);
+    let (result_sender, result_receiver) = mpsc::channel::<ChunkResult>();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let result_sender = result_sender.clone();
+            let job_receiver = &job_receiver;
+            scope.spawn(move || loop {
+                let job = match job_receiver.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                if result_sender.send(run_job(program, plan, modes, job)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_sender);
+
+        let result = (|| -> Result<(), RuntimeError> {
+            // the pattern-actions can not change ARGV, so it is read at once
+            let mut operands = vec![];
+            let mut index = 1;
+            while (index as f64) < interpreter.global_number("ARGC") {
+                let operand = interpreter.argv(index)?;
+                index += 1;
+                if !operand.is_empty() {
+                    operands.push((operand.clone(), operand));
+                }
+            }
+            if operands.iter().all(|(operand, _)| split_assignment(operand).is_some()) {
+                operands.push(("-".to_string(), String::new()));
+            }
+
+            let mut reducer = Reducer::new(plan, Arc::new(interpreter.snapshot()));
+            let mut sent = 0;
+            let mut opened = 0;
+            for (operand, filename) in operands {
+                if split_assignment(&operand).is_some() {
+                    // the files before the assignment are read with the old value
+                    while reducer.next < sent {
+                        let result = result_receiver.recv().expect("workers stopped early");
+                        reducer.reduce(interpreter, result)?;
+                    }
+                    reducer.finish(interpreter, opened);
+                    interpreter.command_line_assignment(&operand);
+                    reducer = Reducer::new(plan, Arc::new(interpreter.snapshot()));
+                    sent = 0;
+                    continue;
+                }
+                let mut input = interpreter.open_operand(&operand)?;
+                interpreter.set_global("FILENAME", Value::String(filename.clone()));
+                interpreter.set_global("FNR", Value::Number(0.0));
+                opened += 1;
+                while let Some(chunk) = next_chunk(input.as_mut(), separator, plan.chunk_size)? {
+                    let job = Job {
+                        index: sent,
+                        file: opened - 1,
+                        chunk,
+                        filename: filename.clone(),
+                        initial: reducer.start.clone(),
+                    };
+                    sent += 1;
+                    job_sender.send(job).expect("workers stopped early");
+                    while let Ok(result) = result_receiver.try_recv() {
+                        reducer.reduce(interpreter, result)?;
+                    }
+                }
+            }
+            while reducer.next < sent {
+                let result = result_receiver.recv().expect("workers stopped early");
+                reducer.reduce(interpreter, result)?;
+            }
+            reducer.finish(interpreter, opened);
+            Ok(())
+        })();
+        // lets the workers run out of jobs and stop
+        drop(job_sender);
+        result
+    })
+}
+
+#[cfg(test)]
+fn analyze_source(source: &str) -> Result<ParallelPlan, String> {
+    let program = crate::parser::parse(crate::lexer::tokenize(source.to_string()).unwrap()).unwrap();
+    analyze(&program)
+}
+
+// runs `source` with 3 workers and tiny chunks, returns the output
+#[cfg(test)]
+fn run_parallel(source: &str, input: &str) -> String {
+    let program = crate::parser::parse(crate::lexer::tokenize(source.to_string()).unwrap()).unwrap();
+    let mut plan = analyze(&program).expect("program should run in parallel");
+    plan.chunk_size = 8;
+    let output = SharedOutput::default();
+    let mut interpreter = Interpreter::new(&program, Box::new(output.clone()));
+    interpreter.set_stdin(Box::new(Cursor::new(input.to_string())));
+    interpreter.set_parallel(plan, 3);
+    interpreter.run(&program).unwrap();
+    let printed = output.0.take();
+    String::from_utf8(printed).unwrap()
+}
+
+#[test]
+fn test_analyze() {
+    let plan = analyze_source("{ sum = sum + $1; count[$2] = count[$2] + 1; last = $0 } END { print sum, last }").unwrap();
+    assert_eq!(plan.accumulators, HashSet::from(["sum".to_string(), "count".to_string()]));
+    assert_eq!(plan.assigned, HashSet::from(["last".to_string()]));
+    // temporaries assigned before they are read
+    assert!(analyze_source("{ x = $1 * 2; total = total + x }").is_ok());
+    assert!(analyze_source("function f(a) { return a * 2 } { total = total + f($1) }").is_ok());
+    let plan = analyze_source("{ lines++; bytes += length($0); seen[$1]--; for (i = 1; i <= NF; i++) words += 1 }").unwrap();
+    assert_eq!(
+        plan.accumulators,
+        HashSet::from(["lines", "bytes", "seen", "words"].map(String::from))
+    );
+}
+
+#[test]
+fn test_analyze_refuses_order_dependent_programs() {
+    let refused = [
+        "{ print NR, $0 }",
+        "{ if (prev != \"\") print prev; prev = $0 }",
+        "{ if ($1) x = 1; print x }",
+        "{ sum = sum + $1; print sum }",
+        "{ print count++ }",
+        "{ product *= $1 }",
+        "NR == 1, NR == 3",
+        "{ getline line }",
+        "{ FS = \",\" }",
+        "$1 == \"stop\" { exit }",
+        "{ delete seen[$1] }",
+        "function add(a, k) { a[k] = 1 } { add(seen, $1) }",
+    ];
+    for source in refused {
+        assert!(analyze_source(source).is_err(), "{} should run sequentially", source);
+    }
+}
+
+#[test]
+fn test_next_chunk() {
+    let mut input = Cursor::new("aaaa\nbb\ncccccc\nd");
+    let mut chunks = vec![];
+    while let Some(chunk) = next_chunk(&mut input, b'\n', 6).unwrap() {
+        chunks.push(String::from_utf8(chunk).unwrap());
+    }
+    assert_eq!(chunks, vec!["aaaa\nbb\n", "cccccc\n", "d"]);
+}
+
+#[test]
+fn test_parallel_matches_sequential() {
+    let input: String = (1..=50).map(|i| format!("{} k{}\n", i, i % 4)).collect();
+    let sources = [
+        "{ sum = sum + $1; count[$2] = count[$2] + 1; last = $1 } END { print sum, last, NR; for (k in count) print k, count[k] }",
+        "$1 % 7 == 0 { print \"seven\", $1 }",
+        "BEGIN { base = 100 } { x = $1 + base; total = total - x } END { print total }",
+        "{ n++; sizes[$2] += $1 } END { print n; for (k in sizes) print k, sizes[k] }",
+    ];
+    for source in sources {
+        let expected = crate::interpreter::run_source(source, &input).unwrap();
+        assert_eq!(run_parallel(source, &input), expected, "{}", source);
+    }
+    // the last chunks set the values they had before the records
+    let input = "b\n".repeat(100) + &"a\n".repeat(10);
+    let source = "BEGIN { last = \"a\"; first[1] = \"a\" } { last = $1; first[1] = $1 } END { print last, first[1] }";
+    assert_eq!(run_parallel(source, &input), "a a\n");
+}
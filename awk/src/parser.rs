@@ -0,0 +1,1293 @@
+#[cfg(test)]
+use std::fs;
+
+use crate::diagnostic::{Diagnostic, Span};
+#[cfg(test)]
+use crate::lexer::tokenize;
+use crate::syntax_tree::{
+    Action, BinaryOperator, BuiltinFuncName, Case, Expr, FunctionDeclaration, InputFunction, Item,
+    LValue, OutputRedirection, Pattern, PrintStatement, Program, SimpleGet, SimpleStatement,
+    SpannedStatement, Statement, UnaryOperator,
+};
+use crate::tokens::{SpannedToken, Token};
+
+type ParseResult<T> = Result<T, Diagnostic>;
+
+fn builtin_func_name(token: &Token) -> Option<BuiltinFuncName> {
+    match token {
+        Token::Atan2 => Some(BuiltinFuncName::Atan2),
+        Token::Cos => Some(BuiltinFuncName::Cos),
+        Token::Sin => Some(BuiltinFuncName::Sin),
+        Token::Exp => Some(BuiltinFuncName::Exp),
+        Token::Log => Some(BuiltinFuncName::Log),
+        Token::Sqrt => Some(BuiltinFuncName::Sqrt),
+        Token::Int => Some(BuiltinFuncName::Int),
+        Token::Rand => Some(BuiltinFuncName::Rand),
+        Token::Srand => Some(BuiltinFuncName::Srand),
+        Token::Gsub => Some(BuiltinFuncName::Gsub),
+        Token::Index => Some(BuiltinFuncName::Index),
+        Token::Length => Some(BuiltinFuncName::Length),
+        Token::Match => Some(BuiltinFuncName::Match),
+        Token::Split => Some(BuiltinFuncName::Split),
+        Token::Sprintf => Some(BuiltinFuncName::Sprintf),
+        Token::Sub => Some(BuiltinFuncName::Sub),
+        Token::Substr => Some(BuiltinFuncName::Substr),
+        Token::Tolower => Some(BuiltinFuncName::Tolower),
+        Token::Toupper => Some(BuiltinFuncName::Toupper),
+        Token::Close => Some(BuiltinFuncName::Close),
+        Token::System => Some(BuiltinFuncName::System),
+        Token::Fflush => Some(BuiltinFuncName::Fflush),
+        Token::Gensub => Some(BuiltinFuncName::Gensub),
+        Token::Asort => Some(BuiltinFuncName::Asort),
+        Token::Asorti => Some(BuiltinFuncName::Asorti),
+        Token::Strftime => Some(BuiltinFuncName::Strftime),
+        Token::Systime => Some(BuiltinFuncName::Systime),
+        _ => None,
+    }
+}
+
+fn comparison_operator(token: &Token) -> Option<BinaryOperator> {
+    match token {
+        Token::LessThan => Some(BinaryOperator::LessThan),
+        Token::Le => Some(BinaryOperator::Le),
+        Token::Ne => Some(BinaryOperator::Ne),
+        Token::Eq => Some(BinaryOperator::Eq),
+        Token::GreaterThan => Some(BinaryOperator::GreaterThan),
+        Token::Ge => Some(BinaryOperator::Ge),
+        _ => None,
+    }
+}
+
+fn compound_assign_operator(token: &Token) -> Option<BinaryOperator> {
+    match token {
+        Token::RaiseToAssign => Some(BinaryOperator::RaiseTo),
+        Token::ModulusAssign => Some(BinaryOperator::Modulus),
+        Token::MultiplyAssign => Some(BinaryOperator::Multiply),
+        Token::DivideAssign => Some(BinaryOperator::Divide),
+        Token::AddAssign => Some(BinaryOperator::Add),
+        Token::SubstractAssign => Some(BinaryOperator::Substract),
+        _ => None,
+    }
+}
+
+// tokens which can start the right hand side of a concatenation
+// '+' and '-' are left out as `a -1` is a subtraction
+fn starts_concatenation(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Number(_)
+            | Token::Literal(_)
+            | Token::Ere(_)
+            | Token::Name(_)
+            | Token::FuncName(_)
+            | Token::Dollar
+            | Token::OpenBrace
+            | Token::Incr
+            | Token::Decr
+    ) || builtin_func_name(token).is_some()
+}
+
+pub struct Parser {
+    tokens: Vec<SpannedToken>,
+    position: usize,
+    // inside an unparenthesized print expression list '>' and '|' are redirections
+    in_print: bool,
+    // where each item of the program starts, for --pretty-print to place comments
+    item_spans: Vec<Span>,
+}
+
+impl Parser {
+    pub fn new(tokens: Vec<SpannedToken>) -> Parser {
+        Parser {
+            tokens,
+            position: 0,
+            in_print: false,
+            item_spans: vec![],
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.position + n).map(|spanned| &spanned.token)
+    }
+
+    fn check(&self, token: &Token) -> bool {
+        self.peek() == Some(token)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.check(token) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    // at the end of the program the error points at the last token
+    fn error(&self, expected: &'static str) -> Diagnostic {
+        match self.tokens.get(self.position) {
+            Some(found) => Diagnostic::new(
+                format!("unexpected {}, expecting {}", found.token, expected),
+                found.span,
+            ),
+            None => Diagnostic::new(
+                format!("unexpected end of program, expecting {}", expected),
+                self.tokens.last().map(|last| last.span).unwrap_or_default(),
+            ),
+        }
+    }
+
+    fn expect(&mut self, token: &Token, expected: &'static str) -> ParseResult<()> {
+        if self.eat(token) {
+            return Ok(());
+        }
+        Err(self.error(expected))
+    }
+
+    fn expect_name(&mut self) -> ParseResult<String> {
+        match self.peek() {
+            Some(Token::Name(name)) | Some(Token::FuncName(name)) => {
+                let name = name.iter().collect();
+                self.position += 1;
+                Ok(name)
+            }
+            _ => Err(self.error("name")),
+        }
+    }
+
+    // newline_opt
+    fn skip_newlines(&mut self) {
+        while self.eat(&Token::Newline) {}
+    }
+
+    fn skip_terminators(&mut self) {
+        while self.eat(&Token::Newline) || self.eat(&Token::SemiColon) {}
+    }
+
+    fn at_statement_end(&self) -> bool {
+        matches!(
+            self.peek(),
+            None | Some(Token::Newline) | Some(Token::SemiColon) | Some(Token::CloseCurlyBrace)
+        )
+    }
+
+    fn expect_statement_end(&self) -> ParseResult<()> {
+        if self.at_statement_end() {
+            return Ok(());
+        }
+        Err(self.error("newline, ';' or '}'"))
+    }
+
+    pub fn parse_program(&mut self) -> ParseResult<Program> {
+        let mut items = vec![];
+        self.skip_terminators();
+        while self.peek().is_some() {
+            let span = self.tokens.get(self.position).map(|token| token.span).unwrap_or_default();
+            self.item_spans.push(span);
+            let item = self.parse_item()?;
+            let needs_terminator = matches!(item, Item::Pattern(_));
+            items.push(item);
+            if needs_terminator && self.peek().is_some() && !self.check(&Token::Newline) {
+                self.expect(&Token::SemiColon, "newline or ';'")?;
+            }
+            self.skip_terminators();
+        }
+        Ok(Program { items })
+    }
+
+    fn parse_item(&mut self) -> ParseResult<Item> {
+        match self.peek() {
+            Some(Token::Function) => {
+                self.advance();
+                self.parse_function_declaration()
+            }
+            Some(Token::Begin) => {
+                self.advance();
+                Ok(Item::PatternAction(Pattern::Begin, self.parse_action()?))
+            }
+            Some(Token::End) => {
+                self.advance();
+                Ok(Item::PatternAction(Pattern::End, self.parse_action()?))
+            }
+            Some(Token::BeginFile) => {
+                self.advance();
+                Ok(Item::PatternAction(Pattern::BeginFile, self.parse_action()?))
+            }
+            Some(Token::EndFile) => {
+                self.advance();
+                Ok(Item::PatternAction(Pattern::EndFile, self.parse_action()?))
+            }
+            Some(Token::OpenCurlyBrace) => Ok(Item::Action(self.parse_action()?)),
+            _ => {
+                let expr = self.parse_expr()?;
+                let pattern = if self.eat(&Token::Comma) {
+                    self.skip_newlines();
+                    Pattern::ExprCommaNewlineOptExpr(expr, self.parse_expr()?)
+                } else {
+                    Pattern::Expr(expr)
+                };
+                if self.check(&Token::OpenCurlyBrace) {
+                    return Ok(Item::PatternAction(pattern, self.parse_action()?));
+                }
+                Ok(Item::Pattern(pattern))
+            }
+        }
+    }
+
+    fn parse_function_declaration(&mut self) -> ParseResult<Item> {
+        let name = self.expect_name()?;
+        self.expect(&Token::OpenBrace, "'('")?;
+        let mut params = vec![];
+        if !self.check(&Token::CloseBrace) {
+            params.push(self.expect_name()?);
+            while self.eat(&Token::Comma) {
+                self.skip_newlines();
+                params.push(self.expect_name()?);
+            }
+        }
+        self.expect(&Token::CloseBrace, "')'")?;
+        self.skip_newlines();
+        let body = self.parse_action()?;
+        Ok(Item::FunctionDeclaration(FunctionDeclaration {
+            name,
+            params,
+            body,
+        }))
+    }
+
+    fn parse_action(&mut self) -> ParseResult<Action> {
+        self.expect(&Token::OpenCurlyBrace, "'{'")?;
+        let mut statements = vec![];
+        loop {
+            self.skip_terminators();
+            if self.eat(&Token::CloseCurlyBrace) {
+                break;
+            }
+            if self.peek().is_none() {
+                return Err(self.error("'}'"));
+            }
+            statements.push(self.parse_statement()?);
+        }
+        Ok(Action { statements })
+    }
+
+    // a statement with the span of its first token
+    fn parse_statement(&mut self) -> ParseResult<SpannedStatement> {
+        let span = self.tokens.get(self.position).map(|token| token.span).unwrap_or_default();
+        Ok(SpannedStatement::new(self.parse_bare_statement()?, span))
+    }
+
+    fn parse_bare_statement(&mut self) -> ParseResult<Statement> {
+        match self.peek() {
+            Some(Token::OpenCurlyBrace) => Ok(Statement::Action(self.parse_action()?)),
+            Some(Token::SemiColon) => {
+                self.advance();
+                Ok(Statement::Empty)
+            }
+            Some(Token::If) => {
+                self.advance();
+                self.parse_if()
+            }
+            Some(Token::While) => {
+                self.advance();
+                let condition = self.parse_condition()?;
+                self.skip_newlines();
+                let body = self.parse_statement()?;
+                Ok(Statement::While(condition, Box::new(body)))
+            }
+            Some(Token::Do) => {
+                self.advance();
+                self.skip_newlines();
+                let body = self.parse_statement()?;
+                self.skip_terminators();
+                self.expect(&Token::While, "'while'")?;
+                let condition = self.parse_condition()?;
+                self.expect_statement_end()?;
+                Ok(Statement::Do(Box::new(body), condition))
+            }
+            Some(Token::For) => {
+                self.advance();
+                self.parse_for()
+            }
+            Some(Token::Switch) => {
+                self.advance();
+                self.parse_switch()
+            }
+            _ => {
+                let statement = self.parse_terminatable_statement()?;
+                self.expect_statement_end()?;
+                Ok(statement)
+            }
+        }
+    }
+
+    // '(' expr ')'
+    fn parse_condition(&mut self) -> ParseResult<Expr> {
+        self.expect(&Token::OpenBrace, "'('")?;
+        let condition = self.parse_expr()?;
+        self.expect(&Token::CloseBrace, "')'")?;
+        Ok(condition)
+    }
+
+    fn parse_if(&mut self) -> ParseResult<Statement> {
+        let condition = self.parse_condition()?;
+        self.skip_newlines();
+        let then_branch = self.parse_statement()?;
+
+        // else may follow the terminator of the then branch
+        let position = self.position;
+        self.skip_newlines();
+        self.eat(&Token::SemiColon);
+        self.skip_newlines();
+        if self.eat(&Token::Else) {
+            self.skip_newlines();
+            let else_branch = self.parse_statement()?;
+            return Ok(Statement::If(
+                condition,
+                Box::new(then_branch),
+                Some(Box::new(else_branch)),
+            ));
+        }
+        self.position = position;
+        Ok(Statement::If(condition, Box::new(then_branch), None))
+    }
+
+    fn parse_for(&mut self) -> ParseResult<Statement> {
+        self.expect(&Token::OpenBrace, "'('")?;
+
+        // for (NAME in NAME)
+        if let (
+            Some(Token::Name(_)),
+            Some(Token::In),
+            Some(Token::Name(_)),
+            Some(Token::CloseBrace),
+        ) = (
+            self.peek(),
+            self.peek_nth(1),
+            self.peek_nth(2),
+            self.peek_nth(3),
+        ) {
+            let key = self.expect_name()?;
+            self.advance();
+            let array = self.expect_name()?;
+            self.advance();
+            self.skip_newlines();
+            let body = self.parse_statement()?;
+            return Ok(Statement::ForIn(key, array, Box::new(body)));
+        }
+
+        let init = if self.check(&Token::SemiColon) {
+            None
+        } else {
+            Some(self.parse_simple_statement()?)
+        };
+        self.expect(&Token::SemiColon, "';'")?;
+        self.skip_newlines();
+        let condition = if self.check(&Token::SemiColon) {
+            None
+        } else {
+            Some(self.parse_expr()?)
+        };
+        self.expect(&Token::SemiColon, "';'")?;
+        self.skip_newlines();
+        let update = if self.check(&Token::CloseBrace) {
+            None
+        } else {
+            Some(self.parse_simple_statement()?)
+        };
+        self.expect(&Token::CloseBrace, "')'")?;
+        self.skip_newlines();
+        let body = self.parse_statement()?;
+        Ok(Statement::For(init, condition, update, Box::new(body)))
+    }
+
+    fn parse_switch(&mut self) -> ParseResult<Statement> {
+        let subject = self.parse_condition()?;
+        self.skip_newlines();
+        self.expect(&Token::OpenCurlyBrace, "'{'")?;
+        let mut cases: Vec<Case> = vec![];
+        loop {
+            self.skip_terminators();
+            if self.eat(&Token::CloseCurlyBrace) {
+                break;
+            }
+            let label = match self.peek() {
+                Some(Token::Case) => {
+                    self.advance();
+                    Some(self.parse_case_label()?)
+                }
+                Some(Token::Default) if cases.iter().any(|case| case.label.is_none()) => {
+                    return Err(Diagnostic::new(
+                        "more than one default in switch",
+                        self.tokens[self.position].span,
+                    ));
+                }
+                Some(Token::Default) => {
+                    self.advance();
+                    None
+                }
+                _ => return Err(self.error("'case', 'default' or '}'")),
+            };
+            self.expect(&Token::Colon, "':'")?;
+            let mut statements = vec![];
+            loop {
+                self.skip_terminators();
+                if matches!(
+                    self.peek(),
+                    None | Some(Token::Case) | Some(Token::Default) | Some(Token::CloseCurlyBrace)
+                ) {
+                    break;
+                }
+                statements.push(self.parse_statement()?);
+            }
+            cases.push(Case { label, statements });
+        }
+        Ok(Statement::Switch(subject, cases))
+    }
+
+    // case labels are constants: a number, maybe negative, a string or a regex
+    fn parse_case_label(&mut self) -> ParseResult<Expr> {
+        let negative = self.check(&Token::Substract);
+        if negative {
+            self.advance();
+        }
+        match self.peek() {
+            Some(Token::Number(number)) => {
+                let number = if negative { -number } else { *number };
+                self.advance();
+                Ok(Expr::Number(number))
+            }
+            Some(Token::Literal(text)) if !negative => {
+                let text = text.iter().collect();
+                self.advance();
+                Ok(Expr::String(text))
+            }
+            Some(Token::Ere(ere)) if !negative => {
+                let ere = ere.iter().collect();
+                self.advance();
+                Ok(Expr::Ere(ere))
+            }
+            _ => Err(self.error("number, string or regular expression")),
+        }
+    }
+
+    fn parse_expr_opt(&mut self) -> ParseResult<Option<Expr>> {
+        if self.at_statement_end() {
+            return Ok(None);
+        }
+        Ok(Some(self.parse_expr()?))
+    }
+
+    fn parse_terminatable_statement(&mut self) -> ParseResult<Statement> {
+        match self.peek() {
+            Some(Token::Break) => {
+                self.advance();
+                Ok(Statement::Break)
+            }
+            Some(Token::Continue) => {
+                self.advance();
+                Ok(Statement::Continue)
+            }
+            Some(Token::Next) => {
+                self.advance();
+                Ok(Statement::Next)
+            }
+            Some(Token::Exit) => {
+                self.advance();
+                Ok(Statement::Exit(self.parse_expr_opt()?))
+            }
+            Some(Token::Return) => {
+                self.advance();
+                Ok(Statement::Return(self.parse_expr_opt()?))
+            }
+            _ => Ok(Statement::SimpleStatement(self.parse_simple_statement()?)),
+        }
+    }
+
+    fn parse_simple_statement(&mut self) -> ParseResult<SimpleStatement> {
+        match self.peek() {
+            Some(Token::Delete) => {
+                self.advance();
+                let name = self.expect_name()?;
+                // delete NAME removes every element
+                if self.peek() != Some(&Token::OpenSquareBrace) {
+                    return Ok(SimpleStatement::Delete(name, vec![]));
+                }
+                self.advance();
+                let subscripts = self.parse_expr_list()?;
+                self.expect(&Token::CloseSquareBrace, "']'")?;
+                Ok(SimpleStatement::Delete(name, subscripts))
+            }
+            Some(Token::Print) => {
+                self.advance();
+                let (exprs, redirection) = self.parse_print_arguments()?;
+                Ok(SimpleStatement::PrintStatement(PrintStatement::Print(
+                    exprs,
+                    redirection,
+                )))
+            }
+            Some(Token::Printf) => {
+                self.advance();
+                let (exprs, redirection) = self.parse_print_arguments()?;
+                if exprs.is_empty() {
+                    return Err(self.error("format for printf"));
+                }
+                Ok(SimpleStatement::PrintStatement(PrintStatement::Printf(
+                    exprs,
+                    redirection,
+                )))
+            }
+            _ => Ok(SimpleStatement::Expr(self.parse_expr()?)),
+        }
+    }
+
+    fn at_print_end(&self) -> bool {
+        self.at_statement_end()
+            || matches!(
+                self.peek(),
+                Some(Token::GreaterThan) | Some(Token::Append) | Some(Token::Bar)
+            )
+    }
+
+    fn parse_print_arguments(&mut self) -> ParseResult<(Vec<Expr>, Option<OutputRedirection>)> {
+        let mut exprs = vec![];
+
+        // print (a, b) > "file" - the parenthesis belong to the statement
+        // unless something other than a redirection follows them
+        let mut parenthesized = false;
+        if self.check(&Token::OpenBrace) {
+            let position = self.position;
+            self.advance();
+            let list = self.parse_expr_list()?;
+            if self.eat(&Token::CloseBrace) && self.at_print_end() {
+                exprs = list;
+                parenthesized = true;
+            } else {
+                self.position = position;
+            }
+        }
+
+        if !parenthesized && !self.at_print_end() {
+            self.in_print = true;
+            let list = self.parse_print_expr_list();
+            self.in_print = false;
+            exprs = list?;
+        }
+
+        let redirection = match self.peek() {
+            Some(Token::GreaterThan) => {
+                self.advance();
+                Some(OutputRedirection::GreaterThan(
+                    self.parse_redirection_target()?,
+                ))
+            }
+            Some(Token::Append) => {
+                self.advance();
+                Some(OutputRedirection::Append(self.parse_redirection_target()?))
+            }
+            Some(Token::Bar) => {
+                self.advance();
+                Some(OutputRedirection::Pipe(self.parse_redirection_target()?))
+            }
+            _ => None,
+        };
+        Ok((exprs, redirection))
+    }
+
+    fn parse_print_expr_list(&mut self) -> ParseResult<Vec<Expr>> {
+        let mut exprs = vec![self.parse_expr()?];
+        while self.eat(&Token::Comma) {
+            self.skip_newlines();
+            exprs.push(self.parse_expr()?);
+        }
+        Ok(exprs)
+    }
+
+    fn parse_redirection_target(&mut self) -> ParseResult<Expr> {
+        self.in_print = true;
+        let target = self.parse_concatenation();
+        self.in_print = false;
+        target
+    }
+
+    // expr_list - also used for parenthesized lists, so '>' is a comparison again
+    fn parse_expr_list(&mut self) -> ParseResult<Vec<Expr>> {
+        let in_print = std::mem::replace(&mut self.in_print, false);
+        let exprs = self.parse_print_expr_list();
+        self.in_print = in_print;
+        exprs
+    }
+
+    pub fn parse_expr(&mut self) -> ParseResult<Expr> {
+        self.parse_ternary()
+    }
+
+    // lowest precedence: ?: and assignments, both right associative
+    fn parse_ternary(&mut self) -> ParseResult<Expr> {
+        let condition = self.parse_or()?;
+        if self.eat(&Token::Question) {
+            self.skip_newlines();
+            let then_branch = self.parse_ternary()?;
+            self.skip_newlines();
+            self.expect(&Token::Colon, "':'")?;
+            self.skip_newlines();
+            let else_branch = self.parse_ternary()?;
+            return Ok(Expr::Conditional(
+                Box::new(condition),
+                Box::new(then_branch),
+                Box::new(else_branch),
+            ));
+        }
+        if let Expr::LValue(lvalue) = condition {
+            if self.eat(&Token::Equal) {
+                self.skip_newlines();
+                let value = self.parse_ternary()?;
+                return Ok(Expr::Assign(lvalue, Box::new(value)));
+            }
+            if let Some(operator) = self.peek().and_then(compound_assign_operator) {
+                self.advance();
+                self.skip_newlines();
+                let value = self.parse_ternary()?;
+                return Ok(Expr::CompoundAssign(lvalue, operator, Box::new(value)));
+            }
+            return Ok(Expr::LValue(lvalue));
+        }
+        Ok(condition)
+    }
+
+    fn parse_or(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_and()?;
+        while self.eat(&Token::Or) {
+            self.skip_newlines();
+            let right = self.parse_and()?;
+            left = Expr::Binary(Box::new(left), BinaryOperator::Or, Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_in()?;
+        while self.eat(&Token::And) {
+            self.skip_newlines();
+            let right = self.parse_in()?;
+            left = Expr::Binary(Box::new(left), BinaryOperator::And, Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_in(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_match()?;
+        while self.eat(&Token::In) {
+            let array = self.expect_name()?;
+            left = Expr::In(Box::new(left), array);
+        }
+        Ok(left)
+    }
+
+    fn parse_match(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_comparison()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Tilde) => BinaryOperator::Match,
+                Some(Token::NoMatch) => BinaryOperator::NoMatch,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.parse_comparison()?;
+            left = Expr::Binary(Box::new(left), operator, Box::new(right));
+        }
+    }
+
+    // comparisons are non associative
+    fn parse_comparison(&mut self) -> ParseResult<Expr> {
+        let left = self.parse_pipe_getline()?;
+        let operator = match self.peek().and_then(comparison_operator) {
+            Some(BinaryOperator::GreaterThan) if self.in_print => return Ok(left),
+            Some(operator) => operator,
+            None => return Ok(left),
+        };
+        self.advance();
+        let right = self.parse_pipe_getline()?;
+        Ok(Expr::Binary(Box::new(left), operator, Box::new(right)))
+    }
+
+    // expr | getline [lvalue]
+    fn parse_pipe_getline(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_concatenation()?;
+        while !self.in_print && self.check(&Token::Bar) && self.peek_nth(1) == Some(&Token::Getline)
+        {
+            self.position += 2;
+            let get = self.parse_simple_get()?;
+            left = Expr::InputFunction(InputFunction::ExprBarSimpleGet(Box::new(left), get));
+        }
+        Ok(left)
+    }
+
+    fn parse_concatenation(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_additive()?;
+        while self.peek().is_some_and(starts_concatenation) {
+            // `x in arr` inside a concatenation belongs to the lower precedence level
+            let right = self.parse_additive()?;
+            left = Expr::Binary(Box::new(left), BinaryOperator::Concat, Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_additive(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_multiplicative()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Add) => BinaryOperator::Add,
+                Some(Token::Substract) => BinaryOperator::Substract,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.parse_multiplicative()?;
+            left = Expr::Binary(Box::new(left), operator, Box::new(right));
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Multiply) => BinaryOperator::Multiply,
+                Some(Token::Divide) => BinaryOperator::Divide,
+                Some(Token::Modulus) => BinaryOperator::Modulus,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.parse_unary()?;
+            left = Expr::Binary(Box::new(left), operator, Box::new(right));
+        }
+    }
+
+    fn parse_unary(&mut self) -> ParseResult<Expr> {
+        let operator = match self.peek() {
+            Some(Token::Invert) => UnaryOperator::Invert,
+            Some(Token::Substract) => UnaryOperator::Minus,
+            Some(Token::Add) => UnaryOperator::Plus,
+            _ => return self.parse_power(),
+        };
+        self.advance();
+        let operand = self.parse_unary()?;
+        Ok(Expr::Unary(operator, Box::new(operand)))
+    }
+
+    // '^' is right associative and binds tighter than unary minus: -2^2 == -4
+    fn parse_power(&mut self) -> ParseResult<Expr> {
+        let base = self.parse_primary()?;
+        if self.eat(&Token::RaiseTo) {
+            let exponent = self.parse_unary_exponent()?;
+            return Ok(Expr::Binary(
+                Box::new(base),
+                BinaryOperator::RaiseTo,
+                Box::new(exponent),
+            ));
+        }
+        Ok(base)
+    }
+
+    // 2^-1 is allowed, the sign applies to the exponent
+    fn parse_unary_exponent(&mut self) -> ParseResult<Expr> {
+        let operator = match self.peek() {
+            Some(Token::Substract) => UnaryOperator::Minus,
+            Some(Token::Add) => UnaryOperator::Plus,
+            Some(Token::Invert) => UnaryOperator::Invert,
+            _ => return self.parse_power(),
+        };
+        self.advance();
+        let operand = self.parse_unary_exponent()?;
+        Ok(Expr::Unary(operator, Box::new(operand)))
+    }
+
+    // lvalue++ and lvalue--, `$i++` increments the field
+    fn parse_primary(&mut self) -> ParseResult<Expr> {
+        let operand = self.parse_operand()?;
+        if let Expr::LValue(lvalue) = operand {
+            if self.eat(&Token::Incr) {
+                return Ok(Expr::LValueIncr(lvalue));
+            }
+            if self.eat(&Token::Decr) {
+                return Ok(Expr::LValueDecr(lvalue));
+            }
+            return Ok(Expr::LValue(lvalue));
+        }
+        Ok(operand)
+    }
+
+    fn parse_operand(&mut self) -> ParseResult<Expr> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return Err(self.error("expression")),
+        };
+        match token {
+            Token::Number(number) => {
+                self.advance();
+                Ok(Expr::Number(number))
+            }
+            Token::Literal(literal) => {
+                self.advance();
+                Ok(Expr::String(literal.iter().collect()))
+            }
+            Token::Ere(ere) => {
+                self.advance();
+                Ok(Expr::Ere(ere.iter().collect()))
+            }
+            Token::OpenBrace => {
+                self.advance();
+                self.parse_grouping()
+            }
+            Token::Dollar | Token::Name(_) => Ok(Expr::LValue(self.parse_lvalue()?)),
+            Token::Incr => {
+                self.advance();
+                Ok(Expr::IncrLValue(self.parse_lvalue()?))
+            }
+            Token::Decr => {
+                self.advance();
+                Ok(Expr::DecrLValue(self.parse_lvalue()?))
+            }
+            Token::FuncName(name) => {
+                self.advance();
+                self.expect(&Token::OpenBrace, "'('")?;
+                let args = self.parse_call_arguments()?;
+                Ok(Expr::FuncCall(name.iter().collect(), args))
+            }
+            Token::Getline => {
+                self.advance();
+                let get = self.parse_simple_get()?;
+                if self.eat(&Token::LessThan) {
+                    let file = self.parse_primary()?;
+                    return Ok(Expr::InputFunction(InputFunction::SimpleGetLessThanExpr(
+                        get,
+                        Box::new(file),
+                    )));
+                }
+                Ok(Expr::InputFunction(InputFunction::SimpleGet(get)))
+            }
+            token => match builtin_func_name(&token) {
+                Some(builtin) => {
+                    self.advance();
+                    if self.eat(&Token::OpenBrace) {
+                        let args = self.parse_call_arguments()?;
+                        return Ok(Expr::BuiltinFuncCall(builtin, args));
+                    }
+                    if builtin != BuiltinFuncName::Length {
+                        return Err(self.error("'('"));
+                    }
+                    Ok(Expr::BuiltinFuncCall(builtin, vec![]))
+                }
+                None => Err(self.error("expression")),
+            },
+        }
+    }
+
+    // after '(' - either a grouping or the subscript list of `(a, b) in arr`
+    fn parse_grouping(&mut self) -> ParseResult<Expr> {
+        let mut exprs = self.parse_expr_list()?;
+        self.expect(&Token::CloseBrace, "')'")?;
+        if exprs.len() == 1 {
+            return Ok(Expr::Grouping(Box::new(exprs.remove(0))));
+        }
+        self.expect(&Token::In, "'in'")?;
+        let array = self.expect_name()?;
+        Ok(Expr::BracketMultipleExprListBracket(exprs, array))
+    }
+
+    // after '(' of a function call
+    fn parse_call_arguments(&mut self) -> ParseResult<Vec<Expr>> {
+        if self.eat(&Token::CloseBrace) {
+            return Ok(vec![]);
+        }
+        let args = self.parse_expr_list()?;
+        self.expect(&Token::CloseBrace, "')'")?;
+        Ok(args)
+    }
+
+    fn parse_lvalue(&mut self) -> ParseResult<LValue> {
+        if self.eat(&Token::Dollar) {
+            let index = self.parse_field_index()?;
+            return Ok(LValue::DollarExpr(Box::new(index)));
+        }
+        let name = self.expect_name()?;
+        if self.eat(&Token::OpenSquareBrace) {
+            let subscripts = self.parse_expr_list()?;
+            self.expect(&Token::CloseSquareBrace, "']'")?;
+            return Ok(LValue::NameOpenSquareBraceExprListCloseSquareBrace(
+                name, subscripts,
+            ));
+        }
+        Ok(LValue::Name(name))
+    }
+
+    // after '$', signs and '!' are allowed but '$' still binds tighter than the
+    // binary operators: $-1 is $(-1) and $i+1 is ($i)+1
+    fn parse_field_index(&mut self) -> ParseResult<Expr> {
+        let operator = match self.peek() {
+            Some(Token::Substract) => UnaryOperator::Minus,
+            Some(Token::Add) => UnaryOperator::Plus,
+            Some(Token::Invert) => UnaryOperator::Invert,
+            _ => return self.parse_operand(),
+        };
+        self.advance();
+        let operand = self.parse_field_index()?;
+        Ok(Expr::Unary(operator, Box::new(operand)))
+    }
+
+    // after getline
+    fn parse_simple_get(&mut self) -> ParseResult<SimpleGet> {
+        match self.peek() {
+            Some(Token::Dollar) | Some(Token::Name(_)) => {
+                Ok(SimpleGet::GetlineLValue(self.parse_lvalue()?))
+            }
+            _ => Ok(SimpleGet::Getline),
+        }
+    }
+}
+
+pub fn parse(tokens: Vec<SpannedToken>) -> Result<Program, Diagnostic> {
+    Parser::new(tokens).parse_program()
+}
+
+// the program and the span of the first token of each of its items
+pub fn parse_with_item_spans(tokens: Vec<SpannedToken>) -> Result<(Program, Vec<Span>), Diagnostic> {
+    let mut parser = Parser::new(tokens);
+    let program = parser.parse_program()?;
+    Ok((program, parser.item_spans))
+}
+
+// a lone expression, like the ones the debugger prints and watches
+pub fn parse_expr(tokens: Vec<SpannedToken>) -> Result<Expr, Diagnostic> {
+    let mut parser = Parser::new(tokens);
+    let expr = parser.parse_expr()?;
+    if parser.peek().is_some() {
+        return Err(parser.error("end of expression"));
+    }
+    Ok(expr)
+}
+
+#[cfg(test)]
+fn parse_source(source: &str) -> Result<Program, Diagnostic> {
+    parse(tokenize(source.to_string())?)
+}
+
+#[cfg(test)]
+fn parse_expression(source: &str) -> Expr {
+    let program = parse_source(source).expect("expression should parse");
+    match &program.items[0] {
+        Item::Pattern(Pattern::Expr(expr)) => expr.clone(),
+        item => panic!("expected an expression pattern, found {:?}", item),
+    }
+}
+
+#[cfg(test)]
+fn number(n: f64) -> Box<Expr> {
+    Box::new(Expr::Number(n))
+}
+
+#[test]
+fn test_sample_1() {
+    let sample_1 =
+        fs::read_to_string("./tests/mocks/sample_1.awk").expect("Unable to read sample_1.txt");
+    let parsed_1 = parse_source(&sample_1).unwrap();
+    insta::assert_json_snapshot!(parsed_1);
+}
+
+#[test]
+fn test_sample_2() {
+    let sample_2 =
+        fs::read_to_string("./tests/mocks/sample_2.awk").expect("Unable to read sample_2.txt");
+    let parsed_2 = parse_source(&sample_2).unwrap();
+    insta::assert_json_snapshot!(parsed_2);
+}
+
+#[test]
+fn test_sample_3() {
+    let sample_3 =
+        fs::read_to_string("./tests/mocks/sample_3.awk").expect("Unable to read sample_3.txt");
+    let parsed_3 = parse_source(&sample_3).unwrap();
+    insta::assert_json_snapshot!(parsed_3);
+}
+
+#[test]
+fn test_sample_4() {
+    let sample_4 =
+        fs::read_to_string("./tests/mocks/sample_4.awk").expect("Unable to read sample_4.txt");
+    let parsed_4 = parse_source(&sample_4).unwrap();
+    insta::assert_json_snapshot!(parsed_4);
+}
+
+#[test]
+fn test_sample_5() {
+    let sample_5 =
+        fs::read_to_string("./tests/mocks/sample_5.awk").expect("Unable to read sample_5.txt");
+    let parsed_5 = parse_source(&sample_5).unwrap();
+    insta::assert_json_snapshot!(parsed_5);
+}
+
+#[test]
+fn test_sample_6() {
+    let sample_6 =
+        fs::read_to_string("./tests/mocks/sample_6.awk").expect("Unable to read sample_6.txt");
+    let parsed_6 = parse_source(&sample_6).unwrap();
+    insta::assert_json_snapshot!(parsed_6);
+}
+
+#[test]
+fn test_increment_binds_to_the_field() {
+    // $i++ is ($i)++, $++i increments i
+    assert_eq!(
+        parse_expression("$i++"),
+        Expr::LValueIncr(LValue::DollarExpr(Box::new(Expr::LValue(LValue::Name(
+            "i".to_string()
+        )))))
+    );
+    assert_eq!(
+        parse_expression("$++i"),
+        Expr::LValue(LValue::DollarExpr(Box::new(Expr::IncrLValue(LValue::Name(
+            "i".to_string()
+        )))))
+    );
+    // $-1 is $(-1), $-x+1 is ($(-x))+1
+    let minus = |operand| Box::new(Expr::Unary(UnaryOperator::Minus, operand));
+    assert_eq!(parse_expression("$-1"), Expr::LValue(LValue::DollarExpr(minus(number(1.0)))));
+    assert_eq!(
+        parse_expression("$-x+1"),
+        Expr::Binary(
+            Box::new(Expr::LValue(LValue::DollarExpr(minus(Box::new(Expr::LValue(LValue::Name(
+                "x".to_string()
+            ))))))),
+            BinaryOperator::Add,
+            number(1.0)
+        )
+    );
+    assert_eq!(
+        parse_expression("$!0"),
+        Expr::LValue(LValue::DollarExpr(Box::new(Expr::Unary(UnaryOperator::Invert, number(0.0)))))
+    );
+    assert_eq!(
+        parse_expression("x -= 2"),
+        Expr::CompoundAssign(
+            LValue::Name("x".to_string()),
+            BinaryOperator::Substract,
+            number(2.0)
+        )
+    );
+}
+
+#[test]
+fn test_precedence() {
+    // 1 + 2 * 3
+    assert_eq!(
+        parse_expression("1 + 2 * 3"),
+        Expr::Binary(
+            number(1.0),
+            BinaryOperator::Add,
+            Box::new(Expr::Binary(
+                number(2.0),
+                BinaryOperator::Multiply,
+                number(3.0)
+            ))
+        )
+    );
+    // -2 ^ 2 is -(2 ^ 2)
+    assert_eq!(
+        parse_expression("- 2 ^ 2"),
+        Expr::Unary(
+            UnaryOperator::Minus,
+            Box::new(Expr::Binary(
+                number(2.0),
+                BinaryOperator::RaiseTo,
+                number(2.0)
+            ))
+        )
+    );
+    // 2 ^ 3 ^ 2 is 2 ^ (3 ^ 2)
+    assert_eq!(
+        parse_expression("2 ^ 3 ^ 2"),
+        Expr::Binary(
+            number(2.0),
+            BinaryOperator::RaiseTo,
+            Box::new(Expr::Binary(
+                number(3.0),
+                BinaryOperator::RaiseTo,
+                number(2.0)
+            ))
+        )
+    );
+    // concatenation binds looser than additive
+    assert_eq!(
+        parse_expression("1 \"a\" 2 - 1"),
+        Expr::Binary(
+            Box::new(Expr::Binary(
+                number(1.0),
+                BinaryOperator::Concat,
+                Box::new(Expr::String("a".to_string()))
+            )),
+            BinaryOperator::Concat,
+            Box::new(Expr::Binary(
+                number(2.0),
+                BinaryOperator::Substract,
+                number(1.0)
+            ))
+        )
+    );
+}
+
+#[test]
+fn test_match_precedence() {
+    // $1 ~ "a" "b" is $1 ~ ("a" "b"), and ~ binds looser than comparisons
+    assert_eq!(
+        parse_expression("$1 ~ \"a\" \"b\""),
+        Expr::Binary(
+            Box::new(Expr::LValue(LValue::DollarExpr(number(1.0)))),
+            BinaryOperator::Match,
+            Box::new(Expr::Binary(
+                Box::new(Expr::String("a".to_string())),
+                BinaryOperator::Concat,
+                Box::new(Expr::String("b".to_string()))
+            ))
+        )
+    );
+    assert_eq!(
+        parse_expression("x !~ /re/"),
+        Expr::Binary(
+            Box::new(Expr::LValue(LValue::Name("x".to_string()))),
+            BinaryOperator::NoMatch,
+            Box::new(Expr::Ere("re".to_string()))
+        )
+    );
+}
+
+#[test]
+fn test_print_redirection() {
+    let program = parse_source("{ print a, b > \"out\" }").unwrap();
+    let expected =
+        Statement::SimpleStatement(SimpleStatement::PrintStatement(PrintStatement::Print(
+            vec![
+                Expr::LValue(LValue::Name("a".to_string())),
+                Expr::LValue(LValue::Name("b".to_string())),
+            ],
+            Some(OutputRedirection::GreaterThan(Expr::String(
+                "out".to_string(),
+            ))),
+        )));
+    assert_eq!(
+        program.items,
+        vec![Item::Action(Action {
+            statements: vec![SpannedStatement::new(expected, Span::default())]
+        })]
+    );
+}
+
+#[test]
+fn test_if_else_on_one_line() {
+    let program = parse_source("{ if (x) print 1; else print 2 }").unwrap();
+    match &program.items[0] {
+        Item::Action(action) => {
+            assert!(matches!(action.statements[0].statement, Statement::If(_, _, Some(_))))
+        }
+        item => panic!("unexpected item {:?}", item),
+    }
+}
+
+#[test]
+fn test_for_in_and_multiple_subscripts() {
+    let program = parse_source("{ for (k in arr) if ((k, 1) in seen) delete arr[k] }").unwrap();
+    insta::assert_json_snapshot!(program);
+}
+
+#[test]
+fn test_delete_array() {
+    let program = parse_source("{ delete arr; delete arr[1] }").unwrap();
+    let Item::Action(action) = &program.items[0] else {
+        panic!("expected an action");
+    };
+    let deletes: Vec<&Statement> = action.statements.iter().map(|statement| &statement.statement).collect();
+    assert_eq!(
+        deletes,
+        [
+            &Statement::SimpleStatement(SimpleStatement::Delete("arr".to_string(), vec![])),
+            &Statement::SimpleStatement(SimpleStatement::Delete("arr".to_string(), vec![Expr::Number(1.0)])),
+        ]
+    );
+}
+
+#[test]
+fn test_errors() {
+    assert_eq!(
+        parse_source("{ print 1"),
+        Err(Diagnostic::new(
+            "unexpected end of program, expecting '}'",
+            Span::new(1, 9)
+        ))
+    );
+    assert_eq!(
+        parse_source("BEGIN {\n  x = }"),
+        Err(Diagnostic::new(
+            "unexpected '}', expecting expression",
+            Span::new(2, 7)
+        ))
+    );
+    assert_eq!(
+        parse_source("{ print \"abc }"),
+        Err(Diagnostic::new("unterminated string", Span::new(1, 9)))
+    );
+    assert_eq!(
+        parse_source("{ x = 1 @ 2 }"),
+        Err(Diagnostic::new("unexpected '@'", Span::new(1, 9)))
+    );
+}
+
+#[test]
+fn test_statement_spans() {
+    let program = parse_source("{ x = 1; if (x)\n\tprint x\n  else { y++ } }").unwrap();
+    let Item::Action(action) = &program.items[0] else {
+        panic!("expected an action")
+    };
+    let spans: Vec<Span> = action.statements.iter().map(|statement| statement.span).collect();
+    assert_eq!(spans, [Span::new(1, 3), Span::new(1, 10)]);
+    let Statement::If(_, then_branch, Some(else_branch)) = &action.statements[1].statement else {
+        panic!("expected if with else")
+    };
+    assert_eq!(then_branch.span, Span::new(2, 2));
+    assert_eq!(else_branch.span, Span::new(3, 8));
+}
+
+#[test]
+fn test_switch() {
+    let parse_gawk = |source: &str| crate::lexer::tokenize_with(source.to_string(), true).and_then(parse);
+    let program = parse_gawk("{ switch ($1) {\ncase -1: case \"a\":\n  x = 1\n  break\ncase /b+/: y\ndefault:\n} }").unwrap();
+    let Item::Action(action) = &program.items[0] else {
+        panic!("expected an action");
+    };
+    let Statement::Switch(subject, cases) = &action.statements[0].statement else {
+        panic!("expected a switch");
+    };
+    assert_eq!(*subject, Expr::LValue(LValue::DollarExpr(number(1.0))));
+    let labels: Vec<Option<Expr>> = cases.iter().map(|case| case.label.clone()).collect();
+    assert_eq!(
+        labels,
+        vec![Some(Expr::Number(-1.0)), Some(Expr::String("a".to_string())), Some(Expr::Ere("b+".to_string())), None]
+    );
+    let counts: Vec<usize> = cases.iter().map(|case| case.statements.len()).collect();
+    assert_eq!(counts, vec![0, 2, 1, 0]);
+
+    let error = parse_gawk("{ switch (x) { default: ; default: } }").unwrap_err();
+    assert_eq!(error, Diagnostic::new("more than one default in switch", Span::new(1, 27)));
+    let error = parse_gawk("{ switch (x) { case y: } }").unwrap_err();
+    assert_eq!(error.message, "unexpected name y, expecting number, string or regular expression");
+    let error = parse_gawk("{ switch (x) { print } }").unwrap_err();
+    assert_eq!(error.message, "unexpected 'print', expecting 'case', 'default' or '}'");
+}
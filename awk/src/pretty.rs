@@ -0,0 +1,602 @@
+// Prints a syntax tree back as an awk program for --pretty-print. Groupings are
+// kept as they were written and no parenthesis is added or dropped, so the
+// output parses to the same tree; comments are not in the tree, they are put back
+// before the item or statement that follows them or at the end of its line.
+use crate::diagnostic::Span;
+use crate::interpreter::builtin_name;
+use crate::lexer::Comment;
+use crate::syntax_tree::{
+    Action, BinaryOperator, Expr, InputFunction, Item, LValue, OutputRedirection, Pattern,
+    PrintStatement, Program, SimpleGet, SimpleStatement, SpannedStatement, Statement,
+    UnaryOperator,
+};
+
+const INDENT: &str = "    ";
+
+// what goes before a line, given the span of the statement starting on it
+pub type Margin<'a> = &'a dyn Fn(Option<Span>) -> String;
+
+pub fn pretty_print(program: &Program) -> String {
+    pretty_print_with_comments(program, &[], &[])
+}
+
+// `item_spans` are where the items start in the source `comments` come from
+pub fn pretty_print_with_comments(program: &Program, item_spans: &[Span], comments: &[Comment]) -> String {
+    let mut printer = Printer {
+        out: String::new(),
+        depth: 0,
+        margin: None,
+        anchors: vec![],
+        braces: vec![],
+    };
+    for (index, item) in program.items.iter().enumerate() {
+        if index > 0 {
+            printer.out.push('\n');
+        }
+        if let Some(span) = item_spans.get(index) {
+            printer.anchor(span.line);
+        }
+        printer.item(item);
+    }
+    with_comments(printer, comments)
+}
+
+// a trailing comment goes at the end of the line of the '}' it followed, or of the
+// first output line of the last statement that started on its line, or of the line
+// before what follows it when nothing started there, like `} else {`; any other
+// comment goes before the first item or statement after it, or at the very end
+fn with_comments(printer: Printer, comments: &[Comment]) -> String {
+    let Printer { out, anchors, braces, .. } = printer;
+    let mut insertions = vec![];
+    for comment in comments {
+        let on_line = anchors.iter().rev().find(|anchor| anchor.line == comment.span.line);
+        let after = anchors.iter().find(|anchor| anchor.line > comment.span.line);
+        let line_end = |offset: usize| out[offset..].find('\n').map_or(out.len(), |end| offset + end);
+        if let Some(&brace) = comment.after_brace.and_then(|index| braces.get(index)) {
+            insertions.push((line_end(brace), format!(" {}", comment.text)));
+        } else if let (true, Some(anchor)) = (comment.trailing, on_line) {
+            insertions.push((line_end(anchor.offset), format!(" {}", comment.text)));
+        } else if comment.trailing {
+            let end = out[..after.map_or(out.len(), |anchor| anchor.offset)].trim_end_matches('\n').len();
+            insertions.push((end, format!(" {}", comment.text)));
+        } else if let Some(anchor) = after {
+            insertions.push((anchor.offset, format!("{}{}\n", INDENT.repeat(anchor.depth), comment.text)));
+        } else {
+            insertions.push((out.len(), format!("{}\n", comment.text)));
+        }
+    }
+    insertions.sort_by_key(|(offset, _)| *offset);
+    let mut result = String::new();
+    let mut copied = 0;
+    for (offset, text) in insertions {
+        result.push_str(&out[copied..offset]);
+        result.push_str(&text);
+        copied = offset;
+    }
+    result.push_str(&out[copied..]);
+    result
+}
+
+// `action` from its '{' to its '}', with `margin` before every line after the first,
+// for the profiler to put counts next to the statements
+pub fn block_with_margin(action: &Action, margin: Margin) -> String {
+    let mut printer = Printer {
+        out: String::new(),
+        depth: 0,
+        margin: Some(margin),
+        anchors: vec![],
+        braces: vec![],
+    };
+    printer.block(action);
+    printer.out
+}
+
+struct Printer<'a> {
+    out: String,
+    depth: usize,
+    margin: Option<Margin<'a>>,
+    anchors: Vec<Anchor>,
+    // where the output is right after each '}'
+    braces: Vec<usize>,
+}
+
+// where the output of what started on a source line begins
+struct Anchor {
+    line: usize,
+    offset: usize,
+    depth: usize,
+}
+
+impl Printer<'_> {
+    fn anchor(&mut self, line: usize) {
+        self.anchors.push(Anchor {
+            line,
+            offset: self.out.len(),
+            depth: self.depth,
+        });
+    }
+
+    fn start_line(&mut self, span: Option<Span>) {
+        if let Some(margin) = self.margin {
+            self.out.push_str(&margin(span));
+        }
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn item(&mut self, item: &Item) {
+        match item {
+            Item::Action(action) => self.block(action),
+            Item::PatternAction(pattern, action) => {
+                self.out.push_str(&pattern_text(pattern));
+                self.out.push(' ');
+                self.block(action);
+            }
+            Item::Pattern(pattern) => self.out.push_str(&pattern_text(pattern)),
+            Item::FunctionDeclaration(function) => {
+                self.out.push_str(&format!("function {}({}) ", function.name, function.params.join(", ")));
+                self.block(&function.body);
+            }
+        }
+        self.out.push('\n');
+    }
+
+    // `{`, the statements one level deeper and `}`, without a newline after it
+    fn block(&mut self, action: &Action) {
+        self.out.push_str("{\n");
+        self.depth += 1;
+        for statement in &action.statements {
+            self.statement(statement);
+        }
+        self.depth -= 1;
+        self.start_line(None);
+        self.out.push('}');
+        self.braces.push(self.out.len());
+    }
+
+    fn statement(&mut self, statement: &SpannedStatement) {
+        self.anchor(statement.span.line);
+        self.start_line(Some(statement.span));
+        self.unindented(statement);
+    }
+
+    // a whole statement once the line is indented
+    fn unindented(&mut self, statement: &Statement) {
+        let line = match statement {
+            Statement::Action(action) => {
+                self.block(action);
+                self.out.push('\n');
+                return;
+            }
+            Statement::If(condition, then_branch, else_branch) => {
+                self.out.push_str(&format!("if ({})", expr_text(condition)));
+                let open = self.body(then_branch);
+                let Some(else_branch) = else_branch else {
+                    if open {
+                        self.out.push('\n');
+                    }
+                    return;
+                };
+                if open {
+                    self.out.push(' ');
+                } else {
+                    self.start_line(None);
+                }
+                self.out.push_str("else");
+                if let Statement::If(..) = &else_branch.statement {
+                    self.out.push(' ');
+                    self.unindented(else_branch);
+                } else if self.body(else_branch) {
+                    self.out.push('\n');
+                }
+                return;
+            }
+            Statement::While(condition, body) => {
+                self.out.push_str(&format!("while ({})", expr_text(condition)));
+                if self.body(body) {
+                    self.out.push('\n');
+                }
+                return;
+            }
+            Statement::Do(body, condition) => {
+                self.out.push_str("do");
+                if self.body(body) {
+                    self.out.push(' ');
+                } else {
+                    self.start_line(None);
+                }
+                self.out.push_str(&format!("while ({})\n", expr_text(condition)));
+                return;
+            }
+            Statement::For(init, condition, update, body) => {
+                let init = init.as_ref().map(simple_text).unwrap_or_default();
+                let condition = condition.as_ref().map(expr_text).unwrap_or_default();
+                let update = update.as_ref().map(simple_text).unwrap_or_default();
+                self.out.push_str(&format!("for ({}; {}; {})", init, condition, update));
+                if self.body(body) {
+                    self.out.push('\n');
+                }
+                return;
+            }
+            Statement::ForIn(key, array, body) => {
+                self.out.push_str(&format!("for ({} in {})", key, array));
+                if self.body(body) {
+                    self.out.push('\n');
+                }
+                return;
+            }
+            // the cases line up with the switch, their statements one level deeper
+            Statement::Switch(subject, cases) => {
+                self.out.push_str(&format!("switch ({}) {{\n", expr_text(subject)));
+                for case in cases {
+                    self.start_line(None);
+                    match &case.label {
+                        Some(label) => self.out.push_str(&format!("case {}:\n", expr_text(label))),
+                        None => self.out.push_str("default:\n"),
+                    }
+                    self.depth += 1;
+                    for statement in &case.statements {
+                        self.statement(statement);
+                    }
+                    self.depth -= 1;
+                }
+                self.start_line(None);
+                self.out.push('}');
+                self.braces.push(self.out.len());
+                self.out.push('\n');
+                return;
+            }
+            Statement::Empty => ";".to_string(),
+            Statement::Break => "break".to_string(),
+            Statement::Continue => "continue".to_string(),
+            Statement::Next => "next".to_string(),
+            Statement::Exit(None) => "exit".to_string(),
+            Statement::Exit(Some(code)) => format!("exit {}", expr_text(code)),
+            Statement::Return(None) => "return".to_string(),
+            Statement::Return(Some(value)) => format!("return {}", expr_text(value)),
+            Statement::SimpleStatement(simple) => simple_text(simple),
+        };
+        self.out.push_str(&line);
+        self.out.push('\n');
+    }
+
+    // the body of if, else, while, do and for: a block stays on the line of the
+    // keyword and leaves it open, anything else goes one level deeper on the next
+    fn body(&mut self, statement: &SpannedStatement) -> bool {
+        if let Statement::Action(action) = &statement.statement {
+            self.out.push(' ');
+            self.block(action);
+            return true;
+        }
+        self.out.push('\n');
+        self.depth += 1;
+        self.statement(statement);
+        self.depth -= 1;
+        false
+    }
+}
+
+pub fn pattern_text(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Begin => "BEGIN".to_string(),
+        Pattern::End => "END".to_string(),
+        Pattern::BeginFile => "BEGINFILE".to_string(),
+        Pattern::EndFile => "ENDFILE".to_string(),
+        Pattern::Expr(expr) => expr_text(expr),
+        Pattern::ExprCommaNewlineOptExpr(start, end) => {
+            format!("{}, {}", expr_text(start), expr_text(end))
+        }
+    }
+}
+
+fn simple_text(statement: &SimpleStatement) -> String {
+    match statement {
+        SimpleStatement::Expr(expr) => expr_text(expr),
+        SimpleStatement::Delete(name, subscripts) if subscripts.is_empty() => format!("delete {}", name),
+        SimpleStatement::Delete(name, subscripts) => {
+            format!("delete {}[{}]", name, list_text(subscripts))
+        }
+        SimpleStatement::PrintStatement(PrintStatement::Print(exprs, redirection)) => {
+            print_text("print", exprs, redirection)
+        }
+        SimpleStatement::PrintStatement(PrintStatement::Printf(exprs, redirection)) => {
+            print_text("printf", exprs, redirection)
+        }
+    }
+}
+
+fn print_text(keyword: &str, exprs: &[Expr], redirection: &Option<OutputRedirection>) -> String {
+    let mut text = keyword.to_string();
+    if !exprs.is_empty() {
+        let list = list_text(exprs);
+        // a list starting with '(' would lose its first grouping to the statement, and
+        // a bare '>' or '|' would become a redirection, both only parse back in parenthesis
+        if list.starts_with('(') || exprs.iter().any(redirects) {
+            text.push_str(&format!(" ({})", list));
+        } else {
+            text.push(' ');
+            text.push_str(&list);
+        }
+    }
+    match redirection {
+        Some(OutputRedirection::GreaterThan(target)) => text.push_str(&format!(" > {}", expr_text(target))),
+        Some(OutputRedirection::Append(target)) => text.push_str(&format!(" >> {}", expr_text(target))),
+        Some(OutputRedirection::Pipe(target)) => text.push_str(&format!(" | {}", expr_text(target))),
+        None => {}
+    }
+    text
+}
+
+// whether `expr` has a '>' or '|' outside of parenthesis and brackets
+fn redirects(expr: &Expr) -> bool {
+    match expr {
+        Expr::Binary(_, BinaryOperator::GreaterThan, _)
+        | Expr::InputFunction(InputFunction::ExprBarSimpleGet(..)) => true,
+        Expr::Binary(left, _, right) => redirects(left) || redirects(right),
+        Expr::Unary(_, operand) => redirects(operand),
+        Expr::Conditional(condition, then_branch, else_branch) => {
+            redirects(condition) || redirects(then_branch) || redirects(else_branch)
+        }
+        Expr::In(subscript, _) => redirects(subscript),
+        Expr::Assign(lvalue, value) | Expr::CompoundAssign(lvalue, _, value) => {
+            lvalue_redirects(lvalue) || redirects(value)
+        }
+        Expr::LValue(lvalue)
+        | Expr::LValueIncr(lvalue)
+        | Expr::LValueDecr(lvalue)
+        | Expr::IncrLValue(lvalue)
+        | Expr::DecrLValue(lvalue) => lvalue_redirects(lvalue),
+        Expr::InputFunction(InputFunction::SimpleGetLessThanExpr(_, file)) => redirects(file),
+        _ => false,
+    }
+}
+
+fn lvalue_redirects(lvalue: &LValue) -> bool {
+    match lvalue {
+        LValue::DollarExpr(index) => redirects(index),
+        _ => false,
+    }
+}
+
+fn list_text(exprs: &[Expr]) -> String {
+    exprs.iter().map(expr_text).collect::<Vec<_>>().join(", ")
+}
+
+fn number_text(number: f64) -> String {
+    if number.is_infinite() {
+        // too large for a double, like the literal it came from
+        return "1e999".to_string();
+    }
+    if number != 0.0 && !(1e-4..1e16).contains(&number.abs()) {
+        return format!("{:e}", number);
+    }
+    number.to_string()
+}
+
+// the literal whose unescaped text is `text`
+pub fn string_text(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            '\r' => literal.push_str("\\r"),
+            c if c.is_ascii_control() => literal.push_str(&format!("\\{:03o}", c as u32)),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+fn lvalue_text(lvalue: &LValue) -> String {
+    match lvalue {
+        LValue::Name(name) => name.clone(),
+        LValue::NameOpenSquareBraceExprListCloseSquareBrace(name, subscripts) => {
+            format!("{}[{}]", name, list_text(subscripts))
+        }
+        LValue::DollarExpr(index) => format!("${}", expr_text(index)),
+    }
+}
+
+fn get_text(get: &SimpleGet) -> String {
+    match get {
+        SimpleGet::Getline => "getline".to_string(),
+        SimpleGet::GetlineLValue(lvalue) => format!("getline {}", lvalue_text(lvalue)),
+    }
+}
+
+fn binary_symbol(operator: &BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::RaiseTo => "^",
+        BinaryOperator::Multiply => "*",
+        BinaryOperator::Divide => "/",
+        BinaryOperator::Modulus => "%",
+        BinaryOperator::Add => "+",
+        BinaryOperator::Substract => "-",
+        BinaryOperator::Concat => "",
+        BinaryOperator::LessThan => "<",
+        BinaryOperator::Le => "<=",
+        BinaryOperator::Ne => "!=",
+        BinaryOperator::Eq => "==",
+        BinaryOperator::GreaterThan => ">",
+        BinaryOperator::Ge => ">=",
+        BinaryOperator::Match => "~",
+        BinaryOperator::NoMatch => "!~",
+        BinaryOperator::And => "&&",
+        BinaryOperator::Or => "||",
+    }
+}
+
+pub fn expr_text(expr: &Expr) -> String {
+    match expr {
+        Expr::Number(number) => number_text(*number),
+        Expr::String(text) => string_text(text),
+        Expr::Ere(ere) => format!("/{}/", ere),
+        Expr::LValue(lvalue) => lvalue_text(lvalue),
+        Expr::Grouping(expr) => format!("({})", expr_text(expr)),
+        Expr::Unary(operator, operand) => {
+            let symbol = match operator {
+                UnaryOperator::Plus => "+",
+                UnaryOperator::Minus => "-",
+                UnaryOperator::Invert => "!",
+            };
+            let operand = expr_text(operand);
+            // - -x, not the decrement --x
+            if operand.starts_with(symbol) && *operator != UnaryOperator::Invert {
+                format!("{} {}", symbol, operand)
+            } else {
+                format!("{}{}", symbol, operand)
+            }
+        }
+        Expr::Binary(left, BinaryOperator::Concat, right) => {
+            format!("{} {}", expr_text(left), expr_text(right))
+        }
+        Expr::Binary(left, operator, right) => {
+            format!("{} {} {}", expr_text(left), binary_symbol(operator), expr_text(right))
+        }
+        Expr::Conditional(condition, then_branch, else_branch) => format!(
+            "{} ? {} : {}",
+            expr_text(condition),
+            expr_text(then_branch),
+            expr_text(else_branch)
+        ),
+        Expr::In(subscript, array) => format!("{} in {}", expr_text(subscript), array),
+        Expr::BracketMultipleExprListBracket(subscripts, array) => {
+            format!("({}) in {}", list_text(subscripts), array)
+        }
+        Expr::Assign(lvalue, value) => format!("{} = {}", lvalue_text(lvalue), expr_text(value)),
+        Expr::CompoundAssign(lvalue, operator, value) => format!(
+            "{} {}= {}",
+            lvalue_text(lvalue),
+            binary_symbol(operator),
+            expr_text(value)
+        ),
+        Expr::LValueIncr(lvalue) => format!("{}++", lvalue_text(lvalue)),
+        Expr::LValueDecr(lvalue) => format!("{}--", lvalue_text(lvalue)),
+        Expr::IncrLValue(lvalue) => format!("++{}", lvalue_text(lvalue)),
+        Expr::DecrLValue(lvalue) => format!("--{}", lvalue_text(lvalue)),
+        Expr::FuncCall(name, args) => format!("{}({})", name, list_text(args)),
+        Expr::BuiltinFuncCall(builtin, args) => {
+            format!("{}({})", builtin_name(builtin), list_text(args))
+        }
+        Expr::InputFunction(InputFunction::SimpleGet(get)) => get_text(get),
+        Expr::InputFunction(InputFunction::SimpleGetLessThanExpr(get, file)) => {
+            format!("{} < {}", get_text(get), expr_text(file))
+        }
+        Expr::InputFunction(InputFunction::ExprBarSimpleGet(command, get)) => {
+            format!("{} | {}", expr_text(command), get_text(get))
+        }
+    }
+}
+
+#[cfg(test)]
+fn parse_source(source: &str) -> Program {
+    let tokens = crate::lexer::tokenize(source.to_string()).expect("program should lex");
+    crate::parser::parse(tokens).expect("program should parse")
+}
+
+#[cfg(test)]
+fn assert_round_trip(source: &str) {
+    let program = parse_source(source);
+    let pretty = pretty_print(&program);
+    assert_eq!(parse_source(&pretty), program, "{}", pretty);
+    // printing is a fixed point
+    assert_eq!(pretty_print(&parse_source(&pretty)), pretty);
+}
+
+#[test]
+fn test_round_trip_mocks() {
+    for sample in 1..=6 {
+        let source = std::fs::read_to_string(format!("./tests/mocks/sample_{}.awk", sample))
+            .expect("Unable to read mock");
+        assert_round_trip(&source);
+    }
+}
+
+#[cfg(test)]
+fn pretty_with_comments(source: &str) -> String {
+    let (tokens, comments) =
+        crate::lexer::tokenize_with_comments(source.to_string(), false).expect("program should lex");
+    let (program, item_spans) = crate::parser::parse_with_item_spans(tokens).expect("program should parse");
+    pretty_print_with_comments(&program, &item_spans, &comments)
+}
+
+#[test]
+fn test_round_trip_comments() {
+    let source = std::fs::read_to_string("./tests/mocks/sample_6.awk").expect("Unable to read mock");
+    let pretty = pretty_with_comments(&source);
+    for comment in ["# counts the words of every line", "# none seen yet", "# fields are words", "# done"] {
+        assert!(pretty.contains(comment), "{} is lost in\n{}", comment, pretty);
+    }
+    assert_eq!(parse_source(&pretty), parse_source(&source), "{}", pretty);
+    assert_eq!(pretty_with_comments(&pretty), pretty);
+    assert_eq!(
+        pretty_with_comments(
+            "# one\nBEGIN {\n    # two\n    if (x) { # three\n        y = 1\n    } # five\n    # four\n}\n"
+        ),
+        "# one\nBEGIN {\n    # two\n    if (x) { # three\n        y = 1\n    } # five\n}\n# four\n"
+    );
+}
+
+#[test]
+fn test_round_trip() {
+    let sources = [
+        "BEGIN { x = - -1; y = !!x; z = - --x; w = 2 ^ -3 ^ 2; print -x ^ 2, (a, b) in c }",
+        "{ print (1 > 2), 3 > \"/dev/null\"; print ((1)), (2) | \"cat\"; printf(\"%s\\n\", $1) >> \"f\" }",
+        "{ print (\"cmd\" | getline line) }\n{ \"date\" | getline; getline x < \"file\"; getline $(NF + 1) }",
+        "{ if (a) if (b) print 1; else print 2 }\n{ if (a) { if (b) print 1 } else if (c) print 2; else { print 3 } }",
+        "BEGIN { do x++; while (x < 3); do { x-- } while (x); while (y) ; for (;;) break; for (i = 0; i < 3; i++) continue }",
+        "function f(a, b,   c) { delete a[b, c]; delete a; for (k in a) return k; return }\nNR == 1, /end\\/$/\n$1 ~ \"^a\" { next }\nEND { exit 1 }",
+        "BEGIN { s = \"q\\\"uote\\\\ \\/ tab\\t nl\\n bell\\a \\033[0m\"; n = 1e300 + 0.00001 + 1e-5 + 0x1F + 1.5 }",
+        "{ a[$1, $2] += length; b = length() length($0) substr($0, 2); $3 = c ? d : e; x = y = z }",
+        "{ a /= 2; a = 1 / 2 / 3; print $NF $i++ $++i; print a \" \" (b) }",
+        "{ print $-x, $+2 + 1, $!0, $-1; $-x++ }",
+    ];
+    for source in sources {
+        assert_round_trip(source);
+    }
+}
+
+#[test]
+fn test_pretty_print() {
+    let source = "function max(a,b){return a>b?a:b}
+BEGIN{FS=\":\"}
+/^#/{next}
+{if($2>m){m=$2;name=$1}else if(!$2)empty++;else{n++}
+while(i<3)i++}
+END{print name,max(m,0)>\"/dev/stderr\"}";
+    let expected = "function max(a, b) {
+    return a > b ? a : b
+}
+
+BEGIN {
+    FS = \":\"
+}
+
+/^#/ {
+    next
+}
+
+{
+    if ($2 > m) {
+        m = $2
+        name = $1
+    } else if (!$2)
+        empty++
+    else {
+        n++
+    }
+    while (i < 3)
+        i++
+}
+
+END {
+    print name, max(m, 0) > \"/dev/stderr\"
+}
+";
+    assert_eq!(pretty_print(&parse_source(source)), expected);
+}
@@ -0,0 +1,236 @@
+// --profile: how often statements ran, patterns were tested and functions were called,
+// and the time spent in actions and functions, written next to the program as
+// --pretty-print lays it out, the way gawk's profiler does.
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::diagnostic::Span;
+use crate::pretty::{block_with_margin, pattern_text};
+use crate::syntax_tree::{Action, Item, Pattern, Program};
+
+// the file written when --profile names none, as in gawk
+pub const DEFAULT_FILE: &str = "awkprof.out";
+
+const HEADER: &str = "# awk profile: count is the number of times a statement ran, a pattern
+# was tested or a function was called, # n after a pattern the number of times it
+# matched; time is the time spent in the pattern-action or function, calls included";
+
+#[derive(Debug, Default)]
+pub struct Profile {
+    // statements by the span of their first token
+    statements: HashMap<Span, u64>,
+    // pattern-actions by their index in the program
+    items: HashMap<usize, ItemCounts>,
+    functions: HashMap<String, FunctionCounts>,
+}
+
+#[derive(Debug, Default)]
+struct ItemCounts {
+    tested: u64,
+    matched: u64,
+    time: Duration,
+}
+
+#[derive(Debug, Default)]
+struct FunctionCounts {
+    calls: u64,
+    time: Duration,
+}
+
+impl Profile {
+    pub fn statement(&mut self, span: Span) {
+        *self.statements.entry(span).or_default() += 1;
+    }
+
+    // the item at `index` was tested (or run, for BEGIN, END and plain actions)
+    pub fn item(&mut self, index: usize, matched: bool, time: Duration) {
+        let counts = self.items.entry(index).or_default();
+        counts.tested += 1;
+        counts.matched += matched as u64;
+        counts.time += time;
+    }
+
+    pub fn call(&mut self, name: &str, time: Duration) {
+        let counts = self.functions.entry(name.to_string()).or_default();
+        counts.calls += 1;
+        counts.time += time;
+    }
+
+    // the program with the counts and times in a margin, BEGIN, BEGINFILE, other
+    // pattern-actions, ENDFILE and END grouped, then the functions by name
+    pub fn report(&self, program: &Program) -> String {
+        let mut begin = vec![];
+        let mut begin_file = vec![];
+        let mut rules = vec![];
+        let mut end_file = vec![];
+        let mut end = vec![];
+        let mut functions = vec![];
+        for (index, item) in program.items.iter().enumerate() {
+            let counts = self.items.get(&index);
+            let (tested, time) = match counts {
+                Some(counts) => (counts.tested.to_string(), milliseconds(counts.time)),
+                None => (String::new(), String::new()),
+            };
+            let matched = counts.map_or(0, |counts| counts.matched);
+            let (section, text) = match item {
+                Item::PatternAction(Pattern::Begin, action) => {
+                    (&mut begin, format!("BEGIN {}", self.block(action)))
+                }
+                Item::PatternAction(Pattern::End, action) => {
+                    (&mut end, format!("END {}", self.block(action)))
+                }
+                Item::PatternAction(Pattern::BeginFile, action) => {
+                    (&mut begin_file, format!("BEGINFILE {}", self.block(action)))
+                }
+                Item::PatternAction(Pattern::EndFile, action) => {
+                    (&mut end_file, format!("ENDFILE {}", self.block(action)))
+                }
+                Item::Action(action) => (&mut rules, self.block(action)),
+                Item::PatternAction(pattern, action) => {
+                    // "{ # n" leaves the rest of the block as it was
+                    let block = self.block(action);
+                    let text = format!("{} {{ # {}{}", pattern_text(pattern), matched, &block[1..]);
+                    (&mut rules, text)
+                }
+                Item::Pattern(pattern) => (&mut rules, format!("{} # {}", pattern_text(pattern), matched)),
+                Item::FunctionDeclaration(function) => {
+                    let text = format!(
+                        "function {}({}) {}",
+                        function.name,
+                        function.params.join(", "),
+                        self.block(&function.body)
+                    );
+                    let (calls, time) = match self.functions.get(&function.name) {
+                        Some(counts) => (counts.calls.to_string(), milliseconds(counts.time)),
+                        None => (String::new(), String::new()),
+                    };
+                    functions.push((&function.name, format!("{}{}", margin(&calls, &time), text)));
+                    continue;
+                }
+            };
+            section.push(format!("{}{}", margin(&tested, &time), text));
+        }
+        functions.sort();
+
+        let mut out = format!("{}\n\n{}\n", HEADER, margin("count", "time").trim_end());
+        let sections = [
+            ("BEGIN rule(s)", begin),
+            ("BEGINFILE rule(s)", begin_file),
+            ("Rule(s)", rules),
+            ("ENDFILE rule(s)", end_file),
+            ("END rule(s)", end),
+            ("Functions, listed alphabetically", functions.into_iter().map(|(_, text)| text).collect()),
+        ];
+        for (title, texts) in sections {
+            if texts.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("\n# {}\n", title));
+            for text in texts {
+                out.push_str(&format!("\n{}\n", text));
+            }
+        }
+        out
+    }
+
+    fn block(&self, action: &Action) -> String {
+        let margin = |span: Option<Span>| {
+            let count = span
+                .and_then(|span| self.statements.get(&span))
+                .map_or(String::new(), u64::to_string);
+            margin(&count, "")
+        };
+        block_with_margin(action, &margin)
+    }
+}
+
+fn margin(count: &str, time: &str) -> String {
+    format!("{:>10} {:>12}  ", count, time)
+}
+
+fn milliseconds(time: Duration) -> String {
+    format!("{:.3}ms", time.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+fn profile_report(source: &str, input: &str) -> String {
+    use crate::interpreter::{Interpreter, SharedOutput};
+    use crate::{lexer, parser};
+
+    let tokens = lexer::tokenize(source.to_string()).expect("program should lex");
+    let program = parser::parse(tokens).expect("program should parse");
+    let mut interpreter = Interpreter::new(&program, Box::new(SharedOutput::default()));
+    interpreter.set_stdin(Box::new(std::io::Cursor::new(input.to_string())));
+    interpreter.set_profile();
+    interpreter.run(&program).unwrap();
+    let report = interpreter.take_profile().unwrap().report(&program);
+    // the times change from run to run, T keeps the width of the column
+    let time = regex::Regex::new(r"\d+\.\d{3}ms").unwrap();
+    let masked = time.replace_all(&report, |time: &regex::Captures| format!("{:>1$}", "T", time[0].len()));
+    masked.to_string()
+}
+
+#[test]
+fn test_profile_report() {
+    let source = "function unused() { }
+function add(a, b) { return a + b }
+END { print total, big, small }
+$1 > 1 {
+    total = add(total, $1)
+    if (total > 4) big++; else small++
+}
+BEGIN { FS = \":\" }
+NR == 2";
+    let report = profile_report(source, "1:a\n2:b\n3:c\n");
+    let expected = "
+# BEGIN rule(s)
+
+         1            T  BEGIN {
+         1                   FS = \":\"
+                         }
+
+# Rule(s)
+
+         3            T  $1 > 1 { # 2
+         2                   total = add(total, $1)
+         2                   if (total > 4)
+         1                       big++
+                             else
+         1                       small++
+                         }
+
+         3            T  NR == 2 # 1
+
+# END rule(s)
+
+         1            T  END {
+         1                   print total, big, small
+                         }
+
+# Functions, listed alphabetically
+
+         2            T  function add(a, b) {
+         2                   return a + b
+                         }
+
+                         function unused() {
+                         }
+";
+    assert_eq!(report, format!("{}\n\n     count         time\n{}", HEADER, expected));
+}
+
+#[test]
+fn test_profile_next_and_exit() {
+    let source = "{ n++ }
+$1 == \"skip\" { next }
+{ kept++ }
+$1 == \"stop\" { exit }
+{ after++ }";
+    let report = profile_report(source, "a\nskip\nb\nstop\nc\n");
+    // the records after exit are not read, next still counts its action
+    assert!(report.contains("         4            T  {\n         4                   n++\n"));
+    assert!(report.contains("         4            T  $1 == \"skip\" { # 1\n         1                   next\n"));
+    assert!(report.contains("         3            T  {\n         3                   kept++\n"));
+    assert!(report.contains("         3            T  $1 == \"stop\" { # 1\n"));
+    assert!(report.contains("         2            T  {\n         2                   after++\n"));
+}
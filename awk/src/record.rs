@@ -0,0 +1,422 @@
+use std::io::{self, BufRead};
+
+use crate::ere::{self, Ere};
+use crate::json;
+use crate::value::Value;
+
+// $0 and the fields split out of it
+#[derive(Debug, Default)]
+pub struct Record {
+    text: String,
+    fields: Vec<String>,
+    // --csv: fields are quoted as needed when $0 is rebuilt
+    csv: bool,
+    // --json: $0 parsed the first time a key is looked up
+    json: Option<serde_json::Value>,
+}
+
+// how FS splits a record into fields
+#[derive(Debug, Clone)]
+pub enum FieldSeparator {
+    // " ": runs of blanks and newlines, leading and trailing ones are ignored
+    Blank,
+    // "": every character is a field
+    Characters,
+    // any other single character is taken literally
+    Literal(char),
+    // longer ones are EREs
+    Regex(Ere),
+    // --csv: commas outside of double quotes, FS is not used
+    Csv,
+}
+
+impl FieldSeparator {
+    // with RS = "" a newline separates fields whatever FS is
+    pub fn new(fs: &str, paragraph: bool) -> Result<FieldSeparator, String> {
+        let mut chars = fs.chars();
+        let separator = match (chars.next(), chars.next()) {
+            (Some(' '), None) => return Ok(FieldSeparator::Blank),
+            (None, _) => FieldSeparator::Characters,
+            (Some(c), None) if !paragraph => FieldSeparator::Literal(c),
+            (Some(c), None) => FieldSeparator::Regex(ere::compile(&format!("{}|\n", ere_literal(c)))?),
+            _ if paragraph => FieldSeparator::Regex(ere::compile(&format!("({})|\n", fs))?),
+            _ => FieldSeparator::Regex(ere::compile(fs)?),
+        };
+        Ok(separator)
+    }
+
+    pub fn split(&self, text: &str) -> Vec<String> {
+        if text.is_empty() {
+            return vec![];
+        }
+        match self {
+            FieldSeparator::Blank => text
+                .split([' ', '\t', '\n'])
+                .filter(|field| !field.is_empty())
+                .map(String::from)
+                .collect(),
+            FieldSeparator::Characters => text.chars().map(String::from).collect(),
+            FieldSeparator::Literal(c) => text.split(*c).map(String::from).collect(),
+            FieldSeparator::Regex(regex) => split_regex(text, regex),
+            FieldSeparator::Csv => split_csv(text),
+        }
+    }
+}
+
+// an ERE matching just `c`
+fn ere_literal(c: char) -> String {
+    if c.is_alphanumeric() {
+        return c.to_string();
+    }
+    format!("\\{}", c)
+}
+
+// empty matches do not separate anything
+pub fn split_regex(text: &str, regex: &Ere) -> Vec<String> {
+    if text.is_empty() {
+        return vec![];
+    }
+    let mut fields = vec![];
+    let mut last = 0;
+    for found in regex.find_iter(text.as_bytes()).filter(|found| !found.is_empty()) {
+        fields.push(text[last..found.start].to_string());
+        last = found.end;
+    }
+    fields.push(text[last..].to_string());
+    fields
+}
+
+// RFC 4180 fields, "" inside a quoted field is a quote, text after the closing quote is kept
+pub fn split_csv(text: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+// a field as it is written in --csv mode, quoted when it has a comma, a quote or a line break
+pub fn quote_csv(field: &str) -> String {
+    if !field.contains([',', '"', '\n', '\r']) {
+        return field.to_string();
+    }
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+impl Record {
+    pub fn set_csv(&mut self, csv: bool) {
+        self.csv = csv;
+    }
+
+    pub fn set(&mut self, text: String, fs: &FieldSeparator) {
+        self.fields = fs.split(&text);
+        self.text = text;
+        self.json = None;
+    }
+
+    // $"user.id" in --json mode
+    pub fn key(&mut self, path: &str) -> Value {
+        let text = &self.text;
+        let document = self.json.get_or_insert_with(|| json::parse(text));
+        json::to_value(json::lookup(document, path))
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn nf(&self) -> usize {
+        self.fields.len()
+    }
+
+    // $index for index > 0
+    pub fn field(&self, index: usize) -> Option<&str> {
+        self.fields.get(index - 1).map(String::as_str)
+    }
+
+    pub fn set_field(&mut self, index: usize, value: String, ofs: &str) {
+        if index > self.fields.len() {
+            self.fields.resize(index, String::new());
+        }
+        self.fields[index - 1] = value;
+        self.rebuild(ofs);
+    }
+
+    pub fn set_nf(&mut self, nf: usize, ofs: &str) {
+        self.fields.resize(nf, String::new());
+        self.rebuild(ofs);
+    }
+
+    fn rebuild(&mut self, ofs: &str) {
+        self.json = None;
+        self.text = if self.csv {
+            let fields: Vec<String> = self.fields.iter().map(|field| quote_csv(field)).collect();
+            fields.join(ofs)
+        } else {
+            self.fields.join(ofs)
+        };
+    }
+}
+
+// how RS ends a record
+#[derive(Debug, Clone)]
+pub enum RecordSeparator {
+    // a single byte, usually '\n'
+    Byte(u8),
+    // "": records are separated by blank lines, leading and trailing newlines are dropped
+    Paragraph,
+    // anything longer is an ERE, like in gawk
+    Regex(Ere),
+    // --csv: lines, but a newline inside double quotes belongs to the record, \r\n ends it too
+    Csv,
+}
+
+impl RecordSeparator {
+    pub fn new(rs: &str) -> Result<RecordSeparator, String> {
+        match rs.as_bytes() {
+            [] => Ok(RecordSeparator::Paragraph),
+            [byte] => Ok(RecordSeparator::Byte(*byte)),
+            // a single multibyte character is taken literally as well
+            _ if rs.chars().count() == 1 => Ok(RecordSeparator::Regex(ere::compile(
+                &ere_literal(rs.chars().next().unwrap()),
+            )?)),
+            _ => Ok(RecordSeparator::Regex(ere::compile(rs)?)),
+        }
+    }
+}
+
+pub struct RecordReader {
+    input: Box<dyn BufRead>,
+    // read from the input, what is before `start` was already returned as records
+    buffer: Vec<u8>,
+    start: usize,
+    eof: bool,
+}
+
+impl RecordReader {
+    pub fn new(input: Box<dyn BufRead>) -> RecordReader {
+        RecordReader {
+            input,
+            buffer: vec![],
+            start: 0,
+            eof: false,
+        }
+    }
+
+    // appends whatever the input has ready to the buffer, false at its end
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.eof {
+            return Ok(false);
+        }
+        let available = self.input.fill_buf()?;
+        if available.is_empty() {
+            self.eof = true;
+            return Ok(false);
+        }
+        let length = available.len();
+        self.buffer.drain(..self.start);
+        self.start = 0;
+        self.buffer.extend_from_slice(available);
+        self.input.consume(length);
+        Ok(true)
+    }
+
+    fn pending(&self) -> &[u8] {
+        &self.buffer[self.start..]
+    }
+
+    // the next `length` bytes as a record, dropping `skip` more after them
+    fn take(&mut self, length: usize, skip: usize) -> Option<String> {
+        let record = String::from_utf8_lossy(&self.pending()[..length]).into_owned();
+        self.start += length + skip;
+        Some(record)
+    }
+
+    // what is left at the end of the input, if anything
+    fn rest(&mut self) -> Option<String> {
+        let length = self.pending().len();
+        if length == 0 {
+            return None;
+        }
+        self.take(length, 0)
+    }
+
+    // the RS can change between records, so it is given on every read
+    pub fn read_record(&mut self, rs: &RecordSeparator) -> io::Result<Option<String>> {
+        match rs {
+            RecordSeparator::Byte(separator) => self.read_until(*separator),
+            RecordSeparator::Paragraph => self.read_paragraph(),
+            RecordSeparator::Regex(regex) => self.read_until_match(regex),
+            RecordSeparator::Csv => self.read_csv(),
+        }
+    }
+
+    fn read_until(&mut self, separator: u8) -> io::Result<Option<String>> {
+        let mut searched = 0;
+        loop {
+            if let Some(found) = self.pending()[searched..].iter().position(|&byte| byte == separator) {
+                return Ok(self.take(searched + found, 1));
+            }
+            searched = self.pending().len();
+            if !self.fill()? {
+                return Ok(self.rest());
+            }
+        }
+    }
+
+    fn read_csv(&mut self) -> io::Result<Option<String>> {
+        let mut record = match self.read_until(b'\n')? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        // an odd number of quotes leaves a quoted field open
+        while record.matches('"').count() % 2 == 1 {
+            match self.read_until(b'\n')? {
+                Some(line) => {
+                    record.push('\n');
+                    record.push_str(&line);
+                }
+                None => break,
+            }
+        }
+        if record.ends_with('\r') {
+            record.pop();
+        }
+        Ok(Some(record))
+    }
+
+    fn read_paragraph(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let blank = self.pending().iter().take_while(|&&byte| byte == b'\n').count();
+            self.start += blank;
+            if !self.pending().is_empty() || !self.fill()? {
+                break;
+            }
+        }
+        let mut searched = 0;
+        loop {
+            if let Some(found) = self.pending()[searched..].windows(2).position(|pair| pair == b"\n\n") {
+                // the rest of the blank lines are dropped by the next read
+                return Ok(self.take(searched + found, 2));
+            }
+            searched = self.pending().len().saturating_sub(1);
+            if !self.fill()? {
+                while self.pending().last() == Some(&b'\n') {
+                    self.buffer.pop();
+                }
+                return Ok(self.rest());
+            }
+        }
+    }
+
+    fn read_until_match(&mut self, regex: &Ere) -> io::Result<Option<String>> {
+        loop {
+            let found = regex
+                .find_iter(self.pending())
+                .find(|found| !found.is_empty())
+                .map(|found| (found.start, found.end));
+            // a match reaching the end of the buffer could go on in what is not read yet
+            if let Some((start, end)) = found.filter(|&(_, end)| end < self.pending().len() || self.eof) {
+                return Ok(self.take(start, end - start));
+            }
+            if !self.fill()? && found.is_none() {
+                return Ok(self.rest());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn read_all(input: &'static str, rs: &str) -> Vec<String> {
+    // one byte at a time, so that separators are split between reads
+    let input = io::BufReader::with_capacity(1, io::Cursor::new(input));
+    let mut reader = RecordReader::new(Box::new(input));
+    let rs = RecordSeparator::new(rs).unwrap();
+    let mut records = vec![];
+    while let Some(record) = reader.read_record(&rs).unwrap() {
+        records.push(record);
+    }
+    records
+}
+
+#[test]
+fn test_split_fields() {
+    let split = |text: &str, fs: &str| FieldSeparator::new(fs, false).unwrap().split(text);
+    assert_eq!(split("  a b\t\tc  ", " "), vec!["a", "b", "c"]);
+    assert_eq!(split("a:b::c", ":"), vec!["a", "b", "", "c"]);
+    assert_eq!(split("", ":"), Vec::<String>::new());
+    assert_eq!(split("a|b", "|"), vec!["a", "b"]);
+    assert_eq!(split("abc", ""), vec!["a", "b", "c"]);
+    assert_eq!(split(" a1b22c", "[0-9]+"), vec![" a", "b", "c"]);
+    assert_eq!(split("a, b ,c", " *, *"), vec!["a", "b", "c"]);
+    assert_eq!(split("abc", "x*"), vec!["abc"]);
+}
+
+#[test]
+fn test_split_paragraph_fields() {
+    let split = |text: &str, fs: &str| FieldSeparator::new(fs, true).unwrap().split(text);
+    assert_eq!(split("a b\nc", " "), vec!["a", "b", "c"]);
+    assert_eq!(split("a:b\nc", ":"), vec!["a", "b", "c"]);
+    assert_eq!(split("a-b\nc]d", "]"), vec!["a-b", "c", "d"]);
+    assert_eq!(split("a12b\nc", "[0-9]+"), vec!["a", "b", "c"]);
+}
+
+#[test]
+fn test_set_field_rebuilds_record() {
+    let mut record = Record::default();
+    record.set("a b c".to_string(), &FieldSeparator::Blank);
+    record.set_field(5, "e".to_string(), "-");
+    assert_eq!(record.text(), "a-b-c--e");
+    assert_eq!(record.nf(), 5);
+    record.set_nf(2, " ");
+    assert_eq!(record.text(), "a b");
+}
+
+#[test]
+fn test_read_record() {
+    assert_eq!(read_all("one\ntwo\n\nthree", "\n"), vec!["one", "two", "", "three"]);
+    assert_eq!(read_all("a;b;", ";"), vec!["a", "b"]);
+}
+
+#[test]
+fn test_read_paragraphs() {
+    let input = "\n\nfirst line\nsecond\n\n\n\nnext\n\nlast\n\n";
+    assert_eq!(read_all(input, ""), vec!["first line\nsecond", "next", "last"]);
+    assert_eq!(read_all("\n\n", ""), Vec::<String>::new());
+}
+
+#[test]
+fn test_read_regex_records() {
+    assert_eq!(read_all("a12b3c", "[0-9]+"), vec!["a", "b", "c"]);
+    assert_eq!(read_all("one<>two<>", "<>"), vec!["one", "two"]);
+    assert_eq!(read_all("xéyé", "é"), vec!["x", "y"]);
+}
+
+#[test]
+fn test_csv() {
+    assert_eq!(split_csv("a,\"b,c\",,\"say \"\"hi\"\"\""), vec!["a", "b,c", "", "say \"hi\""]);
+    assert_eq!(split_csv("a,"), vec!["a", ""]);
+    assert_eq!(split_csv("\"multi\nline\",x"), vec!["multi\nline", "x"]);
+    assert_eq!(quote_csv("plain"), "plain");
+    assert_eq!(quote_csv("a,b"), "\"a,b\"");
+    assert_eq!(quote_csv("say \"hi\""), "\"say \"\"hi\"\"\"");
+    let input = "a,\"b\r\nc\"\r\nd,e\r\n\"open";
+    let records: Vec<String> = {
+        let mut reader = RecordReader::new(Box::new(io::Cursor::new(input)));
+        std::iter::from_fn(|| reader.read_record(&RecordSeparator::Csv).unwrap()).collect()
+    };
+    assert_eq!(records, vec!["a,\"b\r\nc\"", "d,e", "\"open"]);
+}
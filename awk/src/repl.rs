@@ -0,0 +1,403 @@
+// --repl: statements run as they are entered, functions and pattern-actions are
+// kept and run over the records stepped through with :next, variables and arrays
+// live in one interpreter for the whole session.
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use crate::diagnostic::Diagnostic;
+use crate::interpreter::{Interpreter, Unwind};
+use crate::syntax_tree::{
+    Action, Expr, Item, Pattern, PrintStatement, Program, SimpleStatement, SpannedStatement,
+    Statement,
+};
+use crate::tokens::Token;
+use crate::{lexer, parser, pretty};
+
+const HELP: &str = "statements run right away, a bare expression prints its value
+BEGIN { ... } runs right away, END { ... } when the session ends
+function f(...) { ... } defines f, { ... } and pattern { ... } run on every record read
+:load file   read the next records from file
+:next [n]    read the next n records (default 1) and run the pattern-actions on them
+:list        print the functions and pattern-actions entered so far
+:help        print this help
+:quit        run the END actions and leave, like exit and end of file";
+
+// how the parser starts the error for an input cut short
+const INCOMPLETE: &str = "unexpected end of program";
+
+// what became of a line of input
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    Done,
+    // the input so far is cut short, like an open '{'
+    More,
+    Quit,
+}
+
+pub struct Repl {
+    interpreter: Interpreter,
+    // the functions and pattern-actions entered so far
+    program: Program,
+    // lines of an input which is not complete yet
+    pending: String,
+    loaded: bool,
+}
+
+impl Repl {
+    // `program` is what -f gave, its BEGIN actions run now
+    pub fn new(interpreter: Interpreter, program: Program) -> Result<(Repl, Step), String> {
+        let mut repl = Repl {
+            interpreter,
+            program: Program { items: vec![] },
+            pending: String::new(),
+            loaded: false,
+        };
+        let step = repl.add_items(program.items)?;
+        Ok((repl, step))
+    }
+
+    pub fn input(&mut self, line: &str) -> Result<Step, String> {
+        if self.pending.is_empty() && line.trim_start().starts_with(':') {
+            return self.command(line.trim());
+        }
+        if !self.pending.is_empty() {
+            self.pending.push('\n');
+        }
+        self.pending.push_str(line);
+        let source = std::mem::take(&mut self.pending);
+        // a backslash at the end joins the next line, even to a complete statement
+        if source.ends_with('\\') {
+            self.pending = source;
+            return Ok(Step::More);
+        }
+        let step = match parse_input(&source, self.interpreter.gawk()) {
+            Ok(Parsed::Items(items)) => self.add_items(items),
+            Ok(Parsed::Statements(action)) => self.run_statements(&action),
+            Err(_) if continues(&source, self.interpreter.gawk()) => {
+                self.pending = source;
+                return Ok(Step::More);
+            }
+            Err(diagnostic) => Err(diagnostic.render(&source)),
+        };
+        self.interpreter.flush().map_err(|e| e.to_string())?;
+        step
+    }
+
+    // leaves the session, like awk the END actions run after exit too
+    pub fn finish(mut self) -> Result<i32, String> {
+        for item in &self.program.items {
+            if let Item::PatternAction(Pattern::End, action) = item {
+                match self.interpreter.run_action(action) {
+                    Ok(()) | Err(Unwind::Next) => {}
+                    Err(Unwind::Exit) => break,
+                    Err(Unwind::Error(error)) => return Err(error.to_string()),
+                }
+            }
+        }
+        self.interpreter.finish().map_err(|e| e.to_string())
+    }
+
+    fn command(&mut self, line: &str) -> Result<Step, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let argument = words.next();
+        let step = match (name, argument) {
+            (":load", Some(path)) => {
+                unwind(self.interpreter.load(path))?;
+                self.loaded = true;
+                Ok(Step::Done)
+            }
+            (":next", count) => {
+                let count = match count.map(str::parse::<usize>) {
+                    None => 1,
+                    Some(Ok(count)) => count,
+                    Some(Err(_)) => return Err(format!("invalid number of records {}", count.unwrap_or_default())),
+                };
+                self.next(count)
+            }
+            (":list", None) => {
+                print!("{}", pretty::pretty_print(&self.program));
+                Ok(Step::Done)
+            }
+            (":help", None) => {
+                println!("{}", HELP);
+                Ok(Step::Done)
+            }
+            (":quit", None) => Ok(Step::Quit),
+            _ => Err(format!("unknown command {}, see :help", line)),
+        };
+        self.interpreter.flush().map_err(|e| e.to_string())?;
+        step
+    }
+
+    // reads `count` records and runs the pattern-actions on each
+    fn next(&mut self, count: usize) -> Result<Step, String> {
+        // the records would otherwise come from the terminal the commands are typed on
+        if !self.loaded && self.interpreter.global_number("ARGC") <= 1.0 {
+            return Err("no input, read a file with :load file".to_string());
+        }
+        for _ in 0..count {
+            let text = match unwind(self.interpreter.next_record())? {
+                Some(text) => text,
+                None => return Err("no more records".to_string()),
+            };
+            unwind(self.interpreter.set_record(text))?;
+            match self.interpreter.run_items(&self.program) {
+                Ok(()) | Err(Unwind::Next) => {}
+                Err(Unwind::Exit) => return Ok(Step::Quit),
+                Err(Unwind::Error(error)) => return Err(error.to_string()),
+            }
+        }
+        Ok(Step::Done)
+    }
+
+    fn add_items(&mut self, items: Vec<Item>) -> Result<Step, String> {
+        for item in items {
+            match item {
+                Item::FunctionDeclaration(function) => {
+                    self.interpreter.define_function(&function);
+                    self.program.items.retain(|item| {
+                        !matches!(item, Item::FunctionDeclaration(defined) if defined.name == function.name)
+                    });
+                    self.program.items.push(Item::FunctionDeclaration(function));
+                }
+                Item::PatternAction(Pattern::Begin, action) => {
+                    if self.run_statements(&action)? == Step::Quit {
+                        return Ok(Step::Quit);
+                    }
+                }
+                item => self.program.items.push(item),
+            }
+        }
+        Ok(Step::Done)
+    }
+
+    fn run_statements(&mut self, action: &Action) -> Result<Step, String> {
+        match self.interpreter.run_action(action) {
+            Ok(()) => Ok(Step::Done),
+            Err(Unwind::Exit) => Ok(Step::Quit),
+            Err(Unwind::Next) => Err("next used outside of :next".to_string()),
+            Err(Unwind::Error(error)) => Err(error.to_string()),
+        }
+    }
+}
+
+fn unwind<T>(result: Result<T, Unwind>) -> Result<T, String> {
+    result.map_err(|unwind| match unwind {
+        Unwind::Error(error) => error.to_string(),
+        Unwind::Next | Unwind::Exit => "unexpected next or exit".to_string(),
+    })
+}
+
+// whether the next line goes on with `source`, which did not parse: a brace or
+// parenthesis is open, the line ends with a token a newline may follow, or with
+// the ')' of an if, for or while waiting for its body; a dangling operator like
+// `1 +` is an error, a newline ends the statement in awk
+fn continues(source: &str, gawk: bool) -> bool {
+    let Ok(tokens) = lexer::tokenize_with(source.to_string(), gawk) else {
+        return false;
+    };
+    let open = |open: Token, close: Token| {
+        let count = |token: &Token| tokens.iter().filter(|spanned| spanned.token == *token).count();
+        count(&open) > count(&close)
+    };
+    if open(Token::OpenCurlyBrace, Token::CloseCurlyBrace)
+        || open(Token::OpenBrace, Token::CloseBrace)
+        || open(Token::OpenSquareBrace, Token::CloseSquareBrace)
+    {
+        return true;
+    }
+    let mut tokens = tokens
+        .iter()
+        .rev()
+        .map(|spanned| &spanned.token)
+        .skip_while(|token| **token == Token::Newline);
+    match tokens.next() {
+        Some(Token::Comma | Token::And | Token::Or | Token::OpenCurlyBrace | Token::Do | Token::Else) => {
+            true
+        }
+        Some(Token::CloseBrace) => {
+            let mut depth = 1;
+            for token in tokens.by_ref() {
+                match token {
+                    Token::CloseBrace => depth += 1,
+                    Token::OpenBrace if depth == 1 => break,
+                    Token::OpenBrace => depth -= 1,
+                    _ => {}
+                }
+            }
+            matches!(tokens.next(), Some(Token::If | Token::For | Token::While))
+        }
+        _ => false,
+    }
+}
+
+enum Parsed {
+    Items(Vec<Item>),
+    Statements(Action),
+}
+
+// items when the input is a program of functions and pattern-actions, statements
+// otherwise, so `x = 1` is an assignment and not a pattern
+fn parse_input(source: &str, gawk: bool) -> Result<Parsed, Diagnostic> {
+    let incomplete = match lexer::tokenize_with(source.to_string(), gawk).and_then(parser::parse) {
+        Ok(program) if !program.items.iter().any(|item| matches!(item, Item::Pattern(_))) => {
+            return Ok(Parsed::Items(program.items));
+        }
+        Ok(_) => None,
+        Err(diagnostic) if diagnostic.message.starts_with(INCOMPLETE) => Some(diagnostic),
+        Err(_) => None,
+    };
+    // on the lines of their own the braces don't move the columns of the input
+    let wrapped = format!("{{\n{}\n}}", source);
+    let mut program = match lexer::tokenize_with(wrapped, gawk).and_then(parser::parse) {
+        Ok(program) => program,
+        Err(mut diagnostic) => {
+            diagnostic.span.line = diagnostic.span.line.saturating_sub(1).max(1);
+            return Err(incomplete.unwrap_or(diagnostic));
+        }
+    };
+    let Some(Item::Action(mut action)) = program.items.pop() else {
+        unreachable!("a block parses to an action");
+    };
+    if let [SpannedStatement { statement: Statement::SimpleStatement(SimpleStatement::Expr(expr)), span }] =
+        action.statements.as_slice()
+    {
+        if !matches!(
+            expr,
+            Expr::Assign(..)
+                | Expr::CompoundAssign(..)
+                | Expr::LValueIncr(_)
+                | Expr::LValueDecr(_)
+                | Expr::IncrLValue(_)
+                | Expr::DecrLValue(_)
+        ) {
+            let print = PrintStatement::Print(vec![expr.clone()], None);
+            let print = Statement::SimpleStatement(SimpleStatement::PrintStatement(print));
+            action.statements = vec![SpannedStatement::new(print, *span)];
+        }
+    }
+    Ok(Parsed::Statements(action))
+}
+
+pub fn run(interpreter: Interpreter, program: Program) -> i32 {
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("awk: can't start the REPL: {}", e);
+            return crate::meta::EXIT_FAILURE;
+        }
+    };
+    let (mut repl, mut step) = match Repl::new(interpreter, program) {
+        Ok(started) => started,
+        Err(e) => {
+            eprintln!("awk: {}", e);
+            return crate::meta::EXIT_FAILURE;
+        }
+    };
+    while step != Step::Quit {
+        let prompt = if step == Step::More { "...> " } else { "awk> " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                let _ = editor.add_history_entry(line.as_str());
+                step = match repl.input(&line) {
+                    Ok(step) => step,
+                    Err(e) => {
+                        eprintln!("awk: {}", e);
+                        Step::Done
+                    }
+                };
+            }
+            // ctrl-c drops the input being typed
+            Err(ReadlineError::Interrupted) => {
+                repl.pending.clear();
+                step = Step::Done;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("awk: {}", e);
+                break;
+            }
+        }
+    }
+    match repl.finish() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("awk: {}", e);
+            crate::meta::EXIT_FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+fn repl_session(lines: &[&str]) -> (String, Vec<Result<Step, String>>) {
+    let output = crate::interpreter::SharedOutput::default();
+    let program = Program { items: vec![] };
+    let interpreter = Interpreter::new(&program, Box::new(output.clone()));
+    let (mut repl, _) = Repl::new(interpreter, program).unwrap();
+    let mut steps: Vec<Result<Step, String>> = lines.iter().map(|line| repl.input(line)).collect();
+    steps.push(repl.finish().map(|_| Step::Quit));
+    let printed = output.0.borrow().clone();
+    (String::from_utf8(printed).unwrap(), steps)
+}
+
+#[test]
+fn test_repl_state() {
+    let (printed, steps) = repl_session(&[
+        "x = 2",
+        "count[\"a\"] = x * 3",
+        "x + 1",
+        "function double(n) { return 2 * n }",
+        "double(count[\"a\"])",
+        "x > 1",
+        "if (x) {",
+        "  print \"yes\"",
+        "}",
+        "print 1 +",
+        "print 4",
+        "print 5,",
+        "6",
+        "if (x)",
+        "  print 7",
+        "END { print \"bye\", x }",
+        "exit",
+        ":next",
+    ]);
+    assert_eq!(printed, "3\n12\n1\nyes\n4\n5 6\n7\nbye 2\n");
+    assert_eq!(steps[6], Ok(Step::More));
+    assert_eq!(steps[7], Ok(Step::More));
+    assert_eq!(steps[8], Ok(Step::Done));
+    // a newline ends the statement after a dangling operator
+    assert!(steps[9].as_ref().is_err_and(|e| e.contains("expecting expression")));
+    assert_eq!(steps[10], Ok(Step::Done));
+    assert_eq!(steps[11], Ok(Step::More));
+    assert_eq!(steps[13], Ok(Step::More));
+    assert_eq!(steps[16], Ok(Step::Quit));
+    assert_eq!(steps[17], Err("no input, read a file with :load file".to_string()));
+}
+
+#[test]
+fn test_repl_records() {
+    let path = std::env::temp_dir().join(format!("awk-repl-{}", std::process::id()));
+    std::fs::write(&path, "1 a\n2 b\n3 c\n").unwrap();
+    let load = format!(":load {}", path.display());
+    let (printed, steps) = repl_session(&[
+        &load,
+        "$1 > 1 { seen = seen $2 }",
+        "NR == 3",
+        ":next",
+        "print $2, NF",
+        ":next 2",
+        "seen",
+        ":next",
+        ":bogus",
+        "BEGIN { x = 1",
+        "}; { y = $1 } END { print seen }",
+    ]);
+    std::fs::remove_file(&path).unwrap();
+    // NR == 3 is an expression, only NR == 3 { print } would be kept
+    assert_eq!(printed, "0\na 2\nbc\nbc\n");
+    assert_eq!(steps[7], Err("no more records".to_string()));
+    assert_eq!(steps[8], Err("unknown command :bogus, see :help".to_string()));
+    assert_eq!(steps[9], Ok(Step::More));
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::process::{Child, Command, Stdio};
+
+use crate::record::{RecordReader, RecordSeparator};
+
+// how print and printf name their output, the same name is always the same stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputKind {
+    // > truncates the file when it is first opened, later prints append to it
+    File,
+    // >>
+    Append,
+    // | runs the command with sh -c, reading what is printed
+    Pipe,
+}
+
+// where getline reads from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputKind {
+    // getline < file
+    File,
+    // command | getline, runs the command with sh -c, reading what it prints
+    Command,
+}
+
+enum Stream {
+    File(BufWriter<File>),
+    Pipe(Child),
+    Input(RecordReader),
+    Command(Child, RecordReader),
+}
+
+impl Stream {
+    // None for the streams getline reads from
+    fn writer(&mut self) -> Option<&mut dyn Write> {
+        match self {
+            Stream::File(file) => Some(file),
+            // stdin is only taken when the stream is closed
+            Stream::Pipe(child) => Some(child.stdin.as_mut().unwrap()),
+            Stream::Input(_) | Stream::Command(_, _) => None,
+        }
+    }
+
+    fn reader(&mut self) -> Option<&mut RecordReader> {
+        match self {
+            Stream::Input(reader) | Stream::Command(_, reader) => Some(reader),
+            Stream::File(_) | Stream::Pipe(_) => None,
+        }
+    }
+
+    // exit status of the command for pipes, 256 + the signal number when it was killed
+    fn close(self) -> io::Result<i32> {
+        match self {
+            Stream::File(mut file) => {
+                file.flush()?;
+                Ok(0)
+            }
+            Stream::Pipe(mut child) => {
+                drop(child.stdin.take());
+                Ok(exit_status(child.wait()?))
+            }
+            Stream::Input(_) => Ok(0),
+            Stream::Command(mut child, reader) => {
+                // the command may still be writing, it gets a broken pipe once the reader is gone
+                drop(reader);
+                Ok(exit_status(child.wait()?))
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+pub fn exit_status(status: std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 256 + signal,
+        (None, None) => -1,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn exit_status(status: std::process::ExitStatus) -> i32 {
+    status.code().unwrap_or(-1)
+}
+
+// the files and commands opened by output redirections, until close() or the end of the program
+#[derive(Default)]
+pub struct Streams {
+    streams: HashMap<String, Stream>,
+}
+
+// a name is either written to or read from until it is closed
+fn in_use(name: &str) -> io::Error {
+    io::Error::other(format!("{} is already open the other way", name))
+}
+
+impl Streams {
+    pub fn write(&mut self, kind: OutputKind, name: &str, text: &[u8]) -> io::Result<()> {
+        if !self.streams.contains_key(name) {
+            let stream = match kind {
+                OutputKind::File => Stream::File(BufWriter::new(File::create(name)?)),
+                OutputKind::Append => Stream::File(BufWriter::new(
+                    OpenOptions::new().append(true).create(true).open(name)?,
+                )),
+                OutputKind::Pipe => Stream::Pipe(
+                    Command::new("sh")
+                        .arg("-c")
+                        .arg(name)
+                        .stdin(Stdio::piped())
+                        .spawn()?,
+                ),
+            };
+            self.streams.insert(name.to_string(), stream);
+        }
+        match self.streams.get_mut(name).unwrap().writer() {
+            Some(writer) => writer.write_all(text),
+            None => Err(in_use(name)),
+        }
+    }
+
+    // the next record of the file or command output called `name`, None at its end
+    pub fn read(&mut self, kind: InputKind, name: &str, rs: &RecordSeparator) -> io::Result<Option<String>> {
+        if !self.streams.contains_key(name) {
+            let stream = match kind {
+                InputKind::File => {
+                    Stream::Input(RecordReader::new(Box::new(BufReader::new(File::open(name)?))))
+                }
+                InputKind::Command => {
+                    let mut child = Command::new("sh")
+                        .arg("-c")
+                        .arg(name)
+                        .stdout(Stdio::piped())
+                        .spawn()?;
+                    let stdout = child.stdout.take().unwrap();
+                    Stream::Command(child, RecordReader::new(Box::new(BufReader::new(stdout))))
+                }
+            };
+            self.streams.insert(name.to_string(), stream);
+        }
+        match self.streams.get_mut(name).unwrap().reader() {
+            Some(reader) => reader.read_record(rs),
+            None => Err(in_use(name)),
+        }
+    }
+
+    pub fn is_open(&self, name: &str) -> bool {
+        self.streams.contains_key(name)
+    }
+
+    // -1 when nothing called `name` is open
+    pub fn close(&mut self, name: &str) -> i32 {
+        match self.streams.remove(name) {
+            Some(stream) => stream.close().unwrap_or(-1),
+            None => -1,
+        }
+    }
+
+    pub fn flush(&mut self, name: &str) -> i32 {
+        match self.streams.get_mut(name).and_then(Stream::writer) {
+            Some(writer) => match writer.flush() {
+                Ok(()) => 0,
+                Err(_) => -1,
+            },
+            None => -1,
+        }
+    }
+
+    pub fn flush_all(&mut self) -> io::Result<()> {
+        for writer in self.streams.values_mut().filter_map(Stream::writer) {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    // at the end of the program, commands are waited for so their output comes before awk exits
+    pub fn close_all(&mut self) -> io::Result<()> {
+        for (_, stream) in self.streams.drain() {
+            stream.close()?;
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,15 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+use crate::diagnostic::Span;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Token {
     // Identifier(Identifier),
     Name(Vec<char>),
-    Number(i64),
-    // Ere(Vec<char>), scopedout~~
-    FuncName(Vec<char>), // derived from Name later
+    Number(f64),
+    Ere(Vec<char>),
+    FuncName(Vec<char>), // a name immediately followed by '('
     Literal(Vec<char>),
     // Keyword(Keyword),
     Begin,
@@ -26,6 +29,12 @@ pub enum Token {
     Printf,
     Return,
     While,
+    // gawk keywords, only lexed with --gawk
+    BeginFile,
+    EndFile,
+    Switch,
+    Case,
+    Default,
     // Builtin(Builtin),
     Atan2,
     Cos,
@@ -48,7 +57,14 @@ pub enum Token {
     Toupper,
     Close,
     System,
+    Fflush,
     Getline,
+    // gawk builtins, only lexed with --gawk
+    Gensub,
+    Asort,
+    Asorti,
+    Strftime,
+    Systime,
     // Operator - OneCharOperator(OneCharOperator),
     Add,       // '+'
     Substract, // '-'
@@ -69,10 +85,20 @@ pub enum Token {
     // Operator - TwoCharOperator(TwoCharOperator),
     Or,      // '||'
     And,     // '&&'
-    NoMatch, // '!='
+    NoMatch, // '!~'
+    Ne,      // '!='
     Eq,      // '=='
     Le,      // '<='
     Ge,      // '>='
+    Append,  // '>>'
+    Incr,    // '++'
+    Decr,    // '--'
+    RaiseToAssign,   // '^='
+    ModulusAssign,   // '%='
+    MultiplyAssign,  // '*='
+    DivideAssign,    // '/='
+    AddAssign,       // '+='
+    SubstractAssign, // '-='
     // Seperator(Seperator),
     OpenCurlyBrace,   // '{'
     CloseCurlyBrace,  // '}'
@@ -83,8 +109,117 @@ pub enum Token {
     Comma,            // ','
     SemiColon,        // ';'
     Newline,          // '\n'
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
 
-    Unknown,
-    WhiteSpace,
-    Error,
+// how a token is named in syntax errors
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Token::Name(name) | Token::FuncName(name) => {
+                return write!(f, "name {}", name.iter().collect::<String>())
+            }
+            Token::Number(number) => return write!(f, "number {}", number),
+            Token::Ere(ere) => return write!(f, "regular expression /{}/", ere.iter().collect::<String>()),
+            Token::Literal(literal) => {
+                return write!(f, "string \"{}\"", literal.iter().collect::<String>())
+            }
+            Token::Newline => return write!(f, "newline"),
+            Token::Begin => "BEGIN",
+            Token::End => "END",
+            Token::Break => "break",
+            Token::Continue => "continue",
+            Token::Delete => "delete",
+            Token::Do => "do",
+            Token::Else => "else",
+            Token::Exit => "exit",
+            Token::For => "for",
+            Token::Function => "function",
+            Token::If => "if",
+            Token::In => "in",
+            Token::Next => "next",
+            Token::Print => "print",
+            Token::Printf => "printf",
+            Token::Return => "return",
+            Token::While => "while",
+            Token::BeginFile => "BEGINFILE",
+            Token::EndFile => "ENDFILE",
+            Token::Switch => "switch",
+            Token::Case => "case",
+            Token::Default => "default",
+            Token::Atan2 => "atan2",
+            Token::Cos => "cos",
+            Token::Sin => "sin",
+            Token::Exp => "exp",
+            Token::Log => "log",
+            Token::Sqrt => "sqrt",
+            Token::Int => "int",
+            Token::Rand => "rand",
+            Token::Srand => "srand",
+            Token::Gsub => "gsub",
+            Token::Index => "index",
+            Token::Length => "length",
+            Token::Match => "match",
+            Token::Split => "split",
+            Token::Sprintf => "sprintf",
+            Token::Sub => "sub",
+            Token::Substr => "substr",
+            Token::Tolower => "tolower",
+            Token::Toupper => "toupper",
+            Token::Close => "close",
+            Token::System => "system",
+            Token::Fflush => "fflush",
+            Token::Getline => "getline",
+            Token::Gensub => "gensub",
+            Token::Asort => "asort",
+            Token::Asorti => "asorti",
+            Token::Strftime => "strftime",
+            Token::Systime => "systime",
+            Token::Add => "+",
+            Token::Substract => "-",
+            Token::Multiply => "*",
+            Token::Divide => "/",
+            Token::Modulus => "%",
+            Token::RaiseTo => "^",
+            Token::Colon => ":",
+            Token::Tilde => "~",
+            Token::Dollar => "$",
+            Token::Question => "?",
+            Token::Invert => "!",
+            Token::LessThan => "<",
+            Token::GreaterThan => ">",
+            Token::Bar => "|",
+            Token::Equal => "=",
+            Token::Or => "||",
+            Token::And => "&&",
+            Token::NoMatch => "!~",
+            Token::Ne => "!=",
+            Token::Eq => "==",
+            Token::Le => "<=",
+            Token::Ge => ">=",
+            Token::Append => ">>",
+            Token::Incr => "++",
+            Token::Decr => "--",
+            Token::RaiseToAssign => "^=",
+            Token::ModulusAssign => "%=",
+            Token::MultiplyAssign => "*=",
+            Token::DivideAssign => "/=",
+            Token::AddAssign => "+=",
+            Token::SubstractAssign => "-=",
+            Token::OpenCurlyBrace => "{",
+            Token::CloseCurlyBrace => "}",
+            Token::OpenBrace => "(",
+            Token::CloseBrace => ")",
+            Token::OpenSquareBrace => "[",
+            Token::CloseSquareBrace => "]",
+            Token::Comma => ",",
+            Token::SemiColon => ";",
+        };
+        write!(f, "'{}'", symbol)
+    }
 }
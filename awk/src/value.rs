@@ -0,0 +1,156 @@
+use std::cmp::Ordering;
+
+use crate::format::format_number;
+
+// Values which come from input (fields, getline, split) and look like numbers
+// are "strnum" - they compare as numbers but print as the original text
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Uninitialized,
+    Number(f64),
+    String(String),
+    StrNum(String),
+}
+
+// length of the longest prefix of `text` which strtod would accept, after leading blanks
+fn numeric_prefix(text: &str) -> (usize, usize) {
+    let bytes = text.as_bytes();
+    let mut start = 0;
+    while start < bytes.len() && (bytes[start] == b' ' || bytes[start] == b'\t' || bytes[start] == b'\n') {
+        start += 1;
+    }
+    let mut end = start;
+    if end < bytes.len() && (bytes[end] == b'+' || bytes[end] == b'-') {
+        end += 1;
+    }
+    let digits_start = end;
+    while end < bytes.len() && bytes[end].is_ascii_digit() {
+        end += 1;
+    }
+    let mut digits = end - digits_start;
+    if end < bytes.len() && bytes[end] == b'.' {
+        end += 1;
+        let fraction_start = end;
+        while end < bytes.len() && bytes[end].is_ascii_digit() {
+            end += 1;
+        }
+        digits += end - fraction_start;
+    }
+    if digits == 0 {
+        return (start, start);
+    }
+    if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+        let mut exponent_end = end + 1;
+        if exponent_end < bytes.len() && (bytes[exponent_end] == b'+' || bytes[exponent_end] == b'-') {
+            exponent_end += 1;
+        }
+        let exponent_digits = exponent_end;
+        while exponent_end < bytes.len() && bytes[exponent_end].is_ascii_digit() {
+            exponent_end += 1;
+        }
+        if exponent_end > exponent_digits {
+            end = exponent_end;
+        }
+    }
+    (start, end)
+}
+
+pub fn str_to_number(text: &str) -> f64 {
+    let (start, end) = numeric_prefix(text);
+    text[start..end].parse::<f64>().unwrap_or(0.0)
+}
+
+// the whole text, ignoring surrounding blanks, is a number
+pub fn looks_numeric(text: &str) -> bool {
+    let (start, end) = numeric_prefix(text);
+    start != end && text[end..].chars().all(|c| c == ' ' || c == '\t' || c == '\n')
+}
+
+impl Value {
+    pub fn from_input(text: String) -> Value {
+        if looks_numeric(&text) {
+            return Value::StrNum(text);
+        }
+        Value::String(text)
+    }
+
+    pub fn to_number(&self) -> f64 {
+        match self {
+            Value::Uninitialized => 0.0,
+            Value::Number(number) => *number,
+            Value::String(text) | Value::StrNum(text) => str_to_number(text),
+        }
+    }
+
+    // convert with `fmt` (CONVFMT or OFMT), integers are always printed as integers
+    pub fn to_string_with(&self, fmt: &str) -> String {
+        match self {
+            Value::Uninitialized => String::new(),
+            Value::Number(number) => format_number(*number, fmt),
+            Value::String(text) | Value::StrNum(text) => text.clone(),
+        }
+    }
+
+    pub fn to_bool(&self) -> bool {
+        match self {
+            Value::Uninitialized => false,
+            Value::Number(number) => *number != 0.0,
+            Value::String(text) => !text.is_empty(),
+            Value::StrNum(text) => str_to_number(text) != 0.0,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        !matches!(self, Value::String(_))
+    }
+
+    // POSIX: compare numerically when both sides are numbers, strnums or
+    // uninitialized, otherwise compare as strings
+    pub fn compare(&self, other: &Value, convfmt: &str) -> Ordering {
+        if self.is_numeric() && other.is_numeric() {
+            let (left, right) = (self.to_number(), other.to_number());
+            return left.partial_cmp(&right).unwrap_or(Ordering::Equal);
+        }
+        self.to_string_with(convfmt)
+            .cmp(&other.to_string_with(convfmt))
+    }
+}
+
+#[test]
+fn test_str_to_number() {
+    assert_eq!(str_to_number("42"), 42.0);
+    assert_eq!(str_to_number("  -3.5abc"), -3.5);
+    assert_eq!(str_to_number("1e3x"), 1000.0);
+    assert_eq!(str_to_number("1e"), 1.0);
+    assert_eq!(str_to_number(".5"), 0.5);
+    assert_eq!(str_to_number("abc"), 0.0);
+    assert_eq!(str_to_number(""), 0.0);
+}
+
+#[test]
+fn test_looks_numeric() {
+    assert!(looks_numeric(" 12 "));
+    assert!(looks_numeric("+1.5e-3"));
+    assert!(!looks_numeric("12abc"));
+    assert!(!looks_numeric("."));
+    assert!(!looks_numeric(""));
+}
+
+#[test]
+fn test_compare() {
+    let convfmt = "%.6g";
+    // strnum fields compare numerically
+    assert_eq!(
+        Value::StrNum("10".to_string()).compare(&Value::StrNum("9".to_string()), convfmt),
+        Ordering::Greater
+    );
+    // string constants force a string comparison
+    assert_eq!(
+        Value::String("10".to_string()).compare(&Value::Number(9.0), convfmt),
+        Ordering::Less
+    );
+    assert_eq!(
+        Value::Uninitialized.compare(&Value::Number(0.0), convfmt),
+        Ordering::Equal
+    );
+}
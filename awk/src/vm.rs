@@ -0,0 +1,462 @@
+// Stack machine running the instructions compiled by bytecode.rs. The Interpreter
+// still owns the globals, the record and the streams, the machine only adds the
+// value stack, the locals of each call and the state of the loops and ranges, so
+// both backends share every builtin and the way records are read and printed.
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::vec;
+
+use crate::bytecode::{Bytecode, Op, Place, Separator};
+use crate::interpreter::{
+    sorted_keys, unary, Array, EvalResult, Interpreter, RuntimeError, SplitSeparator, Unwind,
+    Variable,
+};
+use crate::value::Value;
+
+// the variables of a call, named for error messages
+#[derive(Default)]
+struct Frame<'a> {
+    names: &'a [String],
+    locals: Vec<Variable>,
+}
+
+struct Vm<'a> {
+    bytecode: &'a Bytecode,
+    // the Interpreter slot of each of bytecode.globals
+    slots: Vec<usize>,
+    // whether each range pattern is between its start and end
+    ranges: Vec<bool>,
+}
+
+fn boolean(value: bool) -> Value {
+    Value::Number(value as i32 as f64)
+}
+
+fn pop(stack: &mut Vec<Value>) -> Value {
+    stack.pop().expect("the compiler keeps the stack balanced")
+}
+
+// replaces Interpreter::run_program
+pub fn run(interpreter: &mut Interpreter, bytecode: &Bytecode) -> EvalResult<()> {
+    let mut vm = Vm {
+        bytecode,
+        slots: bytecode
+            .globals
+            .iter()
+            .map(|name| interpreter.global_slot(name))
+            .collect(),
+        ranges: vec![false; bytecode.ranges],
+    };
+    let mut exited = false;
+    match vm.execute(interpreter, &bytecode.begin, &mut Frame::default()) {
+        Err(Unwind::Exit) => exited = true,
+        result => {
+            result?;
+        }
+    }
+    if !exited && bytecode.reads_input {
+        while let Some(text) = interpreter.next_record()? {
+            interpreter.set_record(text)?;
+            match vm.execute(interpreter, &bytecode.records, &mut Frame::default()) {
+                Ok(_) | Err(Unwind::Next) => {}
+                Err(Unwind::Exit) => break,
+                Err(unwind) => return Err(unwind),
+            }
+        }
+    }
+    // exit inside END stops right away, that is handled by the caller
+    vm.execute(interpreter, &bytecode.end, &mut Frame::default())?;
+    Ok(())
+}
+
+impl<'a> Vm<'a> {
+    // the variable of a global or local place and its name, for errors
+    fn variable<'b>(
+        &'b self,
+        interpreter: &'b mut Interpreter,
+        frame: &'b mut Frame,
+        place: Place,
+    ) -> (&'b mut Variable, &'b str) {
+        match place {
+            Place::Global(index) | Place::GlobalElement(index) => (
+                interpreter.global_mut(self.slots[index]),
+                &self.bytecode.globals[index],
+            ),
+            Place::Local(index) | Place::LocalElement(index) => {
+                (&mut frame.locals[index], &frame.names[index])
+            }
+            Place::Nf | Place::Field => unreachable!("{:?} is not a variable", place),
+        }
+    }
+
+    fn array(
+        &self,
+        interpreter: &mut Interpreter,
+        frame: &mut Frame,
+        place: Place,
+    ) -> EvalResult<Rc<RefCell<Array>>> {
+        let (variable, name) = self.variable(interpreter, frame, place);
+        variable.array(name)
+    }
+
+    fn load(
+        &self,
+        interpreter: &mut Interpreter,
+        frame: &mut Frame,
+        place: Place,
+        key: Option<Value>,
+    ) -> EvalResult<Value> {
+        match (place, key) {
+            (Place::Nf, _) => Ok(interpreter.nf()),
+            (Place::Field, Some(index)) => interpreter.get_field(index),
+            (variable @ (Place::Global(_) | Place::Local(_)), _) => {
+                let (variable, name) = self.variable(interpreter, frame, variable);
+                variable.value(name)
+            }
+            (element, Some(key)) => {
+                let key = interpreter.to_string(&key);
+                let array = self.array(interpreter, frame, element)?;
+                // referencing an element creates it
+                let value = array
+                    .borrow_mut()
+                    .entry(key)
+                    .or_insert(Value::Uninitialized)
+                    .clone();
+                Ok(value)
+            }
+            (place, None) => unreachable!("{:?} needs a key", place),
+        }
+    }
+
+    fn store(
+        &self,
+        interpreter: &mut Interpreter,
+        frame: &mut Frame,
+        place: Place,
+        key: Option<Value>,
+        value: Value,
+    ) -> EvalResult<()> {
+        match (place, key) {
+            (Place::Nf, _) => interpreter.set_nf(value),
+            (Place::Field, Some(index)) => interpreter.set_field(index, value),
+            (variable @ (Place::Global(_) | Place::Local(_)), _) => {
+                let (variable, name) = self.variable(interpreter, frame, variable);
+                variable.set_value(name, value)
+            }
+            (element, Some(key)) => {
+                let key = interpreter.to_string(&key);
+                self.array(interpreter, frame, element)?
+                    .borrow_mut()
+                    .insert(key, value);
+                Ok(())
+            }
+            (place, None) => unreachable!("{:?} needs a key", place),
+        }
+    }
+
+    // runs `code` until it returns or reaches its end, which returns an uninitialized value
+    fn execute(&mut self, interpreter: &mut Interpreter, code: &[Op], frame: &mut Frame) -> EvalResult<Value> {
+        let bytecode: &'a Bytecode = self.bytecode;
+        let mut stack: Vec<Value> = vec![];
+        // the arguments of the calls being prepared, innermost last, with the place
+        // of an untyped variable passed
+        let mut arguments: Vec<(Variable, Option<Place>)> = vec![];
+        // the keys left to visit of the for (key in array) loops being run
+        let mut iterators: Vec<(Rc<RefCell<Array>>, vec::IntoIter<String>)> = vec![];
+        let mut pc = 0;
+        while let Some(op) = code.get(pc) {
+            pc += 1;
+            match op {
+                Op::Push(value) => stack.push(value.clone()),
+                Op::Pop => {
+                    pop(&mut stack);
+                }
+                Op::Dup => {
+                    let top = stack.last().cloned().expect("the compiler keeps the stack balanced");
+                    stack.push(top);
+                }
+                Op::Load(place) => {
+                    let key = place.keyed().then(|| pop(&mut stack));
+                    let value = self.load(interpreter, frame, *place, key)?;
+                    stack.push(value);
+                }
+                Op::Store(place) => {
+                    let value = pop(&mut stack);
+                    let key = place.keyed().then(|| pop(&mut stack));
+                    self.store(interpreter, frame, *place, key, value.clone())?;
+                    stack.push(value);
+                }
+                Op::Increment(place, step, prefix) => {
+                    let key = place.keyed().then(|| pop(&mut stack));
+                    let old = self.load(interpreter, frame, *place, key.clone())?.to_number();
+                    self.store(interpreter, frame, *place, key, Value::Number(old + step))?;
+                    stack.push(Value::Number(if *prefix { old + step } else { old }));
+                }
+                Op::PushArgument => arguments.push((Variable::Value(pop(&mut stack)), None)),
+                Op::PassVariable(place) => {
+                    let (variable, _) = self.variable(interpreter, frame, *place);
+                    let untyped = matches!(variable, Variable::Value(Value::Uninitialized));
+                    arguments.push((variable.clone(), untyped.then_some(*place)));
+                }
+                Op::Subscript(count) => {
+                    let values = stack.split_off(stack.len() - count);
+                    stack.push(Value::String(interpreter.join_subscript(&values)));
+                }
+                Op::Unary(operator) => {
+                    let operand = pop(&mut stack);
+                    stack.push(unary(operator, &operand));
+                }
+                Op::Binary(operator) => {
+                    let right = pop(&mut stack);
+                    let left = pop(&mut stack);
+                    stack.push(interpreter.binary(&left, operator, &right)?);
+                }
+                Op::Match(negate) => {
+                    let ere = pop(&mut stack);
+                    let text = pop(&mut stack);
+                    stack.push(boolean(interpreter.is_match(&text, &ere)? != *negate));
+                }
+                Op::MatchRecord(ere) => stack.push(boolean(interpreter.matches_record(ere)?)),
+                Op::In(place) => {
+                    let key = pop(&mut stack);
+                    let key = interpreter.to_string(&key);
+                    let exists = self.array(interpreter, frame, *place)?.borrow().contains_key(&key);
+                    stack.push(boolean(exists));
+                }
+                Op::Clear(place) => self.array(interpreter, frame, *place)?.borrow_mut().clear(),
+                Op::Delete(place) => {
+                    let key = pop(&mut stack);
+                    let key = interpreter.to_string(&key);
+                    self.array(interpreter, frame, *place)?.borrow_mut().remove(&key);
+                }
+                Op::Jump(target) => pc = *target,
+                Op::JumpIfFalse(target) => {
+                    if !pop(&mut stack).to_bool() {
+                        pc = *target;
+                    }
+                }
+                Op::JumpIfTrue(target) => {
+                    if pop(&mut stack).to_bool() {
+                        pc = *target;
+                    }
+                }
+                Op::Call(index, count) => {
+                    let function = &bytecode.functions[*index];
+                    let (mut locals, places): (Vec<_>, Vec<_>) =
+                        arguments.split_off(arguments.len() - count).into_iter().unzip();
+                    locals.resize(function.params.len(), Variable::Value(Value::Uninitialized));
+                    let mut callee = Frame {
+                        names: &function.params,
+                        locals,
+                    };
+                    let value = self.execute(interpreter, &function.code, &mut callee)?;
+                    // an untyped variable becomes the array the function made of it
+                    for (local, place) in callee.locals.into_iter().zip(places) {
+                        if let (Variable::Array(array), Some(place)) = (local, place) {
+                            let (variable, _) = self.variable(interpreter, frame, place);
+                            if let Variable::Value(Value::Uninitialized) = variable {
+                                *variable = Variable::Array(array);
+                            }
+                        }
+                    }
+                    stack.push(value);
+                }
+                Op::Builtin(builtin, count) => {
+                    let args = stack.split_off(stack.len() - count);
+                    stack.push(interpreter.builtin(builtin, args)?);
+                }
+                Op::Length(place) => {
+                    let (variable, _) = self.variable(interpreter, frame, *place);
+                    let length = match variable.clone() {
+                        Variable::Array(array) => array.borrow().len(),
+                        Variable::Value(value) => interpreter.to_string(&value).chars().count(),
+                    };
+                    stack.push(Value::Number(length as f64));
+                }
+                Op::Split(place, separator) => {
+                    let separator = match separator {
+                        Separator::Fs => SplitSeparator::Fs,
+                        Separator::Ere(ere) => SplitSeparator::Ere(ere.clone()),
+                        Separator::Value => SplitSeparator::Value(pop(&mut stack)),
+                    };
+                    let text = pop(&mut stack);
+                    let array = self.array(interpreter, frame, *place)?;
+                    stack.push(interpreter.split(&text, &array, separator)?);
+                }
+                Op::Substitute(place, global) => {
+                    let key = place.keyed().then(|| pop(&mut stack));
+                    let replacement = pop(&mut stack);
+                    let ere = pop(&mut stack);
+                    let text = self.load(interpreter, frame, *place, key.clone())?;
+                    let (count, result) = interpreter.replace(&ere, &replacement, &text, *global)?;
+                    if count > 0 {
+                        self.store(interpreter, frame, *place, key, Value::String(result))?;
+                    }
+                    stack.push(Value::Number(count as f64));
+                }
+                Op::Getline(source, target) => {
+                    let key = target.filter(Place::keyed).map(|_| pop(&mut stack));
+                    let source = source.map(|kind| (kind, pop(&mut stack)));
+                    let status = match interpreter.read_line(source)? {
+                        Ok(Some(text)) => {
+                            match target {
+                                Some(place) => {
+                                    self.store(interpreter, frame, *place, key, Value::from_input(text))?
+                                }
+                                None => interpreter.set_record(text)?,
+                            }
+                            1.0
+                        }
+                        Ok(None) => 0.0,
+                        Err(_) => -1.0,
+                    };
+                    stack.push(Value::Number(status));
+                }
+                Op::Print(count, kind) | Op::Printf(count, kind) => {
+                    let target = kind.map(|kind| (kind, pop(&mut stack)));
+                    let values = stack.split_off(stack.len() - count);
+                    match op {
+                        Op::Print(..) => interpreter.print(values, target)?,
+                        _ => interpreter.printf(values, target)?,
+                    }
+                }
+                Op::PrintRecord => interpreter.print_record()?,
+                Op::ForIn(place) => {
+                    let array = self.array(interpreter, frame, *place)?;
+                    let keys = sorted_keys(&array.borrow());
+                    iterators.push((array, keys.into_iter()));
+                }
+                Op::ForInNext(place, end) => {
+                    let (array, keys) = iterators.last_mut().expect("ForIn comes first");
+                    // elements deleted by the body are skipped
+                    match keys.find(|key| array.borrow().contains_key(key)) {
+                        Some(key) => {
+                            self.store(interpreter, frame, *place, None, Value::from_input(key))?
+                        }
+                        None => {
+                            iterators.pop();
+                            pc = *end;
+                        }
+                    }
+                }
+                Op::ForInEnd => {
+                    iterators.pop();
+                }
+                Op::RangeActive(index) => stack.push(boolean(self.ranges[*index])),
+                Op::RangeEnd(index) => self.ranges[*index] = !pop(&mut stack).to_bool(),
+                Op::Next => return Err(Unwind::Next),
+                Op::Exit(has_code) => {
+                    if *has_code {
+                        let code = pop(&mut stack).to_number();
+                        interpreter.set_exit_code(code as i32);
+                    }
+                    return Err(Unwind::Exit);
+                }
+                Op::Return => return Ok(pop(&mut stack)),
+                Op::Error(message) => return Err(Unwind::Error(RuntimeError(message.clone()))),
+            }
+        }
+        Ok(Value::Uninitialized)
+    }
+}
+
+// runs `source` on the tree-walker and on the VM, which have to agree, output and errors alike
+#[cfg(test)]
+fn run_both(source: &str, input: &str) -> Result<String, RuntimeError> {
+    use crate::interpreter::run_source_with;
+    let walked = run_source_with(source, input, false);
+    let compiled = run_source_with(source, input, true);
+    assert_eq!(compiled, walked, "the backends differ on {}", source);
+    compiled
+}
+
+#[test]
+fn test_mock_programs() {
+    let inputs = [
+        ("sample_1", "a b\nc d\n"),
+        ("sample_2", "1\n2\n3\n"),
+        ("sample_3", "1\n2\n3\n"),
+        ("sample_4", "4\n7\n12\n"),
+        ("sample_5", "a\nb\n"),
+        ("sample_6", "a b\nc d e\n"),
+    ];
+    for (name, input) in inputs {
+        let source = std::fs::read_to_string(format!("./tests/mocks/{}.awk", name)).expect("Unable to read mock");
+        assert!(run_both(&source, input).is_ok(), "{} failed", name);
+    }
+}
+
+#[test]
+fn test_differential() {
+    let input = "alice 3 x\nbob 5 y\ncarol 4 x\ndave 1 z\n";
+    let programs = [
+        "{ print $1, $2 * 2; n += $2 } END { print n, NR, n / NR }",
+        "$3 == \"x\" { print NR \": \" $0 } !/o/",
+        "/bob/, /carol/ { print \"in\", $1 } NR == 4, 0 { print \"tail\", $1 }",
+        "{ count[$3]++; names[$3] = names[$3] \" \" $1 } END { for (k in count) print k, count[k], names[k] }",
+        "{ a[NR] = $1 } END { delete a[2]; for (k in a) { if (k == 3) continue; print k, a[k] } print (2 in a), (3 in a), length(a) }",
+        "{ $2 = $2 + 100; $5 = \"new\"; print; print NF } END { $0 = \"a b c\"; NF = 2; print $0, NF }",
+        "function fib(n) { return n < 2 ? n : fib(n - 1) + fib(n - 2) } BEGIN { print fib(15) }",
+        "function fill(arr, n,  i) { for (i = 1; i <= n; i++) arr[i] = i * i; return i } BEGIN { x[0]; print fill(x, 4); for (k in x) s = s k \"=\" x[k] \" \"; print s }",
+        "function swap(a, i, j,  t) { t = a[i]; a[i] = a[j]; a[j] = t } BEGIN { v[1] = \"p\"; v[2] = \"q\"; swap(v, 1, 2); print v[1] v[2] }",
+        "BEGIN { x = \"hello world\"; print gsub(/o/, \"[&]\", x), x; print sub(/l+/, \"L\"), $0 } { sub(/a/, \"A\", $1); gsub(/[aeiou]/, \"_\"); print }",
+        "BEGIN { n = split(\"a:b::c\", parts, \":\"); print n, parts[1], parts[4]; n = split(\" x  y \", w); print n, w[1], w[2]; print split(\"1a2b3\", d, /[a-z]/), d[3] }",
+        "BEGIN { print length(\"héllo\"), substr(\"hello\", 2, 3), index(\"hello\", \"ll\"), toupper(\"ab\"), tolower(\"CD\") }",
+        "BEGIN { print match(\"foobar\", /o+b/), RSTART, RLENGTH; print match(\"xyz\", \"q\"), RSTART, RLENGTH }",
+        "BEGIN { printf \"%5.2f|%-4s|%x|%c\\n\", 3.14159, \"ab\", 255, 65; s = sprintf(\"%03d\", 7); print s }",
+        "BEGIN { print int(7.9), int(-7.9), 2 ^ 10, 7 % 3, -7 % 3, sqrt(16), exp(0), log(1), atan2(0, 1), cos(0), sin(0) }",
+        "BEGIN { srand(1); a = rand(); srand(1); b = rand(); print a == b, srand(2) }",
+        "BEGIN { x[1, 2] = 3; for (k in x) { split(k, p, SUBSEP); print p[1], p[2], x[k] } print ((1, 2) in x), ((2, 1) in x) }",
+        "BEGIN { SUBSEP = \":\"; x[\"a\", 1]; x[2]; for (k in x) print k; delete x; print length(x); x[3]; print length(x) }",
+        "function f(a) { a[1] = 5 } function g(b,  l) { f(b); f(l); return l[1] } BEGIN { f(u); print u[1], g(w), w[1], length(w) }",
+        "BEGIN { i = 0; do { i++; if (i == 2) continue; if (i > 4) break; s = s i } while (1); print s }",
+        "BEGIN { for (i = 0; i < 10; i++) { if (i % 2) continue; if (i > 6) break; s = s i } print s, i }",
+        "BEGIN { while (i < 5) i++; print i; print i++ + ++i, i--, --i, i }",
+        "BEGIN { x = 5; x += 2; x -= 1; x *= 3; x /= 2; x %= 5; x ^= 3; print x; y[\"k\"] += 4; y[\"k\"] *= 2; print y[\"k\"] }",
+        "BEGIN { print 1 < 2, \"a\" < \"b\", \"10\" < \"9\", 10 < 9, \"abc\" ~ /b/, \"abc\" !~ \"^b\", !0, !\"\", -\"3x\" }",
+        "BEGIN { print (1 && 0), (0 || 2), (\"\" || \"a\"), (x && y), 1 ? 2 : 3, 0 ? 2 : 3 }",
+        "{ print > \"/dev/stdout\" } END { print \"done\" | \"cat > /dev/null\"; close(\"cat > /dev/null\"); print \"after\" }",
+        "BEGIN { while ((\"printf 'a\\nb\\n'\" | getline line) > 0) print \"got\", line; print NR }",
+        "NR == 1 { while ((getline) > 0) last = $0; print \"last\", last, NR }",
+        "NR == 2 { getline x; print \"x\", x, $0, NR } { print NR, $1 }",
+        "BEGIN { print getline line < \"/nonexistent/file\" }",
+        "{ if ($2 > 3) next; print $1 } END { print \"end\" }",
+        "NR == 2 { exit } { print } END { print \"end\", NR }",
+        "BEGIN { exit; print \"never\" } END { print \"end runs\" }",
+        "END { exit; print \"never\" }",
+        "BEGIN { CONVFMT = \"%.2g\"; x = 3.14159; y = x \"\"; print y; OFMT = \"%.1f\"; print x, 1e6, 0.1 + 0.2 }",
+        "BEGIN { OFS = \"-\"; ORS = \"|\\n\" } { $1 = $1; print; print $1, $2 }",
+        "BEGIN { FS = \",\" } { print $2 }",
+        "BEGIN { print substr(\"hello\", 0), substr(\"hello\", -1, 3), length() }",
+        "BEGIN { print x + 0, x \"\", length(x); if (!(\"k\" in a)) print \"absent\"; a[\"k\"]; print length(a) }",
+        "function f(a) { a[1] = 1 } BEGIN { f(x); print length(x) }",
+        "function g(n) { if (n) return; return 7 } BEGIN { print g(1) \"|\" g(0) }",
+        "BEGIN { print 1 / 0 }",
+        "BEGIN { print 1 % 0 }",
+        "BEGIN { a[1] = 1; a = 2 }",
+        "BEGIN { s = 1; s[1] = 2 }",
+        "function f(a) { return a } BEGIN { f(1, 2) }",
+        "BEGIN { next }",
+        "BEGIN { break }",
+        "BEGIN { substr(\"x\") }",
+        "BEGIN { NF = -1 }",
+        "{ $(-1) = 1 }",
+        "function f() { n = 5; return 1 } BEGIN { x[n] = f(); for (k in x) print k \"|\" }",
+        "BEGIN { i = 1; a[i++] = i; for (k in a) print k, a[k] }",
+        "BEGIN { $0 = \"a b c\"; $(++i) = $(++i); print }",
+        "BEGIN { i = 1; x[i] = i++; for (k in x) print k, x[k], i }",
+        "function f() { $0 = \"p q\"; return \"r\" } BEGIN { $0 = \"x\"; $NF = f(); print; j = 1; y[j] += j++; print y[1], y[2] }",
+    ];
+    for source in programs {
+        let _ = run_both(source, input);
+    }
+    assert_eq!(run_both(programs[6], ""), Ok("610\n".to_string()));
+    assert_eq!(
+        run_both(programs[41], ""),
+        Err(RuntimeError("division by zero".to_string()))
+    );
+    // the place of an assignment is evaluated before the value
+    let assignment = run_both("BEGIN { i = 1; a[i++] = i; for (k in a) print k, a[k] }", "");
+    assert_eq!(assignment, Ok("1 2\n".to_string()));
+    assert_eq!(run_both("BEGIN { $0 = \"a b c\"; $(++i) = $(++i); print }", ""), Ok("b b c\n".to_string()));
+}
@@ -0,0 +1,13 @@
+# counts the words of every line
+BEGIN {
+    words = 0 # none seen yet
+}
+
+{
+    words += NF # fields are words
+    print "# not a comment", NF
+}
+
+END {
+    print "Words:", words
+} # done